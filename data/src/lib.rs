@@ -1,14 +1,77 @@
 use http::Uri;
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
 /// Entry in or for the reading-list database.
 #[derive(Debug)]
 pub struct ReadingListEntry {
-    pub url: Uri,
+    pub url: Target,
     pub original_text: String,
     pub body_text: String,
     pub source_date: chrono::NaiveDate,
-    pub read: Option<bool>,
+    pub state: ReadState,
+    /// What the entry is, if set by a tag or by hand; otherwise see [ReadingListEntry::kind].
+    pub kind: Option<Kind>,
+}
+
+impl ReadingListEntry {
+    /// What the entry is: as set, or else as inferred from what it links to.
+    pub fn kind(&self) -> Kind {
+        self.kind.unwrap_or_else(|| Kind::of_target(&self.url))
+    }
+}
+
+/// What an entry links to, as stored in the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// A web page.
+    Page(Uri),
+    /// A paper or book, by its identifier in canonical form, e.g.
+    /// `doi:10.1145/3290605.3300233` or `isbn:9780262046305`.
+    /// It's linked to a page only when shown, as where books link to is configurable.
+    Identifier(String),
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Page(uri) => uri.fmt(f),
+            Target::Identifier(id) => f.write_str(id),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidTarget(pub String);
+
+impl Display for InvalidTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid link: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidTarget {}
+
+impl FromStr for Target {
+    type Err = InvalidTarget;
+
+    /// Parse a URL with a scheme, or else an identifier, such as `doi:10.1145/3290605.3300233`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(uri) = s.parse::<Uri>() {
+            if uri.scheme().is_some() {
+                return Ok(Target::Page(uri));
+            }
+        }
+        match s.split_once(':') {
+            Some((scheme, value))
+                if !scheme.is_empty()
+                    && scheme.bytes().all(|b| b.is_ascii_alphabetic())
+                    && !value.is_empty() =>
+            {
+                Ok(Target::Identifier(s.to_owned()))
+            }
+            _ => Err(InvalidTarget(s.to_owned())),
+        }
+    }
 }
 
 impl Display for ReadingListEntry {
@@ -20,3 +83,273 @@ impl Display for ReadingListEntry {
         )
     }
 }
+
+/// Where an entry is in the reading process.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReadState {
+    #[default]
+    Unknown,
+    ToRead,
+    InProgress,
+    Read,
+    Skimmed,
+    Abandoned,
+}
+
+impl ReadState {
+    pub const ALL: [ReadState; 6] = [
+        ReadState::Unknown,
+        ReadState::ToRead,
+        ReadState::InProgress,
+        ReadState::Read,
+        ReadState::Skimmed,
+        ReadState::Abandoned,
+    ];
+
+    /// Name of the state, as stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadState::Unknown => "unknown",
+            ReadState::ToRead => "to-read",
+            ReadState::InProgress => "in-progress",
+            ReadState::Read => "read",
+            ReadState::Skimmed => "skimmed",
+            ReadState::Abandoned => "abandoned",
+        }
+    }
+
+    /// Human-readable name of the state.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReadState::Unknown => "Unknown",
+            ReadState::ToRead => "TBR",
+            ReadState::InProgress => "Reading",
+            ReadState::Read => "Read",
+            ReadState::Skimmed => "Skimmed",
+            ReadState::Abandoned => "Abandoned",
+        }
+    }
+
+    /// State indicated by a journal tag (without the leading `#`).
+    pub fn from_tag(tag: &str) -> Option<ReadState> {
+        match tag {
+            "tbr" => Some(ReadState::ToRead),
+            "reading" => Some(ReadState::InProgress),
+            "read" => Some(ReadState::Read),
+            "skimmed" => Some(ReadState::Skimmed),
+            "abandoned" => Some(ReadState::Abandoned),
+            _ => None,
+        }
+    }
+
+    /// Journal tag for the state (without the leading `#`), if it has one.
+    pub fn tag(&self) -> Option<&'static str> {
+        match self {
+            ReadState::Unknown => None,
+            ReadState::ToRead => Some("tbr"),
+            ReadState::InProgress => Some("reading"),
+            ReadState::Read => Some("read"),
+            ReadState::Skimmed => Some("skimmed"),
+            ReadState::Abandoned => Some("abandoned"),
+        }
+    }
+
+    /// Whether an entry in this state may be moved to the `next` state.
+    ///
+    /// Any known state can follow any other; but once an entry has a known state,
+    /// it can't go back to being unknown.
+    pub fn can_transition_to(&self, next: ReadState) -> bool {
+        next != ReadState::Unknown && next != *self
+    }
+}
+
+impl Display for ReadState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidReadState(pub String);
+
+impl Display for InvalidReadState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid read state: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidReadState {}
+
+impl FromStr for ReadState {
+    type Err = InvalidReadState;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ReadState::ALL
+            .into_iter()
+            .find(|state| state.as_str() == s)
+            .ok_or_else(|| InvalidReadState(s.to_owned()))
+    }
+}
+
+/// What sort of thing an entry is, for grouping roundups.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    #[default]
+    Article,
+    Video,
+    Podcast,
+    Paper,
+    Book,
+}
+
+/// Hosts of podcast players and directories.
+const PODCAST_HOSTS: [&str; 7] = [
+    "podcasts.apple.com",
+    "podcasts.google.com",
+    "overcast.fm",
+    "pca.st",
+    "pocketcasts.com",
+    "castro.fm",
+    "castbox.fm",
+];
+
+impl Kind {
+    pub const ALL: [Kind; 5] = [
+        Kind::Article,
+        Kind::Video,
+        Kind::Podcast,
+        Kind::Paper,
+        Kind::Book,
+    ];
+
+    /// Name of the kind, as stored in the database and used as a journal tag.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Article => "article",
+            Kind::Video => "video",
+            Kind::Podcast => "podcast",
+            Kind::Paper => "paper",
+            Kind::Book => "book",
+        }
+    }
+
+    /// Human-readable name of the kind.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Kind::Article => "Article",
+            Kind::Video => "Video",
+            Kind::Podcast => "Podcast",
+            Kind::Paper => "Paper",
+            Kind::Book => "Book",
+        }
+    }
+
+    /// Human-readable name for a group of entries of the kind.
+    pub fn plural_label(&self) -> &'static str {
+        match self {
+            Kind::Article => "Articles",
+            Kind::Video => "Videos",
+            Kind::Podcast => "Podcasts",
+            Kind::Paper => "Papers",
+            Kind::Book => "Books",
+        }
+    }
+
+    /// Kind indicated by a journal tag (without the leading `#`), e.g. `video`.
+    pub fn from_tag(tag: &str) -> Option<Kind> {
+        Kind::ALL.into_iter().find(|kind| kind.as_str() == tag)
+    }
+
+    /// Kind of what an entry links to: books by ISBN, papers by other identifiers,
+    /// and pages going by their URLs.
+    pub fn of_target(target: &Target) -> Kind {
+        match target {
+            Target::Page(url) => Kind::of_url(url),
+            Target::Identifier(id) if id.to_ascii_lowercase().starts_with("isbn:") => Kind::Book,
+            Target::Identifier(_) => Kind::Paper,
+        }
+    }
+
+    /// Kind of the page at the URL, going by its site and file type:
+    /// e.g. YouTube is for videos, and PDFs and arXiv for papers.
+    pub fn of_url(url: &Uri) -> Kind {
+        let host = url.host().unwrap_or_default().to_ascii_lowercase();
+        let host = host
+            .strip_prefix("www.")
+            .or_else(|| host.strip_prefix("m."))
+            .unwrap_or(&host);
+        let path = url.path().to_ascii_lowercase();
+        let on = |site: &str| host == site || host.ends_with(&format!(".{site}"));
+        if [
+            "youtube.com",
+            "youtu.be",
+            "vimeo.com",
+            "twitch.tv",
+            "dailymotion.com",
+        ]
+        .into_iter()
+        .any(on)
+        {
+            Kind::Video
+        } else if PODCAST_HOSTS.into_iter().any(on)
+            || (on("open.spotify.com")
+                && (path.starts_with("/episode/") || path.starts_with("/show/")))
+            || path.ends_with(".mp3")
+        {
+            Kind::Podcast
+        } else if ["arxiv.org", "doi.org"].into_iter().any(on) || path.ends_with(".pdf") {
+            Kind::Paper
+        } else if on("openlibrary.org")
+            && (path.starts_with("/isbn/") || path.starts_with("/books/"))
+        {
+            Kind::Book
+        } else {
+            Kind::Article
+        }
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidKind(pub String);
+
+impl Display for InvalidKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid kind: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidKind {}
+
+impl FromStr for Kind {
+    type Err = InvalidKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Kind::from_tag(s).ok_or_else(|| InvalidKind(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_between_known_states_but_never_back_to_unknown() {
+        use ReadState::*;
+        for from in ReadState::ALL {
+            for to in ReadState::ALL {
+                let expected = to != Unknown && to != from;
+                assert_eq!(from.can_transition_to(to), expected, "{from} -> {to}");
+            }
+        }
+        assert!(Unknown.can_transition_to(ToRead));
+        assert!(Read.can_transition_to(InProgress));
+        assert!(!Read.can_transition_to(Unknown));
+        assert!(!Skimmed.can_transition_to(Skimmed));
+    }
+}
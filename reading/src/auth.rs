@@ -0,0 +1,534 @@
+//! Accounts, login sessions, and API tokens for the editor.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, rand_core::RngCore, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+use axum::{
+    extract::{Query, Request, State},
+    http::{
+        header::{AUTHORIZATION, LOCATION},
+        Method, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar, Form,
+};
+use maud::Markup;
+use rusqlite::{named_params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+
+use crate::{csrf::CsrfToken, metrics, Error, Server};
+
+/// Name of the cookie holding the session token.
+const SESSION_COOKIE: &str = "session";
+
+/// How long a login lasts.
+const SESSION_DAYS: i64 = 30;
+
+/// An account that can use the editor.
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: isize,
+    pub name: String,
+    /// Where to scan for the user's entries, if not the server's default journal.
+    pub journal: Option<PathBuf>,
+    /// Whether the user may download backups of the whole database.
+    pub admin: bool,
+}
+
+fn destruct_user(row: &rusqlite::Row) -> rusqlite::Result<User> {
+    Ok(User {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        journal: row.get::<_, Option<String>>("journal")?.map(PathBuf::from),
+        admin: row.get("admin")?,
+    })
+}
+
+/// A new random token, in hex.
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+/// Tokens are stored only as their SHA-256, so a copy of the database can't be used to log in.
+fn token_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Create an account with the given password.
+///
+/// The first account is an administrator, and takes ownership of any articles, roundups,
+/// and publications from before there were accounts.
+pub fn add_user(
+    conn: &Connection,
+    name: &str,
+    password: &str,
+    journal: Option<&Path>,
+    admin: bool,
+) -> Result<User, Error> {
+    // Names appear in URLs and directory names, so keep them simple.
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+    if !valid {
+        return Err(Error::InvalidUserName(name.to_owned()));
+    }
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| Error::PasswordHashError(e.to_string()))?
+        .to_string();
+    let tx = conn.unchecked_transaction()?;
+    let first = count_users(&tx)? == 0;
+    let admin = admin || first;
+    let id: isize = tx.query_row(
+        r#"
+        INSERT INTO users (name, password_hash, journal, admin)
+        VALUES (:name, :hash, :journal, :admin)
+        RETURNING id
+        "#,
+        named_params! {
+            ":name": name,
+            ":hash": hash,
+            ":journal": journal.map(|p| p.to_string_lossy()),
+            ":admin": admin,
+        },
+        |row| row.get(0),
+    )?;
+    if first {
+        for table in [
+            "reading_list",
+            "roundup_contents",
+            "publications",
+            "published_articles",
+        ] {
+            tx.execute(
+                &format!("UPDATE {table} SET owner = :id WHERE owner IS NULL"),
+                named_params! {":id": id},
+            )?;
+        }
+    }
+    tx.commit()?;
+    Ok(User {
+        id,
+        name: name.to_owned(),
+        journal: journal.map(Path::to_owned),
+        admin,
+    })
+}
+
+/// Set (or clear) the journal scanned for the user's entries.
+pub fn set_journal(conn: &Connection, name: &str, journal: Option<&Path>) -> Result<(), Error> {
+    let n = conn.execute(
+        "UPDATE users SET journal = :journal WHERE name = :name",
+        named_params! {":name": name, ":journal": journal.map(|p| p.to_string_lossy())},
+    )?;
+    if n == 0 {
+        return Err(Error::NoSuchUser(name.to_owned()));
+    }
+    Ok(())
+}
+
+/// Look up an account by name.
+pub fn find_user(conn: &Connection, name: &str) -> Result<Option<User>, Error> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM users WHERE name = :name",
+            named_params! {":name": name},
+            destruct_user,
+        )
+        .optional()?)
+}
+
+/// Pick the user to act as, for tools without a login.
+///
+/// With no name, picks the only account, if there is just one;
+/// or none, for a database without accounts.
+pub fn resolve_user(conn: &Connection, name: Option<&str>) -> Result<Option<User>, Error> {
+    if let Some(name) = name {
+        return find_user(conn, name)?
+            .map(Some)
+            .ok_or_else(|| Error::NoSuchUser(name.to_owned()));
+    }
+    let users: Result<Vec<User>, _> = conn
+        .prepare("SELECT * FROM users LIMIT 2")?
+        .query_map([], destruct_user)?
+        .collect();
+    let mut users = users?;
+    match users.len() {
+        0 | 1 => Ok(users.pop()),
+        _ => Err(Error::AmbiguousUser),
+    }
+}
+
+/// Number of accounts in the database.
+pub fn count_users(conn: &Connection) -> Result<isize, Error> {
+    Ok(conn.query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))?)
+}
+
+/// Create a bearer token for the user, for use by scripts.
+/// Returns the token; only its hash is kept.
+pub fn create_api_token(conn: &Connection, name: &str, label: &str) -> Result<String, Error> {
+    let user = find_user(conn, name)?.ok_or_else(|| Error::NoSuchUser(name.to_owned()))?;
+    let token = random_token();
+    conn.execute(
+        "INSERT INTO api_tokens (token_hash, user, label) VALUES (:hash, :user, :label)",
+        named_params! {":hash": token_hash(&token), ":user": user.id, ":label": label},
+    )?;
+    Ok(token)
+}
+
+/// A bearer token, as listed: the token itself isn't kept.
+#[derive(Debug, Clone)]
+pub struct ApiToken {
+    /// Number to revoke the token by.
+    pub id: isize,
+    /// What the token is for.
+    pub label: String,
+    /// When it was created: YYYY-MM-DD HH:MM:SS (UTC).
+    pub created_at: String,
+}
+
+/// The user's bearer tokens, oldest first.
+pub fn api_tokens(conn: &Connection, name: &str) -> Result<Vec<ApiToken>, Error> {
+    let user = find_user(conn, name)?.ok_or_else(|| Error::NoSuchUser(name.to_owned()))?;
+    let tokens: Result<Vec<_>, _> = conn
+        .prepare(
+            r#"
+            SELECT rowid, label, created_at FROM api_tokens
+            WHERE user = :user ORDER BY created_at ASC, rowid ASC
+            "#,
+        )?
+        .query_map(named_params! {":user": user.id}, |row| {
+            Ok(ApiToken {
+                id: row.get(0)?,
+                label: row.get(1)?,
+                created_at: row.get(2)?,
+            })
+        })?
+        .collect();
+    Ok(tokens?)
+}
+
+/// Revoke one of the user's bearer tokens, by its [ApiToken::id].
+pub fn revoke_api_token(conn: &Connection, name: &str, id: isize) -> Result<(), Error> {
+    let user = find_user(conn, name)?.ok_or_else(|| Error::NoSuchUser(name.to_owned()))?;
+    let deleted = conn.execute(
+        "DELETE FROM api_tokens WHERE rowid = :id AND user = :user",
+        named_params! {":id": id, ":user": user.id},
+    )?;
+    if deleted == 0 {
+        return Err(Error::NoSuchToken(id));
+    }
+    Ok(())
+}
+
+/// Hash to check passwords against for names with no account, so that logging in as
+/// one takes as long as a wrong password for an account would, and doesn't reveal which
+/// names have accounts. Made with the default parameters, of a password no one knows.
+const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$eaWXu9AFFhNww4gmcYbEeQ$8hETU7W+1Sf7n7zC4hES7QIj75Ibs10C8fuduE/QaDQ";
+
+/// Look up a user by name, with their password hash.
+fn user_with_password_hash(conn: &Connection, name: &str) -> Result<Option<(User, String)>, Error> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM users WHERE name = :name",
+            named_params! {":name": name},
+            |row| Ok((destruct_user(row)?, row.get("password_hash")?)),
+        )
+        .optional()?)
+}
+
+/// Check a password against its hash. This is slow by design, so call it off the
+/// async runtime, and without holding the database.
+fn verify_password(password: &str, hash: &str) -> Result<bool, Error> {
+    let hash = PasswordHash::new(hash).map_err(|e| Error::PasswordHashError(e.to_string()))?;
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok())
+}
+
+/// Check the user's password.
+async fn authenticate(
+    server: &Mutex<Server>,
+    name: &str,
+    password: String,
+) -> Result<Option<User>, Error> {
+    let row = user_with_password_hash(&metrics::lock(server).conn, name)?;
+    let hash = row
+        .as_ref()
+        .map_or(DUMMY_PASSWORD_HASH.to_owned(), |(_, hash)| hash.clone());
+    let verified = tokio::task::spawn_blocking(move || verify_password(&password, &hash))
+        .await
+        .map_err(|e| Error::PasswordHashError(e.to_string()))??;
+    Ok(row.filter(|_| verified).map(|(user, _)| user))
+}
+
+/// Start a session for the user, returning its token.
+fn create_session(conn: &Connection, user: &User) -> Result<String, Error> {
+    conn.execute(
+        "DELETE FROM sessions WHERE expires_at <= datetime('now')",
+        [],
+    )?;
+    let token = random_token();
+    conn.execute(
+        r#"
+        INSERT INTO sessions (token_hash, user, expires_at)
+        VALUES (:hash, :user, datetime('now', :lifetime))
+        "#,
+        named_params! {
+            ":hash": token_hash(&token),
+            ":user": user.id,
+            ":lifetime": format!("+{SESSION_DAYS} days"),
+        },
+    )?;
+    Ok(token)
+}
+
+fn end_session(conn: &Connection, token: &str) -> Result<(), Error> {
+    conn.execute(
+        "DELETE FROM sessions WHERE token_hash = :hash",
+        named_params! {":hash": token_hash(token)},
+    )?;
+    Ok(())
+}
+
+fn session_user(conn: &Connection, token: &str) -> Result<Option<User>, Error> {
+    Ok(conn
+        .query_row(
+            r#"
+            SELECT users.* FROM sessions JOIN users ON users.id = sessions.user
+            WHERE token_hash = :hash AND expires_at > datetime('now')
+            "#,
+            named_params! {":hash": token_hash(token)},
+            destruct_user,
+        )
+        .optional()?)
+}
+
+fn api_token_user(conn: &Connection, token: &str) -> Result<Option<User>, Error> {
+    Ok(conn
+        .query_row(
+            r#"
+            SELECT users.* FROM api_tokens JOIN users ON users.id = api_tokens.user
+            WHERE token_hash = :hash
+            "#,
+            named_params! {":hash": token_hash(token)},
+            destruct_user,
+        )
+        .optional()?)
+}
+
+/// Only redirect to paths on this site after logging in.
+fn safe_next(next: Option<&String>) -> &str {
+    match next {
+        Some(v) if v.starts_with('/') && !v.starts_with("//") => v,
+        _ => "/",
+    }
+}
+
+/// Middleware: refuse users who aren't administrators.
+/// Goes inside [require_user], which provides the [User].
+pub(crate) async fn require_admin(request: Request, next: Next) -> Response {
+    if !request.extensions().get::<User>().is_some_and(|u| u.admin) {
+        return (StatusCode::FORBIDDEN, "only administrators may see this").into_response();
+    }
+    next.run(request).await
+}
+
+/// Middleware: require a session cookie or bearer token.
+/// The [User] is made available to handlers as a request extension,
+/// along with the [CsrfToken] for a session.
+pub(crate) async fn require_user(
+    State(server): State<Arc<Mutex<Server>>>,
+    jar: CookieJar,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let bearer = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_owned);
+    let session = jar.get(SESSION_COOKIE).map(|c| c.value().to_owned());
+    let user = {
+        let server = metrics::lock(&server);
+        match (&bearer, &session) {
+            (Some(token), _) => api_token_user(&server.conn, token),
+            (None, Some(session)) => session_user(&server.conn, session),
+            (None, None) => Ok(None),
+        }
+    };
+    match user {
+        Ok(Some(user)) => {
+            request.extensions_mut().insert(user);
+            if let (None, Some(session)) = (&bearer, &session) {
+                request
+                    .extensions_mut()
+                    .insert(CsrfToken::for_session(session));
+            }
+            next.run(request).await
+        }
+        Ok(None) if request.method() == Method::GET && bearer.is_none() => {
+            let next: String =
+                form_urlencoded::byte_serialize(request.uri().to_string().as_bytes()).collect();
+            (
+                StatusCode::SEE_OTHER,
+                [(LOCATION, format!("/login?next={next}"))],
+            )
+                .into_response()
+        }
+        Ok(None) => (StatusCode::UNAUTHORIZED, "login required").into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("unexpected error: {e}"),
+        )
+            .into_response(),
+    }
+}
+
+fn render_login(next: &str, failed: bool) -> Markup {
+    maud::html! {
+        head { link rel="stylesheet" href="/style.css"; }
+        body { main {
+            h2 { "Log in" }
+            @if failed {
+                p class="error" { "Incorrect name or password." }
+            }
+            form method="POST" action="/login" class="summary" {
+                input type="hidden" name="next" value=(next);
+                p {
+                    label for="name" { "Name: " }
+                    input type="text" id="name" name="name" autocomplete="username";
+                }
+                p {
+                    label for="password" { "Password: " }
+                    input type="password" id="password" name="password" autocomplete="current-password";
+                }
+                button type="submit" { "Log in" }
+            }
+        } }
+    }
+}
+
+pub(crate) async fn login_form(Query(query): Query<HashMap<String, String>>) -> impl IntoResponse {
+    render_login(safe_next(query.get("next")), false)
+}
+
+pub(crate) async fn login(
+    State(server): State<Arc<Mutex<Server>>>,
+    jar: CookieJar,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    let next = safe_next(form.get("next")).to_owned();
+    let (Some(name), Some(password)) = (form.get("name"), form.get("password")) else {
+        return (StatusCode::BAD_REQUEST, "missing name or password").into_response();
+    };
+
+    let user = authenticate(&server, name, password.clone()).await;
+    let server = metrics::lock(&server);
+    let session = user.and_then(|user| {
+        user.map(|user| create_session(&server.conn, &user))
+            .transpose()
+    });
+    match session {
+        Ok(Some(token)) => {
+            let secure = server
+                .options
+                .base_url
+                .as_ref()
+                .is_some_and(|v| v.starts_with("https:"));
+            let cookie = Cookie::build((SESSION_COOKIE, token))
+                .path("/")
+                .http_only(true)
+                .secure(secure)
+                .same_site(SameSite::Lax)
+                .max_age(time::Duration::days(SESSION_DAYS));
+            (jar.add(cookie), (StatusCode::SEE_OTHER, [(LOCATION, next)])).into_response()
+        }
+        Ok(None) => {
+            tracing::warn!("failed login for {name:?}");
+            (StatusCode::UNAUTHORIZED, render_login(&next, true)).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("unexpected error: {e}"),
+        )
+            .into_response(),
+    }
+}
+
+pub(crate) async fn logout(State(server): State<Arc<Mutex<Server>>>, jar: CookieJar) -> Response {
+    if let Some(cookie) = jar.get(SESSION_COOKIE) {
+        let server = metrics::lock(&server);
+        if let Err(e) = end_session(&server.conn, cookie.value()) {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("unexpected error: {e}"),
+            )
+                .into_response();
+        }
+    }
+    (
+        jar.remove(Cookie::build(SESSION_COOKIE).path("/")),
+        (StatusCode::SEE_OTHER, [(LOCATION, "/login")]),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revoked_tokens_stop_working() {
+        let dir = std::env::temp_dir().join(format!("auth-test-{}-tokens", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = dir.join("reading.db");
+        let _ = std::fs::remove_file(&db);
+        let conn = crate::open(&db).unwrap();
+        add_user(&conn, "alice", "alice's password", None, false).unwrap();
+        add_user(&conn, "bob", "bob's password", None, false).unwrap();
+        let ci = create_api_token(&conn, "alice", "ci").unwrap();
+        let phone = create_api_token(&conn, "alice", "phone").unwrap();
+
+        let tokens = api_tokens(&conn, "alice").unwrap();
+        let labels: Vec<_> = tokens.iter().map(|t| t.label.as_str()).collect();
+        assert_eq!(labels, ["ci", "phone"]);
+        assert!(api_tokens(&conn, "bob").unwrap().is_empty());
+        // Only the owner's tokens can be revoked by their name.
+        assert!(matches!(
+            revoke_api_token(&conn, "bob", tokens[0].id),
+            Err(Error::NoSuchToken(_))
+        ));
+
+        revoke_api_token(&conn, "alice", tokens[0].id).unwrap();
+        assert!(api_token_user(&conn, &ci).unwrap().is_none());
+        assert_eq!(
+            api_token_user(&conn, &phone).unwrap().unwrap().name,
+            "alice"
+        );
+        assert_eq!(api_tokens(&conn, "alice").unwrap().len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
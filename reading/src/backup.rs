@@ -0,0 +1,166 @@
+//! Copies of the database, made with SQLite's online backup API so that they are
+//! consistent even while the server is writing.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use rusqlite::{
+    backup::{Backup, StepResult},
+    Connection, OpenFlags,
+};
+
+use crate::Error;
+
+/// File name prefix of backups made into a directory.
+const PREFIX: &str = "reading-";
+
+/// How long to wait before retrying when the database is busy.
+const BUSY_PAUSE: Duration = Duration::from_millis(50);
+
+/// Open the database for reading only, to back it up without holding up the server.
+pub fn open_source(db: &Path) -> Result<Connection, Error> {
+    Ok(Connection::open_with_flags(
+        db,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?)
+}
+
+/// Copy the database to a new file at `to`.
+fn copy(conn: &Connection, to: &Path) -> Result<(), Error> {
+    let mut dst = Connection::open(to)?;
+    let backup = Backup::new(conn, &mut dst)?;
+    // Copy all pages in one step, so a busy server can't keep restarting the backup.
+    loop {
+        match backup.step(-1)? {
+            StepResult::Done => return Ok(()),
+            StepResult::More => (),
+            _ => std::thread::sleep(BUSY_PAUSE),
+        }
+    }
+}
+
+/// Where to write a file before renaming it to `path`.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+/// Back up the database to `dest`.
+/// The copy is written alongside `dest` and renamed into place, so `dest` is never incomplete.
+pub fn backup(conn: &Connection, dest: &Path) -> Result<(), Error> {
+    let partial = partial_path(dest);
+    let _ = std::fs::remove_file(&partial);
+    let result = copy(conn, &partial).and_then(|()| Ok(std::fs::rename(&partial, dest)?));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Back up the database into the directory, as `reading-YYYYMMDDTHHMMSSZ.db`,
+/// then delete all but the `keep` most recent backups there.
+/// Returns the path of the new backup.
+pub fn backup_to_dir(conn: &Connection, dir: &Path, keep: usize) -> Result<PathBuf, Error> {
+    let path = dir.join(file_name());
+    backup(conn, &path)?;
+    let backups = list(dir)?;
+    for old in &backups[..backups.len().saturating_sub(keep.max(1))] {
+        tracing::info!("removing old backup {}", old.display());
+        std::fs::remove_file(old)?;
+    }
+    Ok(path)
+}
+
+/// Backups in the directory, oldest first.
+fn list(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(PREFIX) && name.ends_with(".db") {
+            backups.push(entry.path());
+        }
+    }
+    // The timestamps in the names sort in order.
+    backups.sort();
+    Ok(backups)
+}
+
+/// When the most recent backup in the directory was made, if there is one.
+pub fn last_backup(dir: &Path) -> Result<Option<SystemTime>, Error> {
+    Ok(match list(dir)?.last() {
+        Some(path) => Some(std::fs::metadata(path)?.modified()?),
+        None => None,
+    })
+}
+
+/// Check that the backup is an intact reading-list database.
+pub fn check(conn: &Connection) -> Result<(), Error> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version == 0 {
+        return Err(Error::BadBackup("not a reading-list database".to_owned()));
+    }
+    let problems: Vec<String> = conn
+        .prepare("PRAGMA integrity_check")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    if problems != ["ok"] {
+        return Err(Error::BadBackup(problems.join("; ")));
+    }
+    let dangling: Option<String> = conn
+        .prepare("PRAGMA foreign_key_check")?
+        .query_map([], |row| row.get(0))?
+        .next()
+        .transpose()?;
+    if let Some(table) = dangling {
+        return Err(Error::BadBackup(format!("dangling references in {table}")));
+    }
+    Ok(())
+}
+
+/// Replace the database at `db` with the backup, once it passes [check].
+/// The database being replaced is kept as `{db}.before-restore`.
+///
+/// The server must not be running.
+pub fn restore(backup: &Path, db: &Path) -> Result<(), Error> {
+    let src = Connection::open_with_flags(backup, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    check(&src)?;
+    let partial = partial_path(db);
+    let _ = std::fs::remove_file(&partial);
+    copy(&src, &partial)?;
+    if db.exists() {
+        let mut name = db.file_name().unwrap_or_default().to_owned();
+        name.push(".before-restore");
+        let previous = db.with_file_name(name);
+        let _ = std::fs::remove_file(&previous);
+        std::fs::hard_link(db, &previous)?;
+        tracing::info!("kept the previous database as {}", previous.display());
+    }
+    std::fs::rename(&partial, db)?;
+    Ok(())
+}
+
+/// A complete copy of the database at `db`, e.g. for download.
+pub fn snapshot(db: &Path) -> Result<Vec<u8>, Error> {
+    let src = open_source(db)?;
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let path = std::env::temp_dir().join(format!(
+        "{PREFIX}snapshot-{}-{nanos}.db",
+        std::process::id()
+    ));
+    let result = copy(&src, &path).and_then(|()| Ok(std::fs::read(&path)?));
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+/// Name to give a backup made now, e.g. for download.
+pub fn file_name() -> String {
+    format!("{PREFIX}{}.db", chrono::Utc::now().format("%Y%m%dT%H%M%SZ"))
+}
@@ -0,0 +1,197 @@
+//! Protection against cross-site request forgery.
+//!
+//! Requests that change anything must come from a page on this site:
+//! their Origin (or Referer) must match the Host or the configured base URL,
+//! and requests made with a session cookie
+//! must carry a token derived from the session, either as a `csrf` form field or an
+//! `X-CSRF-Token` header. The token is added to every form in the editor's pages.
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE, HOST, ORIGIN, REFERER},
+        HeaderMap, HeaderName, Method, StatusCode, Uri,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+
+/// Name of the form field holding the token.
+const FIELD: &str = "csrf";
+
+const HEADER: HeaderName = HeaderName::from_static("x-csrf-token");
+
+/// Largest request or page body we'll buffer to check or add the token.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Token that forms submitted in a session must carry.
+#[derive(Debug, Clone)]
+pub(crate) struct CsrfToken(String);
+
+impl CsrfToken {
+    /// The token for a session, given the session's cookie.
+    ///
+    /// The cookie is HttpOnly, so other sites' scripts can't read it or compute the token.
+    pub(crate) fn for_session(session: &str) -> CsrfToken {
+        CsrfToken(format!("{:x}", Sha256::digest(format!("csrf:{session}"))))
+    }
+
+    fn matches(&self, other: &str) -> bool {
+        // Compare in constant time, so the token can't be guessed a byte at a time.
+        self.0.len() == other.len()
+            && self
+                .0
+                .bytes()
+                .zip(other.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+fn is_bearer(headers: &HeaderMap) -> bool {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("Bearer "))
+}
+
+/// Middleware: reject unsafe requests that come from another site, according to their
+/// Origin or Referer. Requests with neither header (e.g. from scripts) are let through.
+/// Requests authenticated with a bearer token are exempt, as browsers don't send those
+/// on their own.
+///
+/// The site is the request's Host, or the public `base_url`, if any, as a proxy in front
+/// of the editor may pass on a Host of its own.
+pub(crate) async fn check_origin(
+    State(base_url): State<Option<Uri>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let headers = request.headers();
+    if is_safe(request.method()) || is_bearer(headers) {
+        return next.run(request).await;
+    }
+    let host = headers.get(HOST).and_then(|v| v.to_str().ok());
+    let source = headers
+        .get(ORIGIN)
+        .or_else(|| headers.get(REFERER))
+        .map(|v| v.to_str().ok().and_then(|v| v.parse::<Uri>().ok()));
+    let allowed = match source {
+        None => true,
+        Some(Some(uri)) => {
+            let authority = uri.authority().map(|a| a.as_str());
+            (host.is_some() && authority == host)
+                || base_url.as_ref().is_some_and(|base| {
+                    base.scheme() == uri.scheme() && base.authority() == uri.authority()
+                })
+        }
+        // Including "Origin: null", as sent from sandboxed or privacy-sensitive contexts.
+        Some(None) => false,
+    };
+    if allowed {
+        next.run(request).await
+    } else {
+        tracing::warn!(
+            "rejected cross-origin {} to {}",
+            request.method(),
+            request.uri()
+        );
+        (StatusCode::FORBIDDEN, "cross-origin request refused").into_response()
+    }
+}
+
+/// Middleware: require the session's [CsrfToken] on unsafe requests, and add it to the
+/// forms of HTML responses.
+///
+/// Runs within [crate::auth::require_user], which provides the token for session logins.
+pub(crate) async fn protect(request: Request, next: Next) -> Response {
+    let Some(token) = request.extensions().get::<CsrfToken>().cloned() else {
+        // Logged in with a bearer token.
+        return next.run(request).await;
+    };
+    let request = if is_safe(request.method()) {
+        request
+    } else {
+        match check_token(request, &token).await {
+            Ok(v) => v,
+            Err(e) => return e,
+        }
+    };
+    add_token(next.run(request).await, &token).await
+}
+
+/// Check the token in the header or form, returning the request for further handling.
+async fn check_token(request: Request, token: &CsrfToken) -> Result<Request, Response> {
+    let forbidden = || (StatusCode::FORBIDDEN, "missing or invalid CSRF token").into_response();
+    if let Some(v) = request.headers().get(HEADER) {
+        return match v.to_str() {
+            Ok(v) if token.matches(v) => Ok(request),
+            _ => Err(forbidden()),
+        };
+    }
+    let is_form = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"));
+    if !is_form {
+        return Err(forbidden());
+    }
+    // Read the form, then put it back for the handler.
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("{e}")).into_response())?;
+    let valid = form_urlencoded::parse(&bytes)
+        .find(|(k, _)| k == FIELD)
+        .is_some_and(|(_, v)| token.matches(&v));
+    if valid {
+        Ok(Request::from_parts(parts, Body::from(bytes)))
+    } else {
+        Err(forbidden())
+    }
+}
+
+/// Add a hidden field with the token to each form in an HTML response.
+async fn add_token(response: Response, token: &CsrfToken) -> Response {
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    if !is_html {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let html = match axum::body::to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(v) => String::from_utf8_lossy(&v).into_owned(),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("unexpected error: {e}"),
+            )
+                .into_response()
+        }
+    };
+    let field = maud::html! { input type="hidden" name=(FIELD) value=(token.0); }.into_string();
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html.as_str();
+    while let Some(start) = rest.find("<form") {
+        // maud escapes '>' in attribute values, so the first one closes the tag.
+        let Some(end) = rest[start..].find('>').map(|i| start + i + 1) else {
+            break;
+        };
+        out.push_str(&rest[..end]);
+        out.push_str(&field);
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(out))
+}
@@ -0,0 +1,676 @@
+//! Rendering roundups for publication outside the editor.
+
+use std::{borrow::Cow, collections::HashMap, fmt::Write, path::PathBuf, str::FromStr};
+
+use chrono::NaiveDate;
+use markdown::mdast::Node;
+use rusqlite::{named_params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use roundup::identifier::{page_url, resolve_identifiers, DEFAULT_BOOK_URL};
+
+use crate::{destruct_entry, feed::roundup_url, Article, Error, Kind};
+
+/// Width to wrap plain-text output at.
+const TEXT_WIDTH: usize = 72;
+
+/// Template for Markdown exports, when no other is configured.
+///
+/// Templates are rendered with [minijinja](https://docs.rs/minijinja),
+/// with the following context:
+///
+/// - `roundup.date`: date of the roundup, as `YYYY-MM-DD`
+/// - `roundup.title`: "Reading Roundup, YYYY-MM-DD"
+/// - `roundup.slug`: "reading-roundup-YYYY-MM-DD"
+/// - `roundup.count`: number of articles in the roundup
+/// - `roundup.author`: name of the user whose roundup it is, if any
+/// - `articles`: list of the articles in the roundup, oldest first. Each has:
+///   - `url`: the article's link
+///   - `body`: Markdown text of the entry
+///   - `source_date`: journal date of the entry, as `YYYY-MM-DD`
+///   - `state`: read state of the article (e.g. `read`, `skimmed`)
+///   - `title`: text of the entry's first link
+///   - `text`: the entry as plain text, without Markdown markup
+///   - `kind`: what the article is (`article`, `video`, `podcast`, `paper`, or `book`)
+///   - `kind_label`: the kind for display, e.g. "Video"
+/// - `groups`: the articles grouped by kind, in that order, leaving out empty groups.
+///   Each has `kind`, `label` (e.g. "Videos"), and `articles` as above.
+pub const DEFAULT_MARKDOWN_TEMPLATE: &str = r#"---
+title: "{{ roundup.title }}"
+date: {{ roundup.date }}
+---
+
+{% for article in articles -%}
+{{ article.body }}
+
+{% endfor %}
+"#;
+
+/// Settings for exporting roundups.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Path of the template for Markdown exports.
+    /// If unset, uses [DEFAULT_MARKDOWN_TEMPLATE].
+    pub markdown_template: Option<PathBuf>,
+    /// Page that books journaled by ISBN link to, with `{isbn}` in place of the ISBN-13.
+    pub book_url: String,
+    /// Public URL of the site serving roundups, whose pages identify JSON Feed items.
+    /// Without it, items are identified by their dates.
+    pub base_url: Option<String>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            markdown_template: None,
+            book_url: DEFAULT_BOOK_URL.to_owned(),
+            base_url: None,
+        }
+    }
+}
+
+impl ExportOptions {
+    /// Source of the Markdown template.
+    pub fn load_markdown_template(&self) -> Result<String, Error> {
+        Ok(match &self.markdown_template {
+            Some(path) => std::fs::read_to_string(path)?,
+            None => DEFAULT_MARKDOWN_TEMPLATE.to_owned(),
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    roundup: RoundupContext,
+    articles: Vec<ArticleContext<'a>>,
+    groups: Vec<GroupContext<'a>>,
+}
+
+#[derive(Serialize)]
+struct RoundupContext {
+    date: String,
+    title: String,
+    slug: String,
+    count: usize,
+    author: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+struct ArticleContext<'a> {
+    url: String,
+    body: Cow<'a, str>,
+    source_date: String,
+    state: &'static str,
+    title: String,
+    text: String,
+    kind: &'static str,
+    kind_label: &'static str,
+}
+
+#[derive(Serialize)]
+struct GroupContext<'a> {
+    kind: &'static str,
+    label: &'static str,
+    articles: Vec<ArticleContext<'a>>,
+}
+
+/// Output format for an exported roundup.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Markdown with Hugo-style front matter.
+    #[default]
+    Markdown,
+    /// Standalone HTML document.
+    Html,
+    /// A single JSON Feed (https://jsonfeed.org/) item.
+    JsonFeed,
+    /// Gemini gemtext.
+    Gemtext,
+    /// Plain text, wrapped for email.
+    Text,
+}
+
+impl Format {
+    pub const ALL: [Format; 5] = [
+        Format::Markdown,
+        Format::Html,
+        Format::JsonFeed,
+        Format::Gemtext,
+        Format::Text,
+    ];
+
+    /// Name of the format, as used in the `format` query parameter.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Markdown => "markdown",
+            Format::Html => "html",
+            Format::JsonFeed => "jsonfeed",
+            Format::Gemtext => "gemtext",
+            Format::Text => "text",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Markdown => "text/markdown; charset=UTF-8",
+            Format::Html => "text/html; charset=UTF-8",
+            Format::JsonFeed => "application/feed+json",
+            Format::Gemtext => "text/gemini; charset=UTF-8",
+            Format::Text => "text/plain; charset=UTF-8",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Html => "html",
+            Format::JsonFeed => "json",
+            Format::Gemtext => "gmi",
+            Format::Text => "txt",
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("unknown export format: {0:?}")]
+pub struct UnknownFormat(String);
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Format::ALL
+            .into_iter()
+            .find(|f| f.as_str() == s || f.extension() == s)
+            .ok_or_else(|| UnknownFormat(s.to_owned()))
+    }
+}
+
+/// A roundup, with the contents of its articles.
+#[derive(Debug)]
+pub struct Roundup {
+    /// ID of the user whose roundup this is.
+    pub owner: Option<isize>,
+    /// Name of the user whose roundup this is.
+    pub author: Option<String>,
+    pub date: NaiveDate,
+    pub articles: Vec<Article>,
+}
+
+impl Roundup {
+    /// Load the owner's roundup for the given date.
+    pub fn load(
+        conn: &Connection,
+        owner: Option<isize>,
+        date: NaiveDate,
+    ) -> Result<Roundup, Error> {
+        Roundup::query(
+            conn,
+            owner,
+            date,
+            r#"
+            SELECT reading_list.*
+            FROM roundup_contents LEFT JOIN reading_list ON reading_list.id = roundup_contents.entry
+            WHERE roundup_contents.owner IS :owner AND roundup_contents.date = :date
+            ORDER BY reading_list.source_date ASC, reading_list.id ASC
+            "#,
+        )
+    }
+
+    /// Load the owner's roundup for the given date as it was last published,
+    /// whatever has been edited since. It's empty if it was never published.
+    pub fn load_published(
+        conn: &Connection,
+        owner: Option<isize>,
+        date: NaiveDate,
+    ) -> Result<Roundup, Error> {
+        Roundup::query(
+            conn,
+            owner,
+            date,
+            r#"
+            SELECT *, entry AS id FROM published_articles
+            WHERE owner IS :owner AND date = :date
+            ORDER BY source_date ASC, entry ASC
+            "#,
+        )
+    }
+
+    /// Load a roundup's articles with a query by `:owner` and `:date`.
+    fn query(
+        conn: &Connection,
+        owner: Option<isize>,
+        date: NaiveDate,
+        sql: &str,
+    ) -> Result<Roundup, Error> {
+        let author = conn
+            .query_row(
+                "SELECT name FROM users WHERE id IS :owner",
+                named_params! {":owner": owner},
+                |row| row.get(0),
+            )
+            .optional()?;
+        let rows: Result<Vec<_>, _> = conn
+            .prepare(sql)?
+            .query_map(
+                named_params! {":owner": owner, ":date": format!("{date}")},
+                |row| {
+                    Ok(Article {
+                        id: row.get("id")?,
+                        roundups: 0,
+                        entry: destruct_entry(row)?,
+                    })
+                },
+            )?
+            .collect();
+        Ok(Roundup {
+            owner,
+            author,
+            date,
+            articles: rows?,
+        })
+    }
+
+    pub fn title(&self) -> String {
+        format!("Reading Roundup, {}", self.date)
+    }
+
+    /// Render the roundup in the given format.
+    pub fn render(&self, format: Format, options: &ExportOptions) -> Result<String, Error> {
+        match format {
+            Format::Markdown => {
+                self.markdown(&options.load_markdown_template()?, &options.book_url)
+            }
+            Format::Html => Ok(self.html(&options.book_url)),
+            Format::JsonFeed => Ok(self.json_feed(options)),
+            Format::Gemtext => self.gemtext(&options.book_url),
+            Format::Text => self.text(&options.book_url),
+        }
+    }
+
+    /// Render the roundup through a Markdown template.
+    /// Papers and books link to their pages, with books at `book_url`.
+    pub fn markdown(&self, template: &str, book_url: &str) -> Result<String, Error> {
+        let articles: Vec<ArticleContext> = self
+            .articles
+            .iter()
+            .map(|article| {
+                let body = resolve_identifiers(&article.entry.body_text, book_url);
+                let (text, links) = flatten_body(&body);
+                let kind = article.entry.kind();
+                ArticleContext {
+                    url: page_url(&article.entry.url, book_url),
+                    body,
+                    source_date: format!("{}", article.entry.source_date),
+                    state: article.entry.state.as_str(),
+                    title: links.into_iter().next().map(|(_, t)| t).unwrap_or_default(),
+                    text: text.trim().to_owned(),
+                    kind: kind.as_str(),
+                    kind_label: kind.label(),
+                }
+            })
+            .collect();
+        let groups = Kind::ALL
+            .into_iter()
+            .map(|kind| GroupContext {
+                kind: kind.as_str(),
+                label: kind.plural_label(),
+                articles: articles
+                    .iter()
+                    .filter(|a| a.kind == kind.as_str())
+                    .cloned()
+                    .collect(),
+            })
+            .filter(|group| !group.articles.is_empty())
+            .collect();
+        let context = TemplateContext {
+            roundup: RoundupContext {
+                date: format!("{}", self.date),
+                title: self.title(),
+                slug: format!("reading-roundup-{}", self.date),
+                count: self.articles.len(),
+                author: self.author.clone(),
+            },
+            articles,
+            groups,
+        };
+        let env = minijinja::Environment::new();
+        let template = env.template_from_named_str("markdown template", template)?;
+        Ok(template.render(context)?)
+    }
+
+    /// The bodies of the articles, rendered as an HTML fragment.
+    /// Papers and books link to their pages, with books at `book_url`.
+    pub fn body_html(&self, book_url: &str) -> String {
+        self.articles
+            .iter()
+            .map(|article| crate::entry_html(&article.entry, book_url))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn html(&self, book_url: &str) -> String {
+        let title = self.title();
+        maud::html! {
+            (maud::DOCTYPE)
+            html {
+                head {
+                    meta charset="utf-8";
+                    title { (title) }
+                }
+                body {
+                    h1 { (title) }
+                    (maud::PreEscaped(self.body_html(book_url)))
+                }
+            }
+        }
+        .into_string()
+    }
+
+    fn json_feed(&self, options: &ExportOptions) -> String {
+        let book_url = &options.book_url;
+        // The same ID as the roundup's entry in the Atom feed.
+        let url = match (&options.base_url, &self.author) {
+            (Some(base_url), Some(author)) => Some(roundup_url(base_url, author, self.date)),
+            _ => None,
+        };
+        let mut item = serde_json::json!({
+            "id": url.clone().unwrap_or_else(|| format!("{}", self.date)),
+            "title": self.title(),
+            "content_html": self.body_html(book_url),
+            "date_published": format!("{}T00:00:00Z", self.date),
+            "external_urls": self.articles.iter().map(|a| page_url(&a.entry.url, book_url)).collect::<Vec<_>>(),
+        });
+        if let Some(url) = url {
+            item["url"] = url.into();
+        }
+        serde_json::to_string_pretty(&item).expect("JSON values are always serializable")
+    }
+
+    fn gemtext(&self, book_url: &str) -> Result<String, Error> {
+        let mut s = String::new();
+        writeln!(s, "# {}", self.title())?;
+        for article in &self.articles {
+            let body = resolve_identifiers(&article.entry.body_text, book_url);
+            let (text, links) = flatten_body(&body);
+            writeln!(s)?;
+            writeln!(s, "{}", text.trim())?;
+            for (url, label) in links {
+                writeln!(s, "=> {url} {label}")?;
+            }
+        }
+        Ok(s)
+    }
+
+    fn text(&self, book_url: &str) -> Result<String, Error> {
+        let mut s = String::new();
+        writeln!(s, "{}", self.title())?;
+        for article in &self.articles {
+            let body = resolve_identifiers(&article.entry.body_text, book_url);
+            let (text, links) = flatten_body(&body);
+            writeln!(s)?;
+            for (i, paragraph) in text.trim().split("\n\n").enumerate() {
+                if i > 0 {
+                    writeln!(s)?;
+                }
+                wrap(&mut s, paragraph)?;
+            }
+            for (url, _) in links {
+                wrap(&mut s, &format!("<{url}>"))?;
+            }
+        }
+        Ok(s)
+    }
+}
+
+fn parse(body: &str) -> Node {
+    markdown::to_mdast(body, &markdown::ParseOptions::default()).unwrap_or_else(|_| {
+        Node::Text(markdown::mdast::Text {
+            value: body.to_owned(),
+            position: None,
+        })
+    })
+}
+
+/// Flatten a Markdown body into plain text and the links (URL and text) it contains,
+/// with reference links resolved by the body's definitions.
+fn flatten_body(body: &str) -> (String, Vec<(String, String)>) {
+    let node = parse(body);
+    let mut definitions = HashMap::new();
+    collect_definitions(&node, &mut definitions);
+    let mut text = String::new();
+    let mut links = Vec::new();
+    flatten(&node, &definitions, &mut text, &mut links);
+    (text, links)
+}
+
+/// Link reference definitions in the Markdown, by their normalized identifiers.
+fn collect_definitions(node: &Node, definitions: &mut HashMap<String, String>) {
+    if let Node::Definition(d) = node {
+        // As in CommonMark, the first definition of an identifier wins.
+        definitions
+            .entry(d.identifier.clone())
+            .or_insert_with(|| d.url.clone());
+    }
+    for child in node.children().into_iter().flatten() {
+        collect_definitions(child, definitions);
+    }
+}
+
+/// Flatten Markdown into plain text, collecting the links (URL and text) it contains.
+fn flatten(
+    node: &Node,
+    definitions: &HashMap<String, String>,
+    text: &mut String,
+    links: &mut Vec<(String, String)>,
+) {
+    match node {
+        Node::Text(t) => text.push_str(&t.value),
+        Node::InlineCode(c) => text.push_str(&c.value),
+        Node::Code(c) => {
+            text.push_str(&c.value);
+            text.push_str("\n\n");
+        }
+        Node::Break(_) => text.push(' '),
+        Node::Link(link) => {
+            let start = text.len();
+            for child in &link.children {
+                flatten(child, definitions, text, links);
+            }
+            let label = text[start..].trim().to_owned();
+            links.push((link.url.clone(), label));
+        }
+        Node::LinkReference(reference) => {
+            let start = text.len();
+            for child in &reference.children {
+                flatten(child, definitions, text, links);
+            }
+            if let Some(url) = definitions.get(&reference.identifier) {
+                let label = text[start..].trim().to_owned();
+                links.push((url.clone(), label));
+            }
+        }
+        Node::Definition(_) => {}
+        Node::Paragraph(_) | Node::Heading(_) | Node::ListItem(_) => {
+            for child in node.children().into_iter().flatten() {
+                flatten(child, definitions, text, links);
+            }
+            text.push_str("\n\n");
+        }
+        _ => {
+            for child in node.children().into_iter().flatten() {
+                flatten(child, definitions, text, links);
+            }
+        }
+    }
+}
+
+/// Write the paragraph to the output, word-wrapped.
+fn wrap(out: &mut String, paragraph: &str) -> std::fmt::Result {
+    let mut width = 0;
+    for word in paragraph.split_whitespace() {
+        let len = word.chars().count();
+        if width > 0 && width + 1 + len > TEXT_WIDTH {
+            writeln!(out)?;
+            width = 0;
+        }
+        if width > 0 {
+            out.push(' ');
+            width += 1;
+        }
+        out.push_str(word);
+        width += len;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    /// A roundup of the entries, scanned from their Markdown.
+    fn roundup(bodies: &[&str]) -> Roundup {
+        let articles = bodies
+            .iter()
+            .enumerate()
+            .map(|(i, body)| Article {
+                id: i as isize + 1,
+                roundups: 1,
+                entry: roundup::scan_body(date(2024, 9, 1), body).unwrap(),
+            })
+            .collect();
+        Roundup {
+            owner: None,
+            author: None,
+            date: date(2024, 9, 2),
+            articles,
+        }
+    }
+
+    #[test]
+    fn gemtext_links_reference_links() {
+        let r = roundup(&[
+            "[A post][post], via [the blog]\n\n[post]: https://example.com/a\n[The Blog]: https://example.com/",
+        ]);
+        assert_eq!(
+            r.render(Format::Gemtext, &ExportOptions::default())
+                .unwrap(),
+            "# Reading Roundup, 2024-09-02\n\
+             \n\
+             A post, via the blog\n\
+             => https://example.com/a A post\n\
+             => https://example.com/ the blog\n"
+        );
+    }
+
+    #[test]
+    fn json_feed_items_are_identified_by_their_pages() {
+        let mut r = roundup(&["[A post](https://example.com/a)"]);
+        r.author = Some("alice".to_owned());
+        let options = ExportOptions {
+            base_url: Some("https://example.com/reading/".to_owned()),
+            ..ExportOptions::default()
+        };
+        let item: serde_json::Value =
+            serde_json::from_str(&r.render(Format::JsonFeed, &options).unwrap()).unwrap();
+        let url = "https://example.com/reading/users/alice/roundups/2024-09-02/";
+        assert_eq!(item["id"], url);
+        assert_eq!(item["url"], url);
+
+        let item: serde_json::Value = serde_json::from_str(
+            &r.render(Format::JsonFeed, &ExportOptions::default())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(item["id"], "2024-09-02");
+        assert!(item.get("url").is_none());
+    }
+
+    #[test]
+    fn renders_the_default_template() {
+        let r = roundup(&[
+            "[A post](https://example.com/a) is good",
+            "<https://example.com/b>",
+        ]);
+        assert_eq!(
+            r.render(Format::Markdown, &ExportOptions::default())
+                .unwrap(),
+            "---\n\
+             title: \"Reading Roundup, 2024-09-02\"\n\
+             date: 2024-09-02\n\
+             ---\n\
+             \n\
+             [A post](https://example.com/a) is good\n\
+             \n\
+             <https://example.com/b>\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn renders_a_custom_template() {
+        let dir = std::env::temp_dir().join(format!("export-test-{}-custom", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let template = dir.join("roundup.md.j2");
+        std::fs::write(
+            &template,
+            "{{ roundup.slug }} by {{ roundup.author }}: {{ roundup.count }}\n\
+             {% for a in articles %}{{ a.title }} | {{ a.text }} | {{ a.url }} | \
+             {{ a.source_date }} | {{ a.state }} | {{ a.kind_label }}\n{% endfor %}",
+        )
+        .unwrap();
+        let options = ExportOptions {
+            markdown_template: Some(template),
+            ..ExportOptions::default()
+        };
+        let mut r = roundup(&["[A *post*](https://example.com/a) is good"]);
+        r.author = Some("alice".to_owned());
+        assert_eq!(
+            r.render(Format::Markdown, &options).unwrap(),
+            "reading-roundup-2024-09-02 by alice: 1\n\
+             A post | A post is good | https://example.com/a | 2024-09-01 | unknown | Article\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_template_errors() {
+        let r = roundup(&["[A post](https://example.com/a)"]);
+        assert!(matches!(
+            r.markdown("{% for a in articles %}{{ a.title }}", ""),
+            Err(Error::TemplateError(_))
+        ));
+        let options = ExportOptions {
+            markdown_template: Some(PathBuf::from("/nonexistent/roundup.md.j2")),
+            ..ExportOptions::default()
+        };
+        assert!(matches!(
+            r.render(Format::Markdown, &options),
+            Err(Error::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn groups_articles_by_kind() {
+        let r = roundup(&[
+            "[Clip](https://www.youtube.com/watch?v=x)",
+            "[A post](https://example.com/a)",
+            "isbn:978-0-262-04630-5",
+            "[Paper](https://arxiv.org/abs/2401.01234)",
+            "[Another post](https://example.com/b)",
+        ]);
+        let template = "{% for g in groups %}{{ g.label }}:\
+            {% for a in g.articles %} {{ a.url }}{% endfor %}\n{% endfor %}";
+        assert_eq!(
+            r.markdown(template, DEFAULT_BOOK_URL).unwrap(),
+            "Articles: https://example.com/a https://example.com/b\n\
+             Videos: https://www.youtube.com/watch?v=x\n\
+             Papers: https://arxiv.org/abs/2401.01234\n\
+             Books: https://openlibrary.org/isbn/9780262046305\n"
+        );
+    }
+}
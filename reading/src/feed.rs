@@ -0,0 +1,305 @@
+//! Syndication feeds of published roundups.
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
+use rusqlite::{named_params, Connection};
+
+use crate::{export::Roundup, Error};
+
+/// Number of roundups to include in a feed.
+const FEED_LENGTH: usize = 20;
+
+const FEED_TITLE: &str = "Reading Roundup";
+
+/// A roundup that has been published.
+#[derive(Debug)]
+pub struct Published {
+    pub roundup: Roundup,
+    /// Name of the user who published it.
+    pub author: String,
+    pub published_at: DateTime<FixedOffset>,
+}
+
+/// Load published roundups, most recent first.
+/// Only includes roundups with an owner, or they'd have nowhere to be served from.
+pub fn published(conn: &Connection, limit: Option<usize>) -> Result<Vec<Published>, Error> {
+    let rows: Result<Vec<(isize, String, String, String)>, _> = conn
+        .prepare(
+            r#"
+            SELECT publications.owner, users.name, date, published_at
+            FROM publications JOIN users ON users.id = publications.owner
+            ORDER BY date DESC, published_at DESC LIMIT :limit
+            "#,
+        )?
+        .query_map(
+            named_params! {":limit": limit.map(|v| v as i64).unwrap_or(-1)},
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?
+        .collect();
+    rows?
+        .into_iter()
+        .map(|(owner, author, date, published_at)| {
+            let published_at = NaiveDateTime::parse_from_str(&published_at, "%Y-%m-%d %H:%M:%S")
+                .map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        3,
+                        rusqlite::types::Type::Text,
+                        Box::new(e),
+                    )
+                })?
+                .and_utc()
+                .fixed_offset();
+            let date = date.parse().map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    2,
+                    rusqlite::types::Type::Text,
+                    Box::new(e),
+                )
+            })?;
+            Ok(Published {
+                roundup: Roundup::load_published(conn, Some(owner), date)?,
+                author,
+                published_at,
+            })
+        })
+        .collect()
+}
+
+/// Public URL of a user's roundup, under the given base URL.
+pub fn roundup_url(base_url: &str, author: &str, date: NaiveDate) -> String {
+    format!(
+        "{}/users/{author}/roundups/{date}/",
+        base_url.trim_end_matches('/'),
+    )
+}
+
+fn updated(published: &[Published]) -> DateTime<FixedOffset> {
+    published
+        .iter()
+        .map(|p| p.published_at)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Render an Atom feed of the published roundups.
+pub fn atom(conn: &Connection, base_url: &str, book_url: &str) -> Result<String, Error> {
+    use atom_syndication::{Content, Entry, Feed, Link, Text};
+
+    let published = published(conn, Some(FEED_LENGTH))?;
+    let base_url = base_url.trim_end_matches('/');
+    let entries = published
+        .iter()
+        .map(|p| {
+            let url = roundup_url(base_url, &p.author, p.roundup.date);
+            Entry {
+                title: Text::plain(p.roundup.title()),
+                authors: vec![atom_syndication::Person {
+                    name: p.author.clone(),
+                    ..Default::default()
+                }],
+                id: url.clone(),
+                updated: p.published_at,
+                published: Some(p.published_at),
+                links: vec![Link {
+                    href: url,
+                    rel: "alternate".to_owned(),
+                    ..Default::default()
+                }],
+                content: Some(Content {
+                    value: Some(p.roundup.body_html(book_url)),
+                    content_type: Some("html".to_owned()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+        })
+        .collect();
+    let feed = Feed {
+        title: Text::plain(FEED_TITLE),
+        id: format!("{base_url}/"),
+        updated: updated(&published),
+        links: vec![
+            Link {
+                href: format!("{base_url}/"),
+                rel: "alternate".to_owned(),
+                ..Default::default()
+            },
+            Link {
+                href: format!("{base_url}/feed.atom"),
+                rel: "self".to_owned(),
+                ..Default::default()
+            },
+        ],
+        entries,
+        ..Default::default()
+    };
+    Ok(feed.to_string())
+}
+
+/// Render an RSS feed of the published roundups.
+pub fn rss(conn: &Connection, base_url: &str, book_url: &str) -> Result<String, Error> {
+    use rss::{Channel, Guid, Item};
+
+    let published = published(conn, Some(FEED_LENGTH))?;
+    let base_url = base_url.trim_end_matches('/');
+    let items = published
+        .iter()
+        .map(|p| {
+            let url = roundup_url(base_url, &p.author, p.roundup.date);
+            Item {
+                title: Some(p.roundup.title()),
+                link: Some(url.clone()),
+                guid: Some(Guid {
+                    value: url,
+                    permalink: true,
+                }),
+                pub_date: Some(p.published_at.to_rfc2822()),
+                description: Some(p.roundup.body_html(book_url)),
+                ..Default::default()
+            }
+        })
+        .collect();
+    let channel = Channel {
+        title: FEED_TITLE.to_owned(),
+        link: format!("{base_url}/"),
+        description: FEED_TITLE.to_owned(),
+        last_build_date: Some(updated(&published).to_rfc2822()),
+        items,
+        ..Default::default()
+    };
+    Ok(channel.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{add_article, auth, auth::User, export::ExportOptions, open, publish::publish};
+
+    const BASE_URL: &str = "https://example.com/reading/";
+
+    /// A database with a roundup by "alice" of one article, removed when dropped.
+    struct Fixture {
+        dir: PathBuf,
+        conn: Connection,
+        alice: User,
+        article: isize,
+        date: NaiveDate,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Fixture {
+            let dir = std::env::temp_dir().join(format!("feed-test-{}-{name}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let db = dir.join("reading.db");
+            let _ = std::fs::remove_file(&db);
+            let mut conn = open(&db).unwrap();
+            let alice = auth::add_user(&conn, "alice", "alice's password", None, false).unwrap();
+            let date = NaiveDate::from_ymd_opt(2024, 9, 2).unwrap();
+            let article = add_article(
+                &mut conn,
+                Some(alice.id),
+                "[A post](https://example.com/a), first take",
+                NaiveDate::from_ymd_opt(2024, 9, 1).unwrap(),
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO roundup_contents (owner, date, entry) VALUES (:owner, :date, :entry)",
+                named_params! {":owner": alice.id, ":date": format!("{date}"), ":entry": article},
+            )
+            .unwrap();
+            Fixture {
+                dir,
+                conn,
+                alice,
+                article,
+                date,
+            }
+        }
+
+        fn publish(&self) {
+            publish(
+                &self.conn,
+                &self.alice,
+                self.date,
+                &ExportOptions::default(),
+                None,
+                false,
+            )
+            .unwrap();
+        }
+
+        fn edit(&self, body: &str) {
+            self.conn
+                .execute(
+                    "UPDATE reading_list SET body_text = :body WHERE id = :id",
+                    named_params! {":body": body, ":id": self.article},
+                )
+                .unwrap();
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn shows_roundups_as_published_until_republished() {
+        let f = Fixture::new("snapshot");
+        f.publish();
+        f.edit("[A post](https://example.com/a), second thoughts");
+        let feed = atom(&f.conn, BASE_URL, "").unwrap();
+        assert!(feed.contains("first take"), "{feed}");
+        assert!(!feed.contains("second thoughts"), "{feed}");
+
+        f.publish();
+        let feed = rss(&f.conn, BASE_URL, "").unwrap();
+        assert!(feed.contains("second thoughts"), "{feed}");
+    }
+
+    #[test]
+    fn keeps_ids_across_renders_and_republishing() {
+        let f = Fixture::new("ids");
+        f.publish();
+        let url = "https://example.com/reading/users/alice/roundups/2024-09-02/";
+        for _ in 0..2 {
+            let feed: atom_syndication::Feed =
+                atom(&f.conn, BASE_URL, "").unwrap().parse().unwrap();
+            assert_eq!(feed.entries()[0].id(), url);
+            let channel: rss::Channel = rss(&f.conn, BASE_URL, "").unwrap().parse().unwrap();
+            assert_eq!(channel.items()[0].guid().unwrap().value(), url);
+            f.edit("[A post](https://example.com/a), second thoughts");
+            f.publish();
+        }
+    }
+
+    #[test]
+    fn updates_entries_when_republished() {
+        let f = Fixture::new("updated");
+        f.publish();
+        f.conn
+            .execute(
+                "UPDATE publications SET published_at = '2024-09-02 12:00:00'",
+                [],
+            )
+            .unwrap();
+        let feed: atom_syndication::Feed = atom(&f.conn, BASE_URL, "").unwrap().parse().unwrap();
+        let before = *feed.entries()[0].updated();
+        assert_eq!(before.to_rfc3339(), "2024-09-02T12:00:00+00:00");
+        assert_eq!(*feed.updated(), before);
+
+        f.publish();
+        let feed: atom_syndication::Feed = atom(&f.conn, BASE_URL, "").unwrap().parse().unwrap();
+        let after = *feed.entries()[0].updated();
+        assert!(after > before);
+        assert_eq!(*feed.updated(), after);
+        // RSS dates items by when they were last published too.
+        let channel: rss::Channel = rss(&f.conn, BASE_URL, "").unwrap().parse().unwrap();
+        assert_eq!(
+            channel.items()[0].pub_date(),
+            Some(after.to_rfc2822().as_str())
+        );
+    }
+}
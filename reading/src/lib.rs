@@ -1,30 +1,48 @@
 // TODO:
 // - Javascript to auto-save?
 
+pub mod auth;
+pub mod backup;
+mod csrf;
+pub mod export;
+pub mod feed;
+mod metrics;
+pub mod public;
+pub mod publish;
+mod quick_add;
+
 use std::{
     collections::HashMap,
-    io::{Cursor, Write},
     path::PathBuf,
     str::FromStr,
     sync::{Arc, Mutex},
+    time::Instant,
 };
 
+use auth::User;
 use axum::{
-    extract::{OriginalUri, Path, State},
+    extract::{Extension, OriginalUri, Path, Query, State},
     http::{
         header::{CONTENT_DISPOSITION, CONTENT_TYPE, LOCATION},
         uri::PathAndQuery,
         StatusCode, Uri,
     },
+    middleware,
     response::IntoResponse,
-    routing::get,
+    routing::{get, post},
 };
 use axum_extra::extract::Form;
 use chrono::NaiveDate;
+use export::{ExportOptions, Format, Roundup};
 use maud::PreEscaped;
-use reading_roundup_data::ReadingListEntry;
-use roundup::scan_files;
-use rusqlite::{named_params, Connection};
+use publish::{FileStatus, PublishOptions};
+pub use reading_roundup_data::{Kind, ReadState, ReadingListEntry};
+use roundup::{
+    identifier::{page_url, resolve_identifiers},
+    mail::Mailbox,
+    scan_journal, ScanOptions, ScanReport,
+};
+use rusqlite::{named_params, Connection, OptionalExtension};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -32,67 +50,391 @@ pub enum Error {
     SqlError(#[from] rusqlite::Error),
     #[error("error in performing I/O query: {0}")]
     IoError(#[from] std::io::Error),
+    #[error("error in formatting output: {0}")]
+    FormatError(#[from] std::fmt::Error),
+    #[error("error in rendering template: {0}")]
+    TemplateError(#[from] minijinja::Error),
+    #[error("{0} was edited since it was last published")]
+    PublishConflict(PathBuf),
+    #[error("error in committing to git: {0}")]
+    GitError(String),
+    #[error("error in hashing password: {0}")]
+    PasswordHashError(String),
+    #[error("no such user: {0}")]
+    NoSuchUser(String),
+    #[error("no such API token: {0}")]
+    NoSuchToken(isize),
+    #[error("the database has several users; say which one")]
+    AmbiguousUser,
+    #[error("invalid user name {0:?}: use letters, digits, '.', '_' and '-'")]
+    InvalidUserName(String),
+    #[error("backup failed its check: {0}")]
+    BadBackup(String),
     #[error("error in scanning body: {0}")]
     ScanningError(#[from] roundup::RoundupErrorKind),
 }
 
-pub fn serve<P: AsRef<std::path::Path>>(db: P, sources: P) -> Result<axum::Router, Error> {
-    let conn = Connection::open(db)?;
+/// Which articles may be included in a roundup.
+#[derive(Debug, Clone)]
+pub struct RoundupPolicy {
+    /// States an article must be in to be added to a roundup.
+    pub include: Vec<ReadState>,
+}
+
+impl Default for RoundupPolicy {
+    fn default() -> Self {
+        RoundupPolicy {
+            include: vec![ReadState::Read, ReadState::Skimmed],
+        }
+    }
+}
+
+impl RoundupPolicy {
+    pub fn allows(&self, state: ReadState) -> bool {
+        self.include.contains(&state)
+    }
+}
+
+/// Settings for the reading-list server.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub policy: RoundupPolicy,
+    /// Which files in journals to scan.
+    pub scan: ScanOptions,
+    /// Mail to scan along with the journal for users without their own.
+    pub mail: Option<Mailbox>,
+    pub export: ExportOptions,
+    /// Where to publish roundups to, if anywhere.
+    pub publish: Option<PublishOptions>,
+    /// URL the published roundups are served at by the public site, e.g.
+    /// `https://example.com/reading`. Feeds, from the editor too, link to pages there.
+    /// If unset, there are no feeds.
+    pub base_url: Option<String>,
+}
+
+/// Open the reading-list database, bringing its schema up to date.
+pub fn open<P: AsRef<std::path::Path>>(db: P) -> Result<Connection, Error> {
+    let mut conn = Connection::open(db)?;
+    roundup::migrate(&mut conn)?;
+    Ok(conn)
+}
+
+/// Number of articles in the database before and after an insertion.
+#[derive(Debug, Clone, Copy)]
+pub struct InsertCounts {
+    pub before: isize,
+    pub after: isize,
+}
+
+/// Insert the entries into the owner's reading list as a single transaction.
+pub fn insert_entries(
+    conn: &mut Connection,
+    owner: Option<isize>,
+    entries: &[ReadingListEntry],
+) -> rusqlite::Result<InsertCounts> {
+    let mut tx = conn.transaction()?;
+    let count = "SELECT COUNT(url) FROM reading_list WHERE owner IS :owner";
+    let before = tx.query_row(count, named_params! {":owner": owner}, |r| r.get(0))?;
+    roundup::insert(entries.iter(), owner, &mut tx)?;
+    let after = tx.query_row(count, named_params! {":owner": owner}, |r| r.get(0))?;
+    tx.commit()?;
+    Ok(InsertCounts { before, after })
+}
+
+/// Add an article to the owner's list from its Markdown body,
+/// as if it were journaled on the given date.
+/// Returns the ID of the article.
+pub fn add_article(
+    conn: &mut Connection,
+    owner: Option<isize>,
+    body: &str,
+    date: NaiveDate,
+) -> Result<isize, Error> {
+    let entry: ReadingListEntry = roundup::scan_body(date, body)?;
+    insert_entries(conn, owner, std::slice::from_ref(&entry))?;
+    Ok(conn.query_row(
+        "SELECT id FROM reading_list WHERE owner IS :owner AND url = :url",
+        named_params! {":owner": owner, ":url": entry.url.to_string()},
+        |row| row.get(0),
+    )?)
+}
+
+/// Condition on `reading_list` rows for the articles a user can see:
+/// their own, and those shared with them. Binds `:owner`.
+const VISIBLE: &str = r#"
+    (reading_list.owner IS :owner
+        OR reading_list.id IN (SELECT entry FROM article_shares WHERE user IS :owner))
+"#;
+
+/// Let another user see one of the owner's articles, and include it in their roundups.
+pub fn share_article(conn: &Connection, owner: isize, id: isize, with: &str) -> Result<(), Error> {
+    let user = auth::find_user(conn, with)?.ok_or_else(|| Error::NoSuchUser(with.to_owned()))?;
+    conn.execute(
+        r#"
+        INSERT INTO article_shares (entry, user)
+        SELECT id, :user FROM reading_list WHERE id = :id AND owner = :owner AND owner != :user
+        ON CONFLICT DO NOTHING
+        "#,
+        named_params! {":id": id, ":owner": owner, ":user": user.id},
+    )?;
+    Ok(())
+}
+
+/// Stop sharing one of the owner's articles with another user,
+/// taking it out of that user's roundups too.
+pub fn unshare_article(
+    conn: &Connection,
+    owner: isize,
+    id: isize,
+    with: &str,
+) -> Result<(), Error> {
+    let tx = conn.unchecked_transaction()?;
+    let params = named_params! {":id": id, ":owner": owner, ":name": with};
+    tx.execute(
+        r#"
+        DELETE FROM roundup_contents
+        WHERE entry IN (SELECT id FROM reading_list WHERE id = :id AND owner = :owner)
+        AND owner IN (SELECT id FROM users WHERE name = :name AND id != :owner)
+        "#,
+        params,
+    )?;
+    tx.execute(
+        r#"
+        DELETE FROM article_shares
+        WHERE entry IN (SELECT id FROM reading_list WHERE id = :id AND owner = :owner)
+        AND user IN (SELECT id FROM users WHERE name = :name)
+        "#,
+        params,
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Names of the users an article is shared with.
+pub fn article_shares(conn: &Connection, id: isize) -> Result<Vec<String>, Error> {
+    let rows: Result<Vec<String>, _> = conn
+        .prepare(
+            r#"
+            SELECT users.name FROM article_shares JOIN users ON users.id = article_shares.user
+            WHERE entry = :id ORDER BY users.name ASC
+            "#,
+        )?
+        .query_map(named_params! {":id": id}, |row| row.get(0))?
+        .collect();
+    Ok(rows?)
+}
+
+/// An article, as listed from the database.
+#[derive(Debug)]
+pub struct Article {
+    pub id: isize,
+    /// Number of roundups the article appears in.
+    pub roundups: isize,
+    pub entry: ReadingListEntry,
+}
+
+/// Criteria for listing articles.
+/// Unset criteria match all articles.
+#[derive(Debug, Default, Clone)]
+pub struct ArticleFilter {
+    /// Match articles in any of these states.
+    pub states: Vec<ReadState>,
+    /// Match articles journaled on or after this date.
+    pub since: Option<NaiveDate>,
+    /// Match articles journaled on or before this date.
+    pub until: Option<NaiveDate>,
+    /// Match articles that are (or are not) in some roundup.
+    pub in_roundup: Option<bool>,
+}
+
+/// List the articles the owner can see that match the filter, oldest first.
+/// Roundup counts are of the owner's roundups.
+pub fn find_articles(
+    conn: &Connection,
+    owner: Option<isize>,
+    filter: &ArticleFilter,
+) -> Result<Vec<Article>, Error> {
+    let rows: Result<Vec<_>, _> = conn
+        .prepare(&format!(
+            r#"
+            SELECT *, COALESCE(count, 0) AS roundups
+            FROM reading_list
+            LEFT JOIN
+                (SELECT entry, COUNT(DISTINCT date) as count FROM roundup_contents
+                    WHERE owner IS :owner GROUP BY entry)
+                ON reading_list.id = entry
+            WHERE {VISIBLE}
+            AND (:since IS NULL OR source_date >= :since)
+            AND (:until IS NULL OR source_date <= :until)
+            AND (:in_roundup IS NULL OR (COALESCE(count, 0) > 0) = :in_roundup)
+            ORDER BY source_date ASC, id ASC
+            "#
+        ))?
+        .query_map(
+            named_params! {
+                ":owner": owner,
+                ":since": filter.since.map(|d| format!("{d}")),
+                ":until": filter.until.map(|d| format!("{d}")),
+                ":in_roundup": filter.in_roundup,
+            },
+            |row| {
+                Ok(Article {
+                    id: row.get("id")?,
+                    roundups: row.get("roundups")?,
+                    entry: destruct_entry(row)?,
+                })
+            },
+        )?
+        .collect();
+    Ok(rows?
+        .into_iter()
+        .filter(|a| filter.states.is_empty() || filter.states.contains(&a.entry.state))
+        .collect())
+}
+
+/// Serve the editor.
+/// `sources` is the journal for users who don't have their own.
+pub fn serve<P: AsRef<std::path::Path>>(
+    db: P,
+    sources: Option<P>,
+    options: Options,
+) -> Result<axum::Router, Error> {
+    let conn = open(db)?;
+    if auth::count_users(&conn)? == 0 {
+        tracing::warn!("no users in the database; add one with `server user add`");
+    }
+    // Forms may come from pages at the public base URL, through a proxy with its own Host.
+    let base_url = options
+        .base_url
+        .as_deref()
+        .and_then(|v| v.parse::<Uri>().ok());
     let s = Arc::new(Mutex::new(Server {
         conn,
-        sources: sources.as_ref().to_owned(),
+        sources: sources.map(|p| p.as_ref().to_owned()),
+        options,
     }));
     Ok(axum::Router::new()
         .route(
             "/",
             get(|| async { (StatusCode::FOUND, [(LOCATION, "roundups/")]) }),
         )
-        .route("/update/", get(update))
+        .route("/update/", get(render_update).post(update))
         .route("/roundups/:date/", get(render_roundup).post(update_roundup))
         .route("/roundups/:date/md", get(render_roundup_md))
+        .route("/roundups/:date/export", get(export_roundup))
+        .route("/roundups/:date/publish", post(publish_roundup))
         .route("/roundups/", get(list_roundups).post(create_roundup))
         .route("/roundups/by-article/:id/", get(list_roundups_by_article))
         .route("/articles/", get(list_articles).post(create_article))
         .route("/articles/:id/", get(render_article).post(update_article))
+        .route("/articles/:id/share", post(share_article_form))
+        .route("/articles/:id/unshare", post(unshare_article_form))
+        .route("/add", get(quick_add::add_form).post(quick_add::add))
+        .route("/admin/backup", get(download_backup))
+        .route("/logout", post(auth::logout))
+        .merge(metrics::routes().route_layer(middleware::from_fn(auth::require_admin)))
+        .route_layer(middleware::from_fn(csrf::protect))
+        .route_layer(middleware::from_fn_with_state(
+            s.clone(),
+            auth::require_user,
+        ))
+        .route("/login", get(auth::login_form).post(auth::login))
+        .route("/feed.atom", get(atom_feed))
+        .route("/feed.rss", get(rss_feed))
         .route("/style.css", get(css))
+        .route("/manifest.webmanifest", get(quick_add::manifest))
+        .merge(metrics::health_routes())
+        .layer(middleware::from_fn_with_state(base_url, csrf::check_origin))
+        .layer(middleware::from_fn(metrics::track))
         .with_state(s))
 }
 
 struct Server {
     conn: rusqlite::Connection,
-    sources: PathBuf,
+    /// Journal for users without their own.
+    sources: Option<PathBuf>,
+    options: Options,
+}
+
+impl metrics::Monitored for Server {
+    fn conn(&self) -> &Connection {
+        &self.conn
+    }
+
+    fn dirs(&self) -> Vec<(&'static str, &std::path::Path)> {
+        let mut dirs = Vec::new();
+        if let Some(sources) = self
+            .sources
+            .as_ref()
+            .filter(|p| !roundup::source::is_archive(p))
+        {
+            dirs.push(("journal", sources.as_path()));
+        }
+        if let Some(publish) = &self.options.publish {
+            dirs.push(("publish dir", publish.dir.as_path()));
+        }
+        dirs
+    }
 }
 
 struct RoundupRow {
     id: isize,
     included: bool,
     count: isize,
-    html: String,
     entry: ReadingListEntry,
 }
 
-async fn update(State(s): State<Arc<Mutex<Server>>>) -> impl IntoResponse {
-    let dir = { s.lock().unwrap().sources.clone() };
-    let (entries, errors) = scan_files(&dir);
-    let mut s = s.lock().unwrap();
-    let mut count_pre: isize = 0;
-    let mut count_post: isize = 0;
-    let tx_done: rusqlite::Result<()> = (|| {
-        let mut tx = s.conn.transaction()?;
-        count_pre = tx.query_row(
-            "SELECT COUNT(url) FROM reading_list",
-            named_params! {},
-            |r| r.get(0),
-        )?;
-        roundup::insert(entries.iter(), &mut tx)?;
-        count_post = tx.query_row(
-            "SELECT COUNT(url) FROM reading_list",
-            named_params! {},
-            |r| r.get(0),
-        )?;
-        tx.commit()?;
-        Ok(())
-    })();
+/// Offer to scan the journal for new entries.
+async fn render_update() -> impl IntoResponse {
+    maud::html!(
+        head { link rel="stylesheet" href="/style.css"; }
+        body { (nav(1)) main {
+            h2 { "Update" }
+            form method="POST" class="controls" {
+                span { "Scan the journal for new articles." }
+                button type="submit" { "Scan" }
+            }
+        } }
+    )
+}
+
+/// Scan the user's journal and add new entries to their list.
+async fn update(
+    State(s): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
+) -> impl IntoResponse {
+    let (sources, mail, scan) = {
+        let s = metrics::lock(&s);
+        (
+            s.sources.clone(),
+            s.options.mail.clone(),
+            s.options.scan.clone(),
+        )
+    };
+    let (dir, mail) = match user.journal {
+        Some(dir) => (Some(dir), None),
+        None => (sources, mail),
+    };
+    if dir.is_none() && mail.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("no journal is configured for {}", user.name),
+        )
+            .into_response();
+    }
+    let start = Instant::now();
+    let mut report = dir.map(|dir| scan_journal(&dir, &scan)).unwrap_or_default();
+    if let Some(mail) = mail {
+        report.append(mail.scan());
+    }
+    let ScanReport {
+        entries,
+        errors,
+        files,
+    } = report;
+    metrics::record_scan(start.elapsed(), files, entries.len(), errors.len());
+    let mut s = metrics::lock(&s);
+    let tx_done = insert_entries(&mut s.conn, Some(user.id), &entries);
     let html = maud::html!(
         head { link rel="stylesheet" href="/style.css"; }
         body { (nav(1)) main {
@@ -104,7 +446,7 @@ async fn update(State(s): State<Arc<Mutex<Server>>>) -> impl IntoResponse {
             }
             h3 { "Databse report" }
             @match tx_done {
-                Ok(_) => { p { (format!("Update results: {} before, new total {}", count_pre, count_post)) } }
+                Ok(counts) => { p { (format!("Update results: {} before, new total {}", counts.before, counts.after)) } }
                 Err(ref e) => { p class="error db-error" { (format!("Database error: {e}")) } }
             }
         } }
@@ -118,28 +460,86 @@ async fn update(State(s): State<Arc<Mutex<Server>>>) -> impl IntoResponse {
 }
 
 fn destruct_roundup_row(row: &rusqlite::Row) -> rusqlite::Result<RoundupRow> {
-    let html = markdown::to_html(&row.get::<_, String>("body_text")?);
     let included = row.get::<_, Option<bool>>("included")?.unwrap_or(false);
     let count = row.get::<_, Option<isize>>("count")?.unwrap_or(0);
     Ok(RoundupRow {
         id: row.get("id")?,
         included,
         count,
-        html,
         entry: destruct_entry(row)?,
     })
 }
 
+/// Render an entry's Markdown body as HTML, with papers and books linked to their pages.
+pub(crate) fn entry_html(entry: &ReadingListEntry, book_url: &str) -> String {
+    markdown::to_html(&resolve_identifiers(&entry.body_text, book_url))
+}
+
 fn destruct_entry(row: &rusqlite::Row) -> rusqlite::Result<ReadingListEntry> {
     Ok(ReadingListEntry {
         url: row.get::<_, String>("url")?.parse().unwrap(),
         source_date: row.get::<_, String>("source_date")?.parse().unwrap(),
         original_text: row.get::<_, String>("original_text")?.to_owned(),
         body_text: row.get::<_, String>("body_text")?.to_owned(),
-        read: row.get("read")?,
+        state: row.get::<_, String>("state")?.parse().unwrap_or_default(),
+        kind: row
+            .get::<_, Option<String>>("kind")?
+            .and_then(|v| v.parse().ok()),
     })
 }
 
+fn state_sigil(state: ReadState) -> &'static str {
+    match state {
+        ReadState::Unknown => "?",
+        ReadState::ToRead => "📕",
+        ReadState::InProgress => "🔖",
+        ReadState::Read => "📖",
+        ReadState::Skimmed => "📄",
+        ReadState::Abandoned => "🗑",
+    }
+}
+
+/// Response to requests for feeds when there's no base URL for their links and IDs.
+/// The Host of a request won't do: it's set by the client, and feed IDs would change
+/// with the name the server was reached by.
+fn no_feeds() -> axum::response::Response {
+    (
+        StatusCode::NOT_FOUND,
+        "feeds are only served when base_url is configured",
+    )
+        .into_response()
+}
+
+async fn atom_feed(State(server): State<Arc<Mutex<Server>>>) -> impl IntoResponse {
+    let server = metrics::lock(&server);
+    let Some(base) = server.options.base_url.as_deref() else {
+        return no_feeds();
+    };
+    match feed::atom(&server.conn, base, &server.options.export.book_url) {
+        Ok(v) => (StatusCode::OK, [(CONTENT_TYPE, "application/atom+xml")], v).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("unexpected error: {e}"),
+        )
+            .into_response(),
+    }
+}
+
+async fn rss_feed(State(server): State<Arc<Mutex<Server>>>) -> impl IntoResponse {
+    let server = metrics::lock(&server);
+    let Some(base) = server.options.base_url.as_deref() else {
+        return no_feeds();
+    };
+    match feed::rss(&server.conn, base, &server.options.export.book_url) {
+        Ok(v) => (StatusCode::OK, [(CONTENT_TYPE, "application/rss+xml")], v).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("unexpected error: {e}"),
+        )
+            .into_response(),
+    }
+}
+
 async fn css() -> impl IntoResponse {
     static CSS_CONTENT: &str = include_str!("style.css");
     (
@@ -152,6 +552,7 @@ async fn css() -> impl IntoResponse {
 /// Render the editor for a roundup post.
 async fn render_roundup(
     State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
     Path(p): Path<String>,
 ) -> impl IntoResponse {
     let date: NaiveDate = match p.parse() {
@@ -165,8 +566,8 @@ async fn render_roundup(
         }
     };
 
-    let mut server = server.lock().unwrap();
-    match server.render_roundup(date) {
+    let mut server = metrics::lock(&server);
+    match server.render_roundup(&user, date) {
         Ok(v) => v.into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -176,11 +577,32 @@ async fn render_roundup(
     }
 }
 
-/// Render the editor for a roundup post.
+/// Download a roundup post as Markdown.
 async fn render_roundup_md(
     State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
     Path(p): Path<String>,
 ) -> impl IntoResponse {
+    export_roundup(
+        State(server),
+        Extension(user),
+        Path(p),
+        Query(HashMap::new()),
+    )
+    .await
+}
+
+/// Download a roundup post in the format given by the `format` query parameter.
+async fn export_roundup(
+    State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
+    Path(p): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+) -> axum::response::Response {
+    let format: Format = match query.get("format").map(|v| v.parse()).transpose() {
+        Ok(v) => v.unwrap_or_default(),
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("{e}")).into_response(),
+    };
     let date: NaiveDate = match p.parse() {
         Ok(v) => v,
         Err(_) => {
@@ -192,9 +614,81 @@ async fn render_roundup_md(
         }
     };
 
-    let mut server = server.lock().unwrap();
-    match server.render_roundup_md(date) {
+    let mut server = metrics::lock(&server);
+    match server.export_roundup(&user, date, format) {
+        Ok(v) => v.into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("unexpected error: {e}"),
+        )
+            .into_response(),
+    }
+}
+
+/// Download a backup of the whole database; only for administrators.
+async fn download_backup(
+    State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
+) -> axum::response::Response {
+    if !user.admin {
+        return (
+            StatusCode::FORBIDDEN,
+            "only administrators may download backups",
+        )
+            .into_response();
+    }
+    let Some(db) = metrics::lock(&server).conn.path().map(PathBuf::from) else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "the database is not in a file",
+        )
+            .into_response();
+    };
+    // Back up from a connection of its own, so the editor's stays usable meanwhile.
+    match backup::snapshot(&db) {
+        Ok(bytes) => (
+            [
+                (CONTENT_TYPE, "application/vnd.sqlite3".to_owned()),
+                (
+                    CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", backup::file_name()),
+                ),
+            ],
+            bytes,
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("unexpected error: {e}"),
+        )
+            .into_response(),
+    }
+}
+
+async fn publish_roundup(
+    State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
+    Path(date): Path<String>,
+    Form(form): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let date: NaiveDate = match date.parse() {
+        Ok(v) => v,
+        Err(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "reading roundups are identified by date",
+            )
+                .into_response()
+        }
+    };
+    let force = form.contains_key("force");
+
+    let mut server = metrics::lock(&server);
+    match server.publish_roundup(&user, date, force) {
         Ok(v) => v.into_response(),
+        Err(e @ Error::PublishConflict(_)) => {
+            (StatusCode::CONFLICT, format!("{e}")).into_response()
+        }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("unexpected error: {e}"),
@@ -203,9 +697,12 @@ async fn render_roundup_md(
     }
 }
 
-async fn list_roundups(State(server): State<Arc<Mutex<Server>>>) -> impl IntoResponse {
-    let mut server = server.lock().unwrap();
-    match server.list_roundups() {
+async fn list_roundups(
+    State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
+) -> impl IntoResponse {
+    let mut server = metrics::lock(&server);
+    match server.list_roundups(&user) {
         Ok(v) => v.into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -217,10 +714,11 @@ async fn list_roundups(State(server): State<Arc<Mutex<Server>>>) -> impl IntoRes
 
 async fn list_roundups_by_article(
     State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
     Path(id): Path<isize>,
 ) -> impl IntoResponse {
-    let mut server = server.lock().unwrap();
-    match server.list_roundups_by_article(id) {
+    let mut server = metrics::lock(&server);
+    match server.list_roundups_by_article(&user, id) {
         Ok(v) => v.into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -230,9 +728,12 @@ async fn list_roundups_by_article(
     }
 }
 
-async fn list_articles(State(server): State<Arc<Mutex<Server>>>) -> impl IntoResponse {
-    let mut server = server.lock().unwrap();
-    match server.list_articles() {
+async fn list_articles(
+    State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
+) -> impl IntoResponse {
+    let mut server = metrics::lock(&server);
+    match server.list_articles(&user) {
         Ok(v) => v.into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -245,10 +746,11 @@ async fn list_articles(State(server): State<Arc<Mutex<Server>>>) -> impl IntoRes
 /// Render the editor for a roundup post.
 async fn render_article(
     State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
     Path(p): Path<isize>,
 ) -> impl IntoResponse {
-    let mut server = server.lock().unwrap();
-    match server.render_article(p) {
+    let mut server = metrics::lock(&server);
+    match server.render_article(&user, p) {
         Ok(v) => v.into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -260,6 +762,7 @@ async fn render_article(
 
 async fn create_article(
     State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
     Form(form): Form<HashMap<String, String>>,
 ) -> impl IntoResponse {
     let body = match form.get("text") {
@@ -267,8 +770,8 @@ async fn create_article(
         None => return (StatusCode::BAD_REQUEST, "missing text for new article").into_response(),
     };
 
-    let mut server = server.lock().unwrap();
-    match server.create_article(body) {
+    let mut server = metrics::lock(&server);
+    match server.create_article(&user, body) {
         Ok(v) => v.into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -280,6 +783,7 @@ async fn create_article(
 
 async fn update_article(
     State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
     Path(id): Path<isize>,
     OriginalUri(uri): OriginalUri,
     Form(form): Form<HashMap<String, String>>,
@@ -288,10 +792,22 @@ async fn update_article(
         Some(v) => v,
         None => return (StatusCode::BAD_REQUEST, "missing body_text for update").into_response(),
     };
-    let read_state = form.get("read").map(|v| v == "read");
+    let state: Option<ReadState> = match form.get("state").map(|v| v.parse()).transpose() {
+        Ok(v) => v,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("{e}")).into_response(),
+    };
+    // An empty kind goes back to inferring it from the URL.
+    let kind: Option<Option<Kind>> = match form.get("kind").map(|v| match v.as_str() {
+        "" => Ok(None),
+        v => v.parse().map(Some),
+    }) {
+        None => None,
+        Some(Ok(v)) => Some(v),
+        Some(Err(e)) => return (StatusCode::BAD_REQUEST, format!("{e}")).into_response(),
+    };
 
-    let mut server = server.lock().unwrap();
-    match server.update_article(id, uri, body, read_state) {
+    let mut server = metrics::lock(&server);
+    match server.update_article(&user, id, uri, body, state, kind) {
         Ok(v) => v.into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -301,11 +817,55 @@ async fn update_article(
     }
 }
 
+/// Share an article with the user named in the form.
+async fn share_article_form(
+    State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<isize>,
+    Form(form): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let Some(name) = form.get("name") else {
+        return (StatusCode::BAD_REQUEST, "missing name to share with").into_response();
+    };
+    let server = metrics::lock(&server);
+    match share_article(&server.conn, user.id, id, name) {
+        Ok(()) => (StatusCode::SEE_OTHER, [(LOCATION, "./")]).into_response(),
+        Err(e @ Error::NoSuchUser(_)) => (StatusCode::BAD_REQUEST, format!("{e}")).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("unexpected error: {e}"),
+        )
+            .into_response(),
+    }
+}
+
+/// Stop sharing an article with the user named in the form.
+async fn unshare_article_form(
+    State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
+    Path(id): Path<isize>,
+    Form(form): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let Some(name) = form.get("name") else {
+        return (StatusCode::BAD_REQUEST, "missing name to stop sharing with").into_response();
+    };
+    let server = metrics::lock(&server);
+    match unshare_article(&server.conn, user.id, id, name) {
+        Ok(()) => (StatusCode::SEE_OTHER, [(LOCATION, "./")]).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("unexpected error: {e}"),
+        )
+            .into_response(),
+    }
+}
+
 async fn update_roundup(
     State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
     Path(date): Path<String>,
     OriginalUri(uri): OriginalUri,
-    Form(form): Form<HashMap<String, Vec<isize>>>,
+    Form(form): Form<HashMap<String, Vec<String>>>,
 ) -> impl IntoResponse {
     let date: NaiveDate = match date.parse() {
         Ok(v) => v,
@@ -317,13 +877,19 @@ async fn update_roundup(
                 .into_response()
         }
     };
-    let articles = form
+    let articles: Result<Vec<isize>, _> = form
         .get("article-included")
-        .cloned()
-        .unwrap_or_else(Vec::new);
+        .into_iter()
+        .flatten()
+        .map(|v| v.parse())
+        .collect();
+    let articles = match articles {
+        Ok(v) => v,
+        Err(_) => return (StatusCode::BAD_REQUEST, "invalid article ID").into_response(),
+    };
 
-    let mut server = server.lock().unwrap();
-    match server.update_roundup(uri, date, &articles) {
+    let mut server = metrics::lock(&server);
+    match server.update_roundup(&user, uri, date, &articles) {
         Ok(v) => v.into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -341,11 +907,10 @@ async fn create_roundup(
 ) -> impl IntoResponse {
     let date: NaiveDate = match form
         .get("new-roundup")
-        .ok_or_else(|| (StatusCode::BAD_REQUEST, "missing new-roundup date").into_response())
+        .ok_or((StatusCode::BAD_REQUEST, "missing new-roundup date"))
         .and_then(|s| {
-            s.parse().map_err(|_| {
-                (StatusCode::BAD_REQUEST, "invalid date for new roundup").into_response()
-            })
+            s.parse()
+                .map_err(|_| (StatusCode::BAD_REQUEST, "invalid date for new roundup"))
         }) {
         Ok(v) => v,
         Err(e) => return e.into_response(),
@@ -374,6 +939,9 @@ fn nav(depth: usize) -> PreEscaped<String> {
             li { a href=(format!("{prefix}/roundups/")) { "Roundups" } }
             li { a href=(format!("{prefix}/articles/")) { "Articles" } }
             li { a href=(format!("{prefix}/update/")) { "Update" } }
+            li { form method="POST" action=(format!("{prefix}/logout")) {
+                button type="submit" { "Log out" }
+            } }
         } }
     )
 }
@@ -381,78 +949,168 @@ fn nav(depth: usize) -> PreEscaped<String> {
 impl Server {
     fn update_roundup(
         &mut self,
+        user: &User,
         uri: Uri,
         date: chrono::NaiveDate,
         articles: &[isize],
-    ) -> Result<impl IntoResponse, Error> {
+    ) -> Result<axum::response::Response, Error> {
         let date_str = format!("{date}");
         // Do "remove all other entries" and "add new entries" as a single,
         // atomic, transaction.
         let tx = self.conn.transaction()?;
-        tx.prepare("DELETE FROM roundup_contents WHERE date = :date")?
-            .execute(named_params! {":date": &date_str})?;
-        let mut st =
-            tx.prepare("INSERT INTO roundup_contents (date, entry) VALUES (:date, :id)")?;
+        tx.prepare("DELETE FROM roundup_contents WHERE owner IS :owner AND date = :date")?
+            .execute(named_params! {":owner": user.id, ":date": &date_str})?;
+        let mut state_of = tx.prepare(&format!(
+            "SELECT state FROM reading_list WHERE id = :id AND {VISIBLE}"
+        ))?;
+        let mut st = tx.prepare(
+            "INSERT INTO roundup_contents (owner, date, entry) VALUES (:owner, :date, :id)",
+        )?;
+        let mut rejected = Vec::new();
         for id in articles {
-            st.execute(named_params! {":date": &date_str, ":id": id})?;
+            let state: Option<String> = state_of
+                .query_row(named_params! {":id": id, ":owner": user.id}, |row| {
+                    row.get(0)
+                })
+                .optional()?;
+            // Refuse articles the user can't see, as well as those the policy excludes.
+            match state {
+                Some(state)
+                    if self
+                        .options
+                        .policy
+                        .allows(state.parse().unwrap_or_default()) =>
+                {
+                    st.execute(named_params! {":owner": user.id, ":date": &date_str, ":id": id})?;
+                }
+                _ => rejected.push(id.to_string()),
+            }
         }
         drop(st);
+        drop(state_of);
+        if !rejected.is_empty() {
+            // Dropping the transaction leaves the roundup as it was.
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "these articles can't be included in a roundup: {}",
+                    rejected.join(", ")
+                ),
+            )
+                .into_response());
+        }
         tx.commit()?;
-        Ok((StatusCode::SEE_OTHER, [(LOCATION, uri.to_string())]))
+        Ok((StatusCode::SEE_OTHER, [(LOCATION, uri.to_string())]).into_response())
     }
 
-    fn create_article(&mut self, new_body: &str) -> Result<impl IntoResponse, Error> {
+    fn create_article(&mut self, user: &User, new_body: &str) -> Result<impl IntoResponse, Error> {
         let now: chrono::NaiveDate = chrono::Local::now().date_naive();
-        let entry: ReadingListEntry = roundup::scan_body(now, new_body)?;
-        let mut tx = self.conn.transaction()?;
-        roundup::insert([&entry].into_iter(), &mut tx)?;
-        tx.commit()?;
-        let id: isize = self.conn.query_row(
-            "SELECT id FROM reading_list WHERE url = :url",
-            named_params! {":url": entry.url.to_string()},
-            |row| row.get(0),
-        )?;
+        let id = add_article(&mut self.conn, Some(user.id), new_body, now)?;
 
         Ok((StatusCode::SEE_OTHER, [(LOCATION, format!("{id}/"))]))
     }
 
     fn update_article(
         &mut self,
+        user: &User,
         id: isize,
         uri: Uri,
         new_body: &str,
-        read_state: Option<bool>,
-    ) -> Result<impl IntoResponse, Error> {
-        self.conn
+        state: Option<ReadState>,
+        kind: Option<Option<Kind>>,
+    ) -> Result<axum::response::Response, Error> {
+        let tx = self.conn.transaction()?;
+        let updated = tx
             .prepare(
                 r#"
             UPDATE reading_list
-            SET body_text = :body_text, read = :read
-            WHERE id = :id
+            SET body_text = :body_text
+            WHERE id = :id AND owner IS :owner
         "#,
             )?
-            .execute(named_params! {":id" : id, ":body_text" : new_body, ":read": read_state})?;
-        Ok((StatusCode::SEE_OTHER, [(LOCATION, uri.to_string())]))
+            .execute(named_params! {":id" : id, ":owner": user.id, ":body_text" : new_body})?;
+        if updated == 0 {
+            return Ok((
+                StatusCode::NOT_FOUND,
+                "no such article, or it belongs to someone else",
+            )
+                .into_response());
+        }
+        if let Some(state) = state {
+            let current: ReadState = tx
+                .query_row(
+                    "SELECT state FROM reading_list WHERE id = :id",
+                    named_params! {":id": id},
+                    |row| row.get::<_, String>(0),
+                )?
+                .parse()
+                .unwrap_or_default();
+            if state != current {
+                if !current.can_transition_to(state) {
+                    return Ok((
+                        StatusCode::BAD_REQUEST,
+                        format!("cannot move article from {current} to {state}"),
+                    )
+                        .into_response());
+                }
+                roundup::transition(&tx, id, state)?;
+            }
+        }
+        if let Some(kind) = kind {
+            tx.execute(
+                "UPDATE reading_list SET kind = :kind WHERE id = :id",
+                named_params! {":id": id, ":kind": kind.map(|k| k.as_str())},
+            )?;
+        }
+        tx.commit()?;
+        Ok((StatusCode::SEE_OTHER, [(LOCATION, uri.to_string())]).into_response())
     }
-    fn render_article(&mut self, id: isize) -> Result<impl IntoResponse, Error> {
+
+    fn render_article(
+        &mut self,
+        user: &User,
+        id: isize,
+    ) -> Result<axum::response::Response, Error> {
         // Query everything, prioritizing stuff in the roundup.
-        let (count, entry) = self
+        let row = self
             .conn
-            .prepare(
+            .prepare(&format!(
                 r#"
-            SELECT *, COUNT(roundup_contents.date) as roundups
+            SELECT reading_list.*, users.name AS owner_name,
+                COUNT(roundup_contents.date) as roundups
             FROM reading_list
-            LEFT JOIN roundup_contents ON reading_list.id = roundup_contents.entry
-            WHERE reading_list.id = :id
-            "#,
-            )?
-            .query_row(named_params! {":id": id}, |row| {
+            LEFT JOIN users ON users.id = reading_list.owner
+            LEFT JOIN roundup_contents
+                ON reading_list.id = roundup_contents.entry AND roundup_contents.owner IS :owner
+            WHERE reading_list.id = :id AND {VISIBLE}
+            GROUP BY reading_list.id
+            "#
+            ))?
+            .query_row(named_params! {":id": id, ":owner": user.id}, |row| {
                 let count: isize = row.get("roundups")?;
+                let owner: Option<isize> = row.get("owner")?;
+                let owner_name: Option<String> = row.get("owner_name")?;
                 let entry = destruct_entry(row)?;
-                Ok((count, entry))
-            })?;
-        let tbr = !entry.read.unwrap_or(true);
-        let read = entry.read.unwrap_or(false);
+                Ok((count, owner, owner_name, entry))
+            })
+            .optional()?;
+        let Some((count, owner, owner_name, entry)) = row else {
+            return Ok((StatusCode::NOT_FOUND, "no such article").into_response());
+        };
+        let mine = owner == Some(user.id);
+        let shares = if mine {
+            article_shares(&self.conn, id)?
+        } else {
+            Vec::new()
+        };
+        let transitions: Result<Vec<(String, String)>, _> = self
+            .conn
+            .prepare(
+                "SELECT state, at FROM state_transitions WHERE entry = :id ORDER BY at ASC, rowid ASC",
+            )?
+            .query_map(named_params! {":id": id}, |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect();
+        let transitions = transitions?;
         Ok(maud::html! {
             head { link rel="stylesheet" href="/style.css"; }
             body {
@@ -460,56 +1118,116 @@ impl Server {
                 main {
                 div class="summary" {
                     h3 {
-                        a href=(entry.url) { (entry.url) }
+                        a href=(page_url(&entry.url, &self.options.export.book_url)) { (entry.url) }
                     }
                     h4 class="tile-title" {
                         p { (entry.source_date) }
+                        @if !mine {
+                            p { "Shared by " (owner_name.unwrap_or_default()) }
+                        }
                         p { a href=(format!("../../roundups/by-article/{id}/")) { (format!("{count} roundups")) } }
                     }
                 }
-                form action="" method="POST" {
+                @if mine {
                     div class="controls" {
                         span {
-                            input type="radio" value="tbr" id="tbr" name="read" checked?[tbr];
-                            label for="tbr" { "TBR" }
-                            input type="radio" value="read" id="tbr" name="read" checked?[read];
-                            label for="read" { "Read" }
+                            "Shared with: "
+                            @if shares.is_empty() { "nobody" }
+                            @for name in &shares {
+                                form method="POST" action="unshare" class="inline" {
+                                    input type="hidden" name="name" value=(name);
+                                    (name) " "
+                                    button type="submit" title=(format!("Stop sharing with {name}")) { "×" }
+                                }
+                            }
+                        }
+                        form method="POST" action="share" {
+                            input type="text" name="name" placeholder="user name";
+                            button type="submit" { "Share" }
+                        }
+                    }
+                }
+                form action="" method="POST" {
+                    @if mine {
+                        div class="controls" {
+                            span {
+                                label for="state" { (state_sigil(entry.state)) " " }
+                                select id="state" name="state" {
+                                    @for state in ReadState::ALL {
+                                        option value=(state.as_str())
+                                            selected?[state == entry.state]
+                                            disabled?[state != entry.state && !entry.state.can_transition_to(state)]
+                                            { (state.label()) }
+                                    }
+                                }
+                            }
+                            span {
+                                select name="kind" title="Kind" {
+                                    option value="" selected?[entry.kind.is_none()] {
+                                        "Automatic (" (Kind::of_target(&entry.url).label()) ")"
+                                    }
+                                    @for kind in Kind::ALL {
+                                        option value=(kind.as_str())
+                                            selected?[entry.kind == Some(kind)]
+                                            { (kind.label()) }
+                                    }
+                                }
+                            }
+                            button label="Save" type="submit" { "Save" }
+                        }
+                    } @else {
+                        div class="controls" {
+                            span { (state_sigil(entry.state)) " " (entry.state.label()) }
+                            span { (entry.kind().label()) }
                         }
-                        button label="Save" type="submit" { "Save" }
+                    }
+                    details {
+                        summary { "History" }
+                        table { @for (state, at) in &transitions { tr {
+                            td { (at) }
+                            td { (state.parse::<ReadState>().map(|s| s.label()).unwrap_or(state)) }
+                        } } }
                     }
                     details { summary { "Original" } pre { (entry.original_text) } }
                     details open {
                         summary { "Preview" }
                         div class="summary" {
-                            (maud::PreEscaped(markdown::to_html(&entry.body_text)))
+                            (maud::PreEscaped(entry_html(&entry, &self.options.export.book_url)))
                         }
                     }
-                    textarea name="body_text" { (entry.body_text) }
+                    @if mine {
+                        textarea name="body_text" { (entry.body_text) }
+                    }
                 }
             } }
-        })
+        }
+        .into_response())
     }
 
-    /// List all articles.
-    fn list_articles(&mut self) -> Result<impl IntoResponse, Error> {
-        let rows : Result<Vec<_>, _> = self.conn.prepare(r#"
+    /// List the articles the user can see.
+    fn list_articles(&mut self, user: &User) -> Result<impl IntoResponse, Error> {
+        let rows: Result<Vec<_>, _> = self
+            .conn
+            .prepare(&format!(
+                r#"
             SELECT *
             FROM reading_list
             LEFT JOIN
-                (SELECT entry, COUNT(DISTINCT date) as count, 1 as included FROM roundup_contents GROUP BY entry)
+                (SELECT entry, COUNT(DISTINCT date) as count, 1 as included FROM roundup_contents
+                    WHERE owner IS :owner GROUP BY entry)
                 ON reading_list.id = entry
+            WHERE {VISIBLE}
             ORDER BY count ASC, source_date ASC
-            "#)?.query_map(named_params! {}, destruct_roundup_row)?.collect();
+            "#
+            ))?
+            .query_map(named_params! {":owner": user.id}, destruct_roundup_row)?
+            .collect();
         let entries = rows?;
 
-        fn render_row(row: &RoundupRow) -> PreEscaped<String> {
-            let unread_sigil = match row.entry.read {
-                None => "?",
-                Some(true) => "📖",
-                Some(false) => "📕",
-            };
+        fn render_row(row: &RoundupRow, book_url: &str) -> PreEscaped<String> {
+            let unread_sigil = state_sigil(row.entry.state);
             maud::html!( tr {
-                    td { (maud::PreEscaped(row.html.clone())) }
+                    td { (maud::PreEscaped(entry_html(&row.entry, book_url))) }
                     td { a href=(format!("../roundups/by-article/{}/", row.id)) { (row.count) } }
                     td { (unread_sigil) }
                     td { (format!("{}", row.entry.source_date)) }
@@ -527,20 +1245,24 @@ impl Server {
                     button type="submit" { "Add article" }
                 } }
 
-                table { @for entry in entries { (render_row(&entry)) } }
+                table { @for entry in entries { (render_row(&entry, &self.options.export.book_url)) } }
                 }
             }
         })
     }
 
-    /// List the roundups that contain a particular article.
-    fn list_roundups_by_article(&mut self, id: isize) -> Result<impl IntoResponse, Error> {
+    /// List the user's roundups that contain a particular article.
+    fn list_roundups_by_article(
+        &mut self,
+        user: &User,
+        id: isize,
+    ) -> Result<impl IntoResponse, Error> {
         let rows: Result<Vec<String>, _> = self
             .conn
             .prepare(
-                "SELECT DISTINCT date FROM roundup_contents WHERE entry = :id ORDER BY date ASC",
+                "SELECT DISTINCT date FROM roundup_contents WHERE owner IS :owner AND entry = :id ORDER BY date ASC",
             )?
-            .query_map(named_params! {":id": id}, |row| row.get("date"))?
+            .query_map(named_params! {":owner": user.id, ":id": id}, |row| row.get("date"))?
             .collect();
         let rows = rows?;
 
@@ -561,12 +1283,12 @@ impl Server {
         } })
     }
 
-    /// List all roundups.
-    fn list_roundups(&mut self) -> Result<impl IntoResponse, Error> {
+    /// List the user's roundups.
+    fn list_roundups(&mut self, user: &User) -> Result<impl IntoResponse, Error> {
         let rows: Result<Vec<String>, _> = self
             .conn
-            .prepare("SELECT DISTINCT date FROM roundup_contents ORDER BY date ASC")?
-            .query_map(named_params! {}, |row| row.get("date"))?
+            .prepare("SELECT DISTINCT date FROM roundup_contents WHERE owner IS :owner ORDER BY date ASC")?
+            .query_map(named_params! {":owner": user.id}, |row| row.get("date"))?
             .collect();
         let rows = rows?;
 
@@ -590,79 +1312,116 @@ impl Server {
         })
     }
 
-    fn render_roundup_md(&mut self, date: NaiveDate) -> Result<impl IntoResponse, Error> {
-        let date_str = format!("{date}");
-        let rows: Result<Vec<_>, _> = self
-            .conn
-            .prepare(
-                r#"
-            SELECT reading_list.body_text
-            FROM roundup_contents LEFT JOIN reading_list ON reading_list.id = roundup_contents.entry
-            WHERE roundup_contents.date = :date
-            "#,
-            )?
-            .query_map(named_params! {":date": &date_str}, |row| row.get(0))?
-            .collect();
-        let bodies: Vec<String> = rows?;
-        let mut s = Cursor::new(Vec::<u8>::new());
-        write!(
-            s,
-            r#"---
-title: "Reading Roundup, {date_str}"
-date: {date_str}
----
-
-"#
-        )?;
-        for body in bodies {
-            writeln!(s, "{body}")?;
-            // Additional newline as paragraph break
-            writeln!(s)?;
-        }
+    fn export_roundup(
+        &mut self,
+        user: &User,
+        date: NaiveDate,
+        format: Format,
+    ) -> Result<impl IntoResponse, Error> {
+        let s =
+            Roundup::load(&self.conn, Some(user.id), date)?.render(format, &self.options.export)?;
         Ok((
             StatusCode::OK,
             [
-                (CONTENT_TYPE, "text/markdown; charset=UTF-8".to_owned()),
+                (CONTENT_TYPE, format.content_type().to_owned()),
                 (
                     CONTENT_DISPOSITION,
-                    format!("attachment; filename=\"{date}.md\""),
+                    format!("attachment; filename=\"{date}.{}\"", format.extension()),
                 ),
             ],
-            s.into_inner(),
+            s,
         ))
     }
 
-    fn render_roundup(&mut self, date: NaiveDate) -> Result<impl IntoResponse, Error> {
+    fn publish_roundup(
+        &mut self,
+        user: &User,
+        date: NaiveDate,
+        force: bool,
+    ) -> Result<axum::response::Response, Error> {
+        publish::publish(
+            &self.conn,
+            user,
+            date,
+            &self.options.export,
+            self.options.publish.as_ref(),
+            force,
+        )?;
+        Ok((StatusCode::SEE_OTHER, [(LOCATION, "./")]).into_response())
+    }
+
+    /// Render the controls for publishing a roundup.
+    fn render_publish(&self, user: &User, date: NaiveDate) -> Result<PreEscaped<String>, Error> {
+        let path = self
+            .options
+            .publish
+            .as_ref()
+            .map(|options| options.path(&user.name, date));
+        let last = publish::last_publication(&self.conn, Some(user.id), date)?;
+        let status = match &path {
+            Some(path) => publish::file_status(path, last.as_ref())?,
+            None => FileStatus::Absent,
+        };
+        Ok(maud::html! {
+            form method="POST" action="publish" class="controls" {
+                span {
+                    @match &last {
+                        Some(last) => {
+                            "Published " (last.published_at)
+                            @if let Some(path) = &last.path { " to " (path.display()) }
+                        }
+                        None => { "Not yet published" }
+                    }
+                }
+                @if let (FileStatus::Edited, Some(path)) = (status, &path) {
+                    span class="error" {
+                        (format!("{} was edited since it was last published. ", path.display()))
+                        input type="checkbox" name="force" id="force";
+                        label for="force" { "Overwrite" }
+                    }
+                }
+                button type="submit" { "Publish" }
+            }
+        })
+    }
+
+    fn render_roundup(&mut self, user: &User, date: NaiveDate) -> Result<impl IntoResponse, Error> {
         // Query everything, prioritizing stuff in the roundup.
         let rows: Result<Vec<_>, _> = self
             .conn
-            .prepare(
+            .prepare(&format!(
                 r#"
             SELECT *
             FROM reading_list
             LEFT JOIN
-                (SELECT entry as entry2, COUNT(DISTINCT date) as count FROM roundup_contents GROUP BY entry2)
+                (SELECT entry as entry2, COUNT(DISTINCT date) as count FROM roundup_contents
+                    WHERE owner IS :owner GROUP BY entry2)
                 ON reading_list.id = entry2
             LEFT JOIN
-                (SELECT entry as entry1, 1 as included FROM roundup_contents WHERE date = :date)
+                (SELECT entry as entry1, 1 as included FROM roundup_contents
+                    WHERE owner IS :owner AND date = :date)
                 ON reading_list.id = entry1
+            WHERE {VISIBLE}
             ORDER BY included DESC, count ASC, source_date ASC
-            "#)?
+            "#
+            ))?
             .query_map(
-                named_params! {":date": format!("{date}")},
+                named_params! {":owner": user.id, ":date": format!("{date}")},
                 destruct_roundup_row,
             )?
             .collect();
         let rows = rows?;
+        let publish = self.render_publish(user, date)?;
         let included_rows = rows.iter().filter(|v| v.included);
         let excluded_rows = rows.iter().filter(|v| !v.included);
 
-        fn render_row(row: &RoundupRow) -> PreEscaped<String> {
-            let unread = !row.entry.read.unwrap_or(false);
+        fn render_row(options: &Options, row: &RoundupRow) -> PreEscaped<String> {
+            let excluded = !options.policy.allows(row.entry.state);
             maud::html!( tr {
-                    td { (maud::PreEscaped(row.html.clone())) }
+                    td { (maud::PreEscaped(entry_html(&row.entry, &options.export.book_url))) }
                     td { a href=(format!("../by-article/{}/", row.id)) { (row.count) } }
-                    td { input type="checkbox" name="article-included" value=(row.id) checked?[row.included] disabled?[unread]; }
+                    td { input type="checkbox" name="article-included" value=(row.id) checked?[row.included] disabled?[excluded]; }
+                    td { (state_sigil(row.entry.state)) }
                     td { (format!("{}", row.entry.source_date)) }
                     td { a href=(format!("/articles/{}/", row.id)) { (maud::PreEscaped("&nbsp;🖉&nbsp;")) } }
                 }
@@ -673,25 +1432,403 @@ date: {date_str}
             head { link rel="stylesheet" href="/style.css"; }
             body {
                 (nav(2))
-                main { form method="POST" {
+                main {
+                (publish)
+                form method="POST" {
                     div class="summary" {
                         h3 class="tile-title" {
                             (format!("Reading Roundup, {date}"))
-                            a href="md" { "Download" }
+                            span {
+                                "Download: "
+                                @for format in Format::ALL {
+                                    a href=(format!("export?format={}", format.as_str())) { (format.extension()) }
+                                    " "
+                                }
+                            }
                             button label="Save" type="submit" { "Save" }
                          }
 
                         table {
-                            @for row in included_rows { (render_row(row)) }
+                            @for row in included_rows { (render_row(&self.options, row)) }
                         }
                     }
 
                     h3 { "Add to this roundup: " }
                     table {
-                    @for row in excluded_rows { (render_row(row)) }
+                    @for row in excluded_rows { (render_row(&self.options, row)) }
                     }
                 } }
             }
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{
+            header::{CONTENT_TYPE, COOKIE, HOST, ORIGIN, REFERER, SET_COOKIE},
+            Request,
+        },
+        response::Response,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// An editor with its own database, an article by "alice", and a session for her.
+    struct Fixture {
+        dir: PathBuf,
+        router: axum::Router,
+        article: isize,
+        alice: String,
+    }
+
+    impl Fixture {
+        async fn new(name: &str) -> Fixture {
+            Fixture::with_options(name, Options::default()).await
+        }
+
+        async fn with_options(name: &str, options: Options) -> Fixture {
+            let dir =
+                std::env::temp_dir().join(format!("reading-test-{}-{name}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let db = dir.join("reading.db");
+            let _ = std::fs::remove_file(&db);
+            let mut conn = open(&db).unwrap();
+            let alice = auth::add_user(&conn, "alice", "alice's password", None, false).unwrap();
+            auth::add_user(&conn, "bob", "bob's password", None, false).unwrap();
+            let article = add_article(
+                &mut conn,
+                Some(alice.id),
+                "[A post](https://example.com/a)",
+                NaiveDate::from_ymd_opt(2024, 9, 1).unwrap(),
+            )
+            .unwrap();
+            drop(conn);
+            let router = serve(db.as_path(), None, options).unwrap();
+            let mut fixture = Fixture {
+                dir,
+                router,
+                article,
+                alice: String::new(),
+            };
+            fixture.alice = fixture.login("alice", "alice's password").await;
+            fixture
+        }
+
+        async fn send(&self, request: Request<Body>) -> Response {
+            self.router.clone().oneshot(request).await.unwrap()
+        }
+
+        /// Log in, returning the session cookie.
+        async fn login(&self, name: &str, password: &str) -> String {
+            let body: String = form_urlencoded::Serializer::new(String::new())
+                .append_pair("name", name)
+                .append_pair("password", password)
+                .finish();
+            let response = self
+                .send(
+                    Request::post("/login")
+                        .header(HOST, "localhost")
+                        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await;
+            assert_eq!(response.status(), StatusCode::SEE_OTHER);
+            let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+            let (pair, _) = cookie.split_once(';').unwrap_or((cookie, ""));
+            pair.strip_prefix("session=").unwrap().to_owned()
+        }
+
+        /// The CSRF token in the session's forms.
+        async fn token(&self, session: &str) -> String {
+            let page = self
+                .send(
+                    Request::get("/articles/")
+                        .header(COOKIE, format!("session={session}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await;
+            assert_eq!(page.status(), StatusCode::OK);
+            let page = body_text(page).await;
+            let (_, rest) = page.split_once(r#"name="csrf" value=""#).unwrap();
+            let (token, _) = rest.split_once('"').unwrap();
+            token.to_owned()
+        }
+
+        /// A form post to the article from the session's user.
+        fn update(&self, session: &str) -> axum::http::request::Builder {
+            Request::post(format!("/articles/{}/", self.article))
+                .header(HOST, "localhost")
+                .header(COOKIE, format!("session={session}"))
+                .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    async fn body_text(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn refuses_forms_without_the_session_token() {
+        let f = Fixture::new("csrf").await;
+        let response = f
+            .send(f.update(&f.alice).body(Body::from("body_text=x")).unwrap())
+            .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(body_text(response).await, "missing or invalid CSRF token");
+
+        let response = f
+            .send(
+                f.update(&f.alice)
+                    .body(Body::from("body_text=x&csrf=0123"))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // Another session's token won't do.
+        let bob = f.login("bob", "bob's password").await;
+        let token = f.token(&bob).await;
+        let response = f
+            .send(
+                f.update(&f.alice)
+                    .header("X-CSRF-Token", token)
+                    .body(Body::from("body_text=x"))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn accepts_the_token_from_the_page() {
+        let f = Fixture::new("csrf-page").await;
+        let token = f.token(&f.alice).await;
+        let body = format!("body_text=x&csrf={token}");
+        let response = f
+            .send(f.update(&f.alice).body(Body::from(body)).unwrap())
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let response = f
+            .send(
+                f.update(&f.alice)
+                    .header("X-CSRF-Token", &token)
+                    .body(Body::from("body_text=y"))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    }
+
+    #[tokio::test]
+    async fn refuses_posts_from_other_origins() {
+        let f = Fixture::new("origin").await;
+        let token = f.token(&f.alice).await;
+        let post = |header, value: &str| {
+            f.update(&f.alice)
+                .header(header, value)
+                .header("X-CSRF-Token", &token)
+                .body(Body::from("body_text=x"))
+                .unwrap()
+        };
+        for (header, value) in [
+            (ORIGIN, "https://evil.example"),
+            (ORIGIN, "null"),
+            (REFERER, "https://evil.example/articles/1/"),
+            (REFERER, "https://localhost.evil.example/"),
+        ] {
+            let response = f.send(post(header.clone(), value)).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::FORBIDDEN,
+                "{header}: {value}"
+            );
+            assert_eq!(body_text(response).await, "cross-origin request refused");
+        }
+        for (header, value) in [
+            (ORIGIN, "http://localhost"),
+            (REFERER, "http://localhost/articles/1/"),
+        ] {
+            let response = f.send(post(header.clone(), value)).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::SEE_OTHER,
+                "{header}: {value}"
+            );
+        }
+
+        // Even the login form.
+        let response = f
+            .send(
+                Request::post("/login")
+                    .header(HOST, "localhost")
+                    .header(ORIGIN, "https://evil.example")
+                    .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from("name=alice&password=alice%27s+password"))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn accepts_posts_from_the_base_url() {
+        let options = Options {
+            base_url: Some("https://reading.example/".to_owned()),
+            ..Options::default()
+        };
+        let f = Fixture::with_options("origin-base", options).await;
+        let token = f.token(&f.alice).await;
+        let post = |host: Option<&str>, origin: &str| {
+            let mut request = Request::post(format!("/articles/{}/", f.article))
+                .header(COOKIE, format!("session={}", f.alice))
+                .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .header("X-CSRF-Token", &token)
+                .header(ORIGIN, origin);
+            if let Some(host) = host {
+                request = request.header(HOST, host);
+            }
+            request.body(Body::from("body_text=x")).unwrap()
+        };
+        // Behind a proxy that passes on its own Host.
+        for host in [Some("127.0.0.1:3000"), None] {
+            let response = f.send(post(host, "https://reading.example")).await;
+            assert_eq!(response.status(), StatusCode::SEE_OTHER, "{host:?}");
+        }
+        for (host, origin) in [
+            (None, "https://evil.example"),
+            (None, "http://reading.example"),
+            (Some("127.0.0.1:3000"), "https://evil.example"),
+        ] {
+            let response = f.send(post(host, origin)).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::FORBIDDEN,
+                "{host:?}: {origin}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_out_only_with_the_session_token() {
+        let f = Fixture::new("logout").await;
+        let token = f.token(&f.alice).await;
+        let logout = |token: Option<&str>| {
+            let mut request = Request::post("/logout")
+                .header(HOST, "localhost")
+                .header(COOKIE, format!("session={}", f.alice));
+            if let Some(token) = token {
+                request = request.header("X-CSRF-Token", token);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+        let response = f.send(logout(None)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        // Still logged in.
+        assert_eq!(f.token(&f.alice).await, token);
+
+        let response = f.send(logout(Some(&token))).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let response = f
+            .send(
+                Request::get("/articles/")
+                    .header(COOKIE, format!("session={}", f.alice))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert_ne!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn refuses_other_users_articles_unless_shared() {
+        let f = Fixture::new("sharing").await;
+        let bob = f.login("bob", "bob's password").await;
+        let bob_token = f.token(&bob).await;
+        let alice_token = f.token(&f.alice).await;
+        let read = |session: &str| {
+            Request::get(format!("/articles/{}/", f.article))
+                .header(COOKIE, format!("session={session}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let write = || {
+            f.update(&bob)
+                .header("X-CSRF-Token", &bob_token)
+                .body(Body::from("body_text=Bob+was+here"))
+                .unwrap()
+        };
+        let sharing = |action: &str, session: &str, token: &str| {
+            Request::post(format!("/articles/{}/{action}", f.article))
+                .header(HOST, "localhost")
+                .header(COOKIE, format!("session={session}"))
+                .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .header("X-CSRF-Token", token)
+                .body(Body::from("name=bob"))
+                .unwrap()
+        };
+
+        assert_eq!(f.send(read(&bob)).await.status(), StatusCode::NOT_FOUND);
+        assert_eq!(f.send(write()).await.status(), StatusCode::NOT_FOUND);
+        // Only the owner can share it.
+        let response = f.send(sharing("share", &bob, &bob_token)).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(f.send(read(&bob)).await.status(), StatusCode::NOT_FOUND);
+
+        let response = f.send(sharing("share", &f.alice, &alice_token)).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let response = f.send(read(&bob)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_text(response).await.contains("https://example.com/a"));
+        // Sharing lets them read it, not edit it.
+        assert_eq!(f.send(write()).await.status(), StatusCode::NOT_FOUND);
+        let page = body_text(f.send(read(&f.alice)).await).await;
+        assert!(!page.contains("Bob was here"));
+
+        let response = f.send(sharing("unshare", &f.alice, &alice_token)).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(f.send(read(&bob)).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn serves_health_checks_to_anyone_and_metrics_to_administrators() {
+        let f = Fixture::new("health").await;
+        let get = |path: &str, session: Option<&str>| {
+            let request = Request::get(path);
+            match session {
+                Some(session) => request.header(COOKIE, format!("session={session}")),
+                None => request,
+            }
+            .body(Body::empty())
+            .unwrap()
+        };
+        for path in ["/healthz", "/readyz"] {
+            let response = f.send(get(path, None)).await;
+            assert_eq!(response.status(), StatusCode::OK, "{path}");
+            assert_eq!(body_text(response).await, "ok\n");
+        }
+        let response = f.send(get("/metrics", None)).await;
+        assert_ne!(response.status(), StatusCode::OK);
+        let bob = f.login("bob", "bob's password").await;
+        let response = f.send(get("/metrics", Some(&bob))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        // The first account is an administrator.
+        let response = f.send(get("/metrics", Some(&f.alice))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
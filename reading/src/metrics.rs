@@ -0,0 +1,295 @@
+//! Health checks, and metrics in the Prometheus text format.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::Path,
+    sync::{Arc, LazyLock, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use rusqlite::Connection;
+
+use crate::ReadState;
+
+/// Upper bounds of the histogram buckets, in seconds.
+const BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0,
+];
+
+static METRICS: LazyLock<Mutex<Metrics>> = LazyLock::new(Default::default);
+
+#[derive(Default)]
+struct Metrics {
+    /// Requests handled, by route, method, and status.
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// Request latency by route.
+    request_seconds: BTreeMap<String, Histogram>,
+    scan_seconds: Histogram,
+    scan_files: u64,
+    scan_entries: u64,
+    scan_errors: u64,
+    lock_wait_seconds: Histogram,
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations in each of [BUCKETS], not cumulative.
+    counts: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let value = value.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|&le| value <= le) {
+            self.counts[i] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Write the histogram's samples; `labels` are any others, each followed by a comma.
+    fn write(&self, out: &mut String, name: &str, labels: &str) -> std::fmt::Result {
+        let mut cumulative = 0;
+        for (le, count) in BUCKETS.iter().zip(self.counts) {
+            cumulative += count;
+            writeln!(out, "{name}_bucket{{{labels}le=\"{le}\"}} {cumulative}")?;
+        }
+        writeln!(out, "{name}_bucket{{{labels}le=\"+Inf\"}} {}", self.count)?;
+        let labels = match labels.trim_end_matches(',') {
+            "" => String::new(),
+            v => format!("{{{v}}}"),
+        };
+        writeln!(out, "{name}_sum{labels} {}", self.sum)?;
+        writeln!(out, "{name}_count{labels} {}", self.count)
+    }
+}
+
+fn metrics() -> MutexGuard<'static, Metrics> {
+    METRICS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Lock the server's state, recording how long that took.
+pub(crate) fn lock<S>(server: &Mutex<S>) -> MutexGuard<'_, S> {
+    let start = Instant::now();
+    let guard = server.lock().unwrap();
+    metrics().lock_wait_seconds.observe(start.elapsed());
+    guard
+}
+
+/// Record a scan of a journal.
+pub(crate) fn record_scan(duration: Duration, files: usize, entries: usize, errors: usize) {
+    let mut m = metrics();
+    m.scan_seconds.observe(duration);
+    m.scan_files += files as u64;
+    m.scan_entries += entries as u64;
+    m.scan_errors += errors as u64;
+}
+
+/// Middleware: count requests and their latency, by route.
+pub(crate) async fn track(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_owned());
+    let method = request.method().to_string();
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = start.elapsed();
+    let mut m = metrics();
+    *m.requests
+        .entry((route.clone(), method, response.status().as_u16()))
+        .or_default() += 1;
+    m.request_seconds.entry(route).or_default().observe(elapsed);
+    response
+}
+
+/// State of a server with a database, which the health and metrics routes look into.
+pub(crate) trait Monitored: Send + 'static {
+    fn conn(&self) -> &Connection;
+    /// Directories the server needs, by the setting that names them.
+    fn dirs(&self) -> Vec<(&'static str, &Path)>;
+}
+
+/// Routes for `/healthz` and `/readyz`, for probes that can't log in.
+/// They tell only whether the server is well, not why not.
+pub(crate) fn health_routes<S: Monitored>() -> Router<Arc<Mutex<S>>> {
+    Router::new()
+        .route("/healthz", get(healthz::<S>))
+        .route("/readyz", get(readyz::<S>))
+}
+
+/// Route for `/metrics`.
+/// The metrics tell about the whole server, so should only be served to administrators.
+pub(crate) fn routes<S: Monitored>() -> Router<Arc<Mutex<S>>> {
+    Router::new().route("/metrics", get(render::<S>))
+}
+
+fn ping(conn: &Connection) -> rusqlite::Result<()> {
+    conn.query_row("SELECT 1", [], |_| Ok(()))
+}
+
+/// Whether the server is running and its database responds.
+async fn healthz<S: Monitored>(State(s): State<Arc<Mutex<S>>>) -> Response {
+    match ping(lock(&s).conn()) {
+        Ok(()) => "ok\n".into_response(),
+        Err(e) => {
+            tracing::error!("health check failed: {e}");
+            (StatusCode::SERVICE_UNAVAILABLE, "database error\n").into_response()
+        }
+    }
+}
+
+/// Whether the server can do its work: its database responds, and the directories it
+/// reads and writes exist.
+async fn readyz<S: Monitored>(State(s): State<Arc<Mutex<S>>>) -> Response {
+    let s = lock(&s);
+    let mut problems = Vec::new();
+    if let Err(e) = ping(s.conn()) {
+        tracing::error!("readiness check failed: {e}");
+        problems.push("database error".to_owned());
+    }
+    for (name, dir) in s.dirs() {
+        if !dir.is_dir() {
+            tracing::error!(
+                "readiness check failed: {} is not a directory",
+                dir.display()
+            );
+            problems.push(format!("{name} is not a directory"));
+        }
+    }
+    if problems.is_empty() {
+        "ok\n".into_response()
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, problems.join("\n") + "\n").into_response()
+    }
+}
+
+async fn render<S: Monitored>(State(s): State<Arc<Mutex<S>>>) -> Response {
+    let articles = {
+        let s = lock(&s);
+        count_articles(s.conn())
+    };
+    let result = articles
+        .map_err(|e| e.to_string())
+        .and_then(|articles| write_metrics(&articles).map_err(|e| e.to_string()));
+    match result {
+        Ok(body) => (
+            [(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+            body,
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("unexpected error: {e}"),
+        )
+            .into_response(),
+    }
+}
+
+/// Number of articles in each state, across all users.
+fn count_articles(conn: &Connection) -> rusqlite::Result<BTreeMap<String, u64>> {
+    let mut counts: BTreeMap<String, u64> = ReadState::ALL
+        .into_iter()
+        .map(|state| (state.as_str().to_owned(), 0))
+        .collect();
+    let mut st = conn.prepare("SELECT state, COUNT(*) FROM reading_list GROUP BY state")?;
+    for row in st.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+        let (state, count): (String, u64) = row?;
+        counts.insert(state, count);
+    }
+    Ok(counts)
+}
+
+fn write_metrics(articles: &BTreeMap<String, u64>) -> Result<String, std::fmt::Error> {
+    let m = metrics();
+    let mut out = String::new();
+    let header = |out: &mut String, name: &str, kind: &str, help: &str| {
+        writeln!(out, "# HELP {name} {help}")?;
+        writeln!(out, "# TYPE {name} {kind}")
+    };
+
+    let name = "reading_http_requests_total";
+    header(&mut out, name, "counter", "Requests handled.")?;
+    for ((route, method, status), count) in &m.requests {
+        writeln!(
+            out,
+            "{name}{{route=\"{}\",method=\"{}\",status=\"{status}\"}} {count}",
+            escape(route),
+            escape(method)
+        )?;
+    }
+    let name = "reading_http_request_duration_seconds";
+    header(
+        &mut out,
+        name,
+        "histogram",
+        "Time taken to handle requests.",
+    )?;
+    for (route, histogram) in &m.request_seconds {
+        histogram.write(&mut out, name, &format!("route=\"{}\",", escape(route)))?;
+    }
+
+    let name = "reading_scan_duration_seconds";
+    header(&mut out, name, "histogram", "Time taken to scan journals.")?;
+    m.scan_seconds.write(&mut out, name, "")?;
+    for (name, help, value) in [
+        (
+            "reading_scan_files_total",
+            "Journal files scanned.",
+            m.scan_files,
+        ),
+        (
+            "reading_scan_entries_total",
+            "Entries found in journals.",
+            m.scan_entries,
+        ),
+        (
+            "reading_scan_errors_total",
+            "Errors scanning journals.",
+            m.scan_errors,
+        ),
+    ] {
+        header(&mut out, name, "counter", help)?;
+        writeln!(out, "{name} {value}")?;
+    }
+
+    let name = "reading_articles";
+    header(
+        &mut out,
+        name,
+        "gauge",
+        "Articles in reading lists, by state.",
+    )?;
+    for (state, count) in articles {
+        writeln!(out, "{name}{{state=\"{}\"}} {count}", escape(state))?;
+    }
+
+    let name = "reading_db_lock_wait_seconds";
+    header(
+        &mut out,
+        name,
+        "histogram",
+        "Time spent waiting for the database.",
+    )?;
+    m.lock_wait_seconds.write(&mut out, name, "")?;
+    Ok(out)
+}
+
+/// Escape a label value.
+fn escape(s: &str) -> String {
+    s.replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
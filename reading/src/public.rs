@@ -0,0 +1,260 @@
+//! Read-only site of published roundups, suitable for exposing to the internet.
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{Path, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    middleware,
+    response::IntoResponse,
+    routing::get,
+};
+use chrono::NaiveDate;
+use maud::{Markup, PreEscaped, DOCTYPE};
+use rusqlite::{named_params, Connection, OptionalExtension};
+
+use roundup::identifier::page_url;
+
+use crate::{
+    css, destruct_entry, entry_html, export::Roundup, feed, metrics, no_feeds, Error, Options,
+};
+
+/// Serve the published roundups from the database, without any editing routes.
+pub fn public<P: AsRef<std::path::Path>>(db: P, options: Options) -> Result<axum::Router, Error> {
+    let conn = crate::open(db)?;
+    let s = Arc::new(Mutex::new(PublicServer { conn, options }));
+    Ok(axum::Router::new()
+        .route("/", get(index))
+        .route("/users/:name/roundups/:date/", get(render_roundup))
+        .route("/articles/:id/", get(render_article))
+        .route("/feed.atom", get(atom_feed))
+        .route("/feed.rss", get(rss_feed))
+        .route("/style.css", get(css))
+        .layer(middleware::from_fn(metrics::track))
+        .with_state(s))
+}
+
+struct PublicServer {
+    conn: Connection,
+    options: Options,
+}
+
+/// Wrap the content in a complete page.
+/// `depth` is the number of path components below the root of the site.
+fn page(title: &str, depth: usize, content: Markup) -> Markup {
+    let mut prefix = PathBuf::new();
+    for _ in 0..depth {
+        prefix.push("..");
+    }
+    let prefix = prefix.display();
+    maud::html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                title { (title) }
+                link rel="stylesheet" href=(format!("{prefix}/style.css"));
+                link rel="alternate" type="application/atom+xml" title="Reading Roundup" href=(format!("{prefix}/feed.atom"));
+            }
+            body {
+                header { nav { ul class="menu" {
+                    li { a href=(format!("{prefix}/")) { "Reading Roundup" } }
+                    li { a href=(format!("{prefix}/feed.atom")) { "Feed" } }
+                } } }
+                main { (content) }
+            }
+        }
+    }
+}
+
+fn not_found() -> axum::response::Response {
+    (
+        StatusCode::NOT_FOUND,
+        page(
+            "Not found",
+            0,
+            maud::html! { p { "Nothing published here." } },
+        ),
+    )
+        .into_response()
+}
+
+fn internal_error(e: Error) -> axum::response::Response {
+    tracing::error!("error in rendering public page: {e}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        page("Error", 0, maud::html! { p { "Something went wrong." } }),
+    )
+        .into_response()
+}
+
+async fn index(State(server): State<Arc<Mutex<PublicServer>>>) -> impl IntoResponse {
+    let server = metrics::lock(&server);
+    match server.index() {
+        Ok(v) => v.into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+async fn render_roundup(
+    State(server): State<Arc<Mutex<PublicServer>>>,
+    Path((name, date)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let Ok(date) = date.parse() else {
+        return not_found();
+    };
+    let server = metrics::lock(&server);
+    match server.render_roundup(&name, date) {
+        Ok(Some(v)) => v.into_response(),
+        Ok(None) => not_found(),
+        Err(e) => internal_error(e),
+    }
+}
+
+async fn render_article(
+    State(server): State<Arc<Mutex<PublicServer>>>,
+    Path(id): Path<isize>,
+) -> impl IntoResponse {
+    let server = metrics::lock(&server);
+    match server.render_article(id) {
+        Ok(Some(v)) => v.into_response(),
+        Ok(None) => not_found(),
+        Err(e) => internal_error(e),
+    }
+}
+
+async fn atom_feed(State(server): State<Arc<Mutex<PublicServer>>>) -> impl IntoResponse {
+    let server = metrics::lock(&server);
+    let Some(base) = server.options.base_url.as_deref() else {
+        return no_feeds();
+    };
+    match feed::atom(&server.conn, base, &server.options.export.book_url) {
+        Ok(v) => (StatusCode::OK, [(CONTENT_TYPE, "application/atom+xml")], v).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+async fn rss_feed(State(server): State<Arc<Mutex<PublicServer>>>) -> impl IntoResponse {
+    let server = metrics::lock(&server);
+    let Some(base) = server.options.base_url.as_deref() else {
+        return no_feeds();
+    };
+    match feed::rss(&server.conn, base, &server.options.export.book_url) {
+        Ok(v) => (StatusCode::OK, [(CONTENT_TYPE, "application/rss+xml")], v).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+impl PublicServer {
+    fn index(&self) -> Result<Markup, Error> {
+        let published = feed::published(&self.conn, None)?;
+        Ok(page(
+            "Reading Roundup",
+            0,
+            maud::html! {
+                h1 { "Reading Roundup" }
+                @if published.is_empty() {
+                    p { "Nothing published yet." }
+                }
+                @for p in &published {
+                    div class="summary" {
+                        h3 {
+                            a href=(format!("users/{}/roundups/{}/", p.author, p.roundup.date)) { (p.roundup.title()) }
+                        }
+                        p { (format!("{} articles, by {}", p.roundup.articles.len(), p.author)) }
+                    }
+                }
+            },
+        ))
+    }
+
+    /// ID of the user, if they published a roundup on the date.
+    fn publisher(&self, name: &str, date: NaiveDate) -> Result<Option<isize>, Error> {
+        Ok(self
+            .conn
+            .query_row(
+                r#"
+                SELECT users.id FROM publications JOIN users ON users.id = publications.owner
+                WHERE users.name = :name AND date = :date
+                "#,
+                named_params! {":name": name, ":date": format!("{date}")},
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn render_roundup(&self, name: &str, date: NaiveDate) -> Result<Option<Markup>, Error> {
+        let Some(owner) = self.publisher(name, date)? else {
+            return Ok(None);
+        };
+        let roundup = Roundup::load_published(&self.conn, Some(owner), date)?;
+        let title = roundup.title();
+        Ok(Some(page(
+            &title,
+            4,
+            maud::html! {
+                h1 { (title) }
+                p { "By " (name) }
+                @for article in &roundup.articles {
+                    div class="summary" {
+                        (PreEscaped(entry_html(&article.entry, &self.options.export.book_url)))
+                        p { a href=(format!("../../../../articles/{}/", article.id)) { "About this link" } }
+                    }
+                }
+            },
+        )))
+    }
+
+    fn render_article(&self, id: isize) -> Result<Option<Markup>, Error> {
+        let entry = self
+            .conn
+            .query_row(
+                r#"
+            SELECT published_articles.* FROM published_articles
+            JOIN users ON users.id = published_articles.owner
+            WHERE entry = :id ORDER BY date DESC LIMIT 1
+            "#,
+                named_params! {":id": id},
+                destruct_entry,
+            )
+            .optional()?;
+        let Some(entry) = entry else {
+            return Ok(None);
+        };
+        let roundups: Result<Vec<(String, String)>, _> = self
+            .conn
+            .prepare(
+                r#"
+            SELECT users.name, date
+            FROM published_articles JOIN users ON users.id = published_articles.owner
+            WHERE entry = :id ORDER BY date ASC, users.name ASC
+            "#,
+            )?
+            .query_map(named_params! {":id": id}, |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect();
+        let roundups = roundups?;
+        let book_url = &self.options.export.book_url;
+        Ok(Some(page(
+            &entry.url.to_string(),
+            2,
+            maud::html! {
+                h1 { a href=(page_url(&entry.url, book_url)) { (entry.url) } }
+                div class="summary" {
+                    (PreEscaped(entry_html(&entry, book_url)))
+                }
+                p { "Noted " (entry.source_date) "; included in:" }
+                ul {
+                    @for (name, date) in &roundups {
+                        li { a href=(format!("../../users/{name}/roundups/{date}/")) { "Reading Roundup, " (date) ", by " (name) } }
+                    }
+                }
+            },
+        )))
+    }
+}
@@ -0,0 +1,290 @@
+//! Publishing roundups, optionally into a static site's content directory.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use chrono::NaiveDate;
+use rusqlite::{named_params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    auth::User,
+    export::{ExportOptions, Format, Roundup},
+    Error,
+};
+
+/// Settings for publishing roundups.
+#[derive(Debug, Clone)]
+pub struct PublishOptions {
+    /// Content directory to write roundups into.
+    pub dir: PathBuf,
+    /// Write each roundup as a page bundle, `{date}/index.md`, instead of `{date}.md`.
+    pub bundle: bool,
+    /// Path of each roundup within `dir`, with `{user}` and `{date}` replaced,
+    /// e.g. `{user}/{date}.md` to keep each user's roundups apart.
+    /// If unset, the path is chosen by `bundle`.
+    pub path: Option<String>,
+    /// Commit each published file to the git repository containing `dir`.
+    pub git_commit: bool,
+}
+
+impl PublishOptions {
+    /// Path the user's roundup for the given date is published to.
+    pub fn path(&self, user: &str, date: NaiveDate) -> PathBuf {
+        let template = match (&self.path, self.bundle) {
+            (Some(path), _) => path,
+            (None, true) => "{date}/index.md",
+            (None, false) => "{date}.md",
+        };
+        self.dir.join(
+            template
+                .replace("{user}", user)
+                .replace("{date}", &format!("{date}")),
+        )
+    }
+}
+
+/// Record of the last time a roundup was published.
+#[derive(Debug)]
+pub struct Publication {
+    pub date: NaiveDate,
+    /// YYYY-MM-DD HH:MM:SS (UTC)
+    pub published_at: String,
+    /// Where the roundup was written, if anywhere.
+    pub path: Option<PathBuf>,
+    /// SHA-256 of the published file.
+    pub digest: Option<String>,
+}
+
+/// State of the published file on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    /// No file at the publication path.
+    Absent,
+    /// The file is as we last published it.
+    Unchanged,
+    /// The file was changed, or created, by someone else.
+    Edited,
+}
+
+fn digest(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Get the record of the last publication of the owner's roundup, if any.
+pub fn last_publication(
+    conn: &Connection,
+    owner: Option<isize>,
+    date: NaiveDate,
+) -> Result<Option<Publication>, Error> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM publications WHERE owner IS :owner AND date = :date",
+            named_params! {":owner": owner, ":date": format!("{date}")},
+            |row| {
+                Ok(Publication {
+                    date,
+                    published_at: row.get("published_at")?,
+                    path: row.get::<_, Option<String>>("path")?.map(PathBuf::from),
+                    digest: row.get("digest")?,
+                })
+            },
+        )
+        .optional()?)
+}
+
+/// Check whether the published file has changed since the last publication.
+pub fn file_status(path: &Path, last: Option<&Publication>) -> Result<FileStatus, Error> {
+    let content = match std::fs::read(path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(FileStatus::Absent),
+        Err(e) => return Err(e.into()),
+    };
+    match last {
+        Some(last)
+            if last.path.as_deref() == Some(path)
+                && last.digest.as_deref() == Some(&digest(&content)) =>
+        {
+            Ok(FileStatus::Unchanged)
+        }
+        _ => Ok(FileStatus::Edited),
+    }
+}
+
+/// Mark the user's roundup as published,
+/// and write it into the content directory if one is configured.
+///
+/// Refuses to overwrite a file that was edited since the last publication, unless `force` is set.
+pub fn publish(
+    conn: &Connection,
+    user: &User,
+    date: NaiveDate,
+    export: &ExportOptions,
+    options: Option<&PublishOptions>,
+    force: bool,
+) -> Result<Publication, Error> {
+    let (path, digest) = match options {
+        Some(options) => {
+            let (path, digest) = write(conn, user, date, export, options, force)?;
+            (Some(path), Some(digest))
+        }
+        None => (None, None),
+    };
+    let tx = conn.unchecked_transaction()?;
+    let published_at: String = tx.query_row(
+        r#"
+        INSERT INTO publications (owner, date, path, digest) VALUES (:owner, :date, :path, :digest)
+        ON CONFLICT (COALESCE(owner, 0), date) DO UPDATE SET
+            published_at = excluded.published_at,
+            path = excluded.path,
+            digest = excluded.digest
+        RETURNING published_at
+        "#,
+        named_params! {
+            ":owner": user.id,
+            ":date": format!("{date}"),
+            ":path": path.as_ref().map(|p| p.to_string_lossy()),
+            ":digest": &digest,
+        },
+        |row| row.get(0),
+    )?;
+    let params = named_params! {":owner": user.id, ":date": format!("{date}")};
+    tx.execute(
+        "DELETE FROM published_articles WHERE owner IS :owner AND date = :date",
+        params,
+    )?;
+    tx.execute(
+        r#"
+        INSERT INTO published_articles
+            (owner, date, entry, url, source_date, original_text, body_text, state, kind)
+        SELECT :owner, :date, reading_list.id, url, source_date, original_text, body_text,
+            state, kind
+        FROM roundup_contents JOIN reading_list ON reading_list.id = roundup_contents.entry
+        WHERE roundup_contents.owner IS :owner AND roundup_contents.date = :date
+        "#,
+        params,
+    )?;
+    tx.commit()?;
+
+    if let (Some(options), Some(path)) = (options, &path) {
+        if options.git_commit {
+            git_commit(&options.dir, path, date)?;
+        }
+    }
+
+    Ok(Publication {
+        date,
+        published_at,
+        path,
+        digest,
+    })
+}
+
+/// Write the user's roundup into the content directory, returning its path and digest.
+fn write(
+    conn: &Connection,
+    user: &User,
+    date: NaiveDate,
+    export: &ExportOptions,
+    options: &PublishOptions,
+    force: bool,
+) -> Result<(PathBuf, String), Error> {
+    let path = options.path(&user.name, date);
+    let last = last_publication(conn, Some(user.id), date)?;
+    if !force && file_status(&path, last.as_ref())? == FileStatus::Edited {
+        return Err(Error::PublishConflict(path));
+    }
+
+    let content = Roundup::load(conn, Some(user.id), date)?.render(Format::Markdown, export)?;
+    let dir = path.parent().expect("publication path has a parent");
+    std::fs::create_dir_all(dir)?;
+    // Write to a temporary file in the same directory, then move it into place,
+    // so the site never sees a partial file.
+    let tmp = dir.join(format!(".{date}.md.tmp"));
+    {
+        let mut f = std::fs::File::create(&tmp)?;
+        f.write_all(content.as_bytes())?;
+        f.sync_all()?;
+    }
+    std::fs::rename(&tmp, &path)?;
+    Ok((path, digest(content.as_bytes())))
+}
+
+/// Commit the published file to the git repository containing `dir`.
+fn git_commit(dir: &Path, path: &Path, date: NaiveDate) -> Result<(), Error> {
+    // git runs in `dir`, so name the file relative to it.
+    let path = path.strip_prefix(dir).unwrap_or(path);
+    let run = |args: &[&std::ffi::OsStr]| -> Result<(), Error> {
+        let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(Error::GitError(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ))
+        }
+    };
+    run(&["add".as_ref(), "--".as_ref(), path.as_os_str()])?;
+    // Nothing to commit if the file didn't change.
+    let unchanged = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["diff", "--cached", "--quiet", "--"])
+        .arg(path)
+        .status()?
+        .success();
+    if unchanged {
+        return Ok(());
+    }
+    let message = format!("Reading roundup, {date}");
+    run(&[
+        "commit".as_ref(),
+        "-m".as_ref(),
+        message.as_ref(),
+        "--".as_ref(),
+        path.as_os_str(),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(bundle: bool, path: Option<&str>) -> PublishOptions {
+        PublishOptions {
+            dir: PathBuf::from("site/content/reading"),
+            bundle,
+            path: path.map(str::to_owned),
+            git_commit: false,
+        }
+    }
+
+    #[test]
+    fn publishes_to_hugo_content_paths() {
+        let date = NaiveDate::from_ymd_opt(2024, 9, 1).unwrap();
+        assert_eq!(
+            options(false, None).path("alice", date),
+            Path::new("site/content/reading/2024-09-01.md")
+        );
+        assert_eq!(
+            options(true, None).path("alice", date),
+            Path::new("site/content/reading/2024-09-01/index.md")
+        );
+    }
+
+    #[test]
+    fn publishes_by_user_with_a_template() {
+        let date = NaiveDate::from_ymd_opt(2024, 9, 1).unwrap();
+        assert_eq!(
+            options(false, Some("{user}/{date}.md")).path("alice", date),
+            Path::new("site/content/reading/alice/2024-09-01.md")
+        );
+        assert_eq!(
+            options(true, Some("{user}/{date}/index.md")).path("bob", date),
+            Path::new("site/content/reading/bob/2024-09-01/index.md")
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! Adding an article from a bookmarklet or the system share sheet.
+//!
+//! Articles added this way are written to the journal, as a line in today's file,
+//! and then imported from there, so the journal stays the record of what was read.
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{Extension, Query, State},
+    http::{
+        header::{CONTENT_TYPE, HOST, LOCATION},
+        HeaderMap, StatusCode, Uri,
+    },
+    response::IntoResponse,
+};
+use axum_extra::extract::Form;
+use roundup::JournalFormat;
+use rusqlite::named_params;
+
+use crate::{auth::User, insert_entries, metrics, nav, Error, ReadState, ReadingListEntry, Server};
+
+/// The article to add, from the query or form.
+struct Addition {
+    url: String,
+    title: String,
+    note: String,
+    state: ReadState,
+}
+
+impl Addition {
+    fn from_fields(fields: &HashMap<String, String>) -> Result<Addition, String> {
+        let field = |name: &str| fields.get(name).map_or("", |v| v.trim()).to_owned();
+        let mut url = field("url");
+        let mut note = field("note");
+        // Share sheets often send the link as the text.
+        if url.is_empty() {
+            if let Some(found) = note.split_whitespace().find(|w| w.starts_with("http")) {
+                url = found.to_owned();
+                note = one_line(&note.replacen(found, "", 1));
+            }
+        }
+        let state = match fields.get("state").map(|v| v.trim()) {
+            None | Some("") => ReadState::ToRead,
+            Some(v) => ReadState::from_tag(v)
+                .or_else(|| v.parse().ok())
+                .filter(|state| state.tag().is_some())
+                .ok_or_else(|| format!("not a journal state: {v:?}"))?,
+        };
+        Ok(Addition {
+            url,
+            title: field("title"),
+            note,
+            state,
+        })
+    }
+
+    /// Line for a journal file in the format, e.g. `- #tbr [Title](https://example.com/) — note`
+    /// in Markdown.
+    fn journal_line(&self, format: JournalFormat) -> Result<String, String> {
+        let url: Uri = self
+            .url
+            .parse()
+            .map_err(|e| format!("invalid URL {:?}: {e}", self.url))?;
+        if !matches!(url.scheme_str(), Some("http" | "https")) {
+            return Err(format!("not a web page: {url}"));
+        }
+        let title = one_line(&self.title);
+        let title = if title.is_empty() {
+            url.to_string()
+        } else {
+            title
+        };
+        let tag = self.state.tag().unwrap_or("tbr");
+        Ok(format.entry_line(tag, &title, &url.to_string(), &one_line(&self.note)))
+    }
+}
+
+/// Journal files are read a line at a time.
+fn one_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Show the form for adding an article, filled in from the query,
+/// and a bookmarklet that opens it for the page being viewed.
+pub(crate) async fn add_form(
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let addition = match Addition::from_fields(&query) {
+        Ok(v) => v,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let bookmarklet = bookmarklet(&editor_url(&headers));
+    maud::html!(
+        head {
+            link rel="stylesheet" href="/style.css";
+            link rel="manifest" href="/manifest.webmanifest";
+            meta name="viewport" content="width=device-width";
+        }
+        body { (nav(0)) main {
+            h2 { "Add an article" }
+            form method="POST" action="/add" {
+                p {
+                    label for="url" { "URL " }
+                    input type="url" id="url" name="url" required value=(addition.url);
+                }
+                p {
+                    label for="title" { "Title " }
+                    input type="text" id="title" name="title" value=(addition.title);
+                }
+                p {
+                    label for="note" { "Note " }
+                    textarea id="note" name="note" class="narrow" { (addition.note) }
+                }
+                div class="controls" {
+                    select name="state" {
+                        @for state in ReadState::ALL.into_iter().filter(|s| s.tag().is_some()) {
+                            option value=(state.tag().unwrap_or_default())
+                                selected?[state == addition.state]
+                                { (state.label()) }
+                        }
+                    }
+                    button type="submit" { "Add to journal" }
+                }
+            }
+            p {
+                "Drag this to the bookmarks bar to add the page you're reading: "
+                a href=(bookmarklet) { "Add to reading list" }
+            }
+        } }
+    )
+    .into_response()
+}
+
+/// Append the article to today's journal file, then import it from there.
+pub(crate) async fn add(
+    State(server): State<Arc<Mutex<Server>>>,
+    Extension(user): Extension<User>,
+    Form(form): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let today = chrono::Local::now().date_naive();
+    let addition = match Addition::from_fields(&form) {
+        Ok(v) => v,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let mut server = metrics::lock(&server);
+    let Some(dir) = user.journal.clone().or(server.sources.clone()) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("no journal is configured for {}", user.name),
+        )
+            .into_response();
+    };
+    let source = roundup::journal_source(&dir, &server.options.scan);
+    let Some(dir) = source.directory().map(Path::to_owned) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "can't add to {}: the journal is read from an archive or a git revision",
+                dir.display()
+            ),
+        )
+            .into_response();
+    };
+    let file = match roundup::journal_file(&dir, today, &server.options.scan) {
+        Ok(v) => v,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("unexpected error: {e}"),
+            )
+                .into_response()
+        }
+    };
+    // Read the line back as it will be scanned from the file.
+    let (line, entry) = match addition
+        .journal_line(JournalFormat::of(&file))
+        .and_then(|line| match roundup::scan_text(&file, &line) {
+            Ok(mut entries) if entries.len() == 1 => Ok((line, entries.remove(0))),
+            Ok(_) => Err(format!("not a journal entry: {line}")),
+            Err(e) => Err(format!("{e}")),
+        }) {
+        Ok(v) => v,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    match server.add_to_journal(&user, &file, &line, entry) {
+        Ok(id) => (
+            StatusCode::SEE_OTHER,
+            [(LOCATION, format!("/articles/{id}/"))],
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("unexpected error: {e}"),
+        )
+            .into_response(),
+    }
+}
+
+impl Server {
+    /// Add the line for the entry to the journal file, then import it.
+    /// Returns the ID of the article.
+    fn add_to_journal(
+        &mut self,
+        user: &User,
+        file: &Path,
+        line: &str,
+        entry: ReadingListEntry,
+    ) -> Result<isize, Error> {
+        roundup::append_to_file(file, line)?;
+        tracing::info!("added to {}: {line}", file.display());
+        let url = entry.url.to_string();
+        insert_entries(&mut self.conn, Some(user.id), &[entry])?;
+        Ok(self.conn.query_row(
+            "SELECT id FROM reading_list WHERE owner IS :owner AND url = :url",
+            named_params! {":owner": user.id, ":url": url},
+            |row| row.get(0),
+        )?)
+    }
+}
+
+/// Web app manifest, so that the editor can be a target of the system share sheet
+/// once installed.
+pub(crate) async fn manifest() -> impl IntoResponse {
+    let manifest = serde_json::json!({
+        "name": "Reading roundup",
+        "start_url": "/articles/",
+        "display": "browser",
+        "share_target": {
+            "action": "/add",
+            "method": "GET",
+            "params": { "url": "url", "title": "title", "text": "note" },
+        },
+    });
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, "application/manifest+json")],
+        manifest.to_string(),
+    )
+}
+
+/// Where the editor is, as reached by the request.
+fn editor_url(headers: &HeaderMap) -> String {
+    let host = headers
+        .get(HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("localhost");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("http");
+    format!("{scheme}://{host}")
+}
+
+/// A `javascript:` link that opens the form for the current page and selection.
+fn bookmarklet(editor: &str) -> String {
+    let editor = editor.replace(['\'', '"', '\\'], "");
+    format!(
+        "javascript:(function(){{var e=encodeURIComponent;\
+         location.href='{editor}/add?url='+e(location.href)+'&title='+e(document.title)\
+         +'&note='+e(String(getSelection()));}})()"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn takes_the_link_from_shared_text() {
+        let addition = Addition::from_fields(&fields(&[
+            ("title", "A post"),
+            ("note", "Look at https://example.com/a this"),
+        ]))
+        .unwrap();
+        assert_eq!(addition.url, "https://example.com/a");
+        assert_eq!(addition.note, "Look at this");
+        assert_eq!(addition.state, ReadState::ToRead);
+    }
+
+    #[test]
+    fn takes_states_by_tag_or_name() {
+        for (state, expected) in [
+            ("read", ReadState::Read),
+            ("skimmed", ReadState::Skimmed),
+            ("to-read", ReadState::ToRead),
+        ] {
+            let addition = Addition::from_fields(&fields(&[("state", state)])).unwrap();
+            assert_eq!(addition.state, expected, "{state}");
+        }
+        assert!(Addition::from_fields(&fields(&[("state", "unknown")])).is_err());
+        assert!(Addition::from_fields(&fields(&[("state", "later")])).is_err());
+    }
+
+    #[test]
+    fn writes_one_line_for_a_web_page() {
+        let addition = Addition::from_fields(&fields(&[
+            ("url", "https://example.com/a"),
+            ("title", "A\npost"),
+            ("note", "two\nlines"),
+            ("state", "read"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            addition.journal_line(JournalFormat::Markdown).unwrap(),
+            "- #read [A post](https://example.com/a) — two lines"
+        );
+
+        let addition = Addition::from_fields(&fields(&[("url", "https://example.com/")])).unwrap();
+        assert_eq!(
+            addition.journal_line(JournalFormat::Text).unwrap(),
+            "#tbr https://example.com/ https://example.com/"
+        );
+
+        let addition = Addition::from_fields(&fields(&[("url", "ftp://example.com/a")])).unwrap();
+        assert!(addition.journal_line(JournalFormat::Markdown).is_err());
+    }
+}
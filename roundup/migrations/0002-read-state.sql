@@ -0,0 +1,24 @@
+-- Replace the tri-state "read" column with a richer state,
+-- and keep a history of transitions between states.
+ALTER TABLE reading_list ADD COLUMN state TEXT NOT NULL DEFAULT 'unknown';
+UPDATE reading_list SET state = CASE read
+    WHEN 1 THEN 'read'
+    WHEN 0 THEN 'to-read'
+    ELSE 'unknown'
+END;
+
+CREATE TABLE IF NOT EXISTS state_transitions
+(   entry   INTEGER NOT NULL
+    -- State entered at this transition; never 'unknown'.
+,   state   TEXT    NOT NULL
+    -- YYYY-MM-DD for transitions from the journal;
+    -- YYYY-MM-DD HH:MM:SS (UTC) for transitions made in the editor.
+,   at      TEXT    NOT NULL    DEFAULT (datetime('now'))
+,   FOREIGN KEY (entry) REFERENCES reading_list(id)
+,   PRIMARY KEY (entry, state, at)
+);
+
+INSERT INTO state_transitions (entry, state, at)
+    SELECT id, state, source_date FROM reading_list WHERE state != 'unknown';
+
+ALTER TABLE reading_list DROP COLUMN read;
@@ -0,0 +1,10 @@
+-- Record of roundups written out to a static site.
+CREATE TABLE IF NOT EXISTS publications
+(   date            TEXT    PRIMARY KEY NOT NULL
+    -- YYYY-MM-DD HH:MM:SS (UTC)
+,   published_at    TEXT    NOT NULL    DEFAULT (datetime('now'))
+    -- Path the roundup was written to, if any
+,   path            TEXT
+    -- SHA-256 of the file as written, in hex
+,   digest          TEXT
+);
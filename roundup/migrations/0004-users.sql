@@ -0,0 +1,29 @@
+-- Accounts for the editor.
+CREATE TABLE IF NOT EXISTS users
+(   id              INTEGER PRIMARY KEY NOT NULL
+,   name            TEXT    UNIQUE      NOT NULL
+    -- Argon2 hash, in PHC string format
+,   password_hash   TEXT                NOT NULL
+,   created_at      TEXT                NOT NULL    DEFAULT (datetime('now'))
+);
+
+-- Logged-in browser sessions.
+CREATE TABLE IF NOT EXISTS sessions
+(   -- SHA-256 of the session cookie, in hex
+    token_hash  TEXT    PRIMARY KEY NOT NULL
+,   user        INTEGER             NOT NULL
+    -- YYYY-MM-DD HH:MM:SS (UTC)
+,   expires_at  TEXT                NOT NULL
+,   FOREIGN KEY (user) REFERENCES users(id)
+);
+
+-- Bearer tokens for scripts.
+CREATE TABLE IF NOT EXISTS api_tokens
+(   -- SHA-256 of the token, in hex
+    token_hash  TEXT    PRIMARY KEY NOT NULL
+,   user        INTEGER             NOT NULL
+    -- What the token is for
+,   label       TEXT                NOT NULL
+,   created_at  TEXT                NOT NULL    DEFAULT (datetime('now'))
+,   FOREIGN KEY (user) REFERENCES users(id)
+);
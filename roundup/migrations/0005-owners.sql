@@ -0,0 +1,67 @@
+-- Give each article, roundup, and publication an owner.
+-- Rows from before accounts have no owner (NULL) until the first user claims them.
+ALTER TABLE users ADD COLUMN journal TEXT;
+
+CREATE TABLE reading_list_new
+(   id              INTEGER PRIMARY KEY NOT NULL
+,   owner           INTEGER
+,   url             TEXT                NOT NULL
+    -- Journal date the key was pulled from; YYYY-MM-DD
+,   source_date     TEXT                NOT NULL    DEFAULT (date('now'))
+    -- Original line in journal, without sanitization
+,   original_text   TEXT
+    -- Text of the body, without tags etc.
+,   body_text       TEXT
+,   state           TEXT                NOT NULL    DEFAULT 'unknown'
+,   FOREIGN KEY (owner) REFERENCES users(id)
+);
+INSERT INTO reading_list_new (id, url, source_date, original_text, body_text, state)
+    SELECT id, url, source_date, original_text, body_text, state FROM reading_list;
+DROP TABLE reading_list;
+ALTER TABLE reading_list_new RENAME TO reading_list;
+-- Each user has their own copy of a link.
+CREATE UNIQUE INDEX reading_list_owner_url ON reading_list (COALESCE(owner, 0), url);
+
+CREATE TABLE roundup_contents_new
+(   owner   INTEGER
+,   date    TEXT    NOT NULL    DEFAULT (date('now'))
+,   entry   INTEGER
+,   FOREIGN KEY (owner) REFERENCES users(id)
+,   FOREIGN KEY (entry) REFERENCES reading_list(id)
+);
+INSERT INTO roundup_contents_new (date, entry) SELECT date, entry FROM roundup_contents;
+DROP TABLE roundup_contents;
+ALTER TABLE roundup_contents_new RENAME TO roundup_contents;
+CREATE UNIQUE INDEX roundup_contents_owner_date_entry
+    ON roundup_contents (COALESCE(owner, 0), date, entry);
+
+CREATE TABLE publications_new
+(   owner           INTEGER
+,   date            TEXT                NOT NULL
+    -- YYYY-MM-DD HH:MM:SS (UTC)
+,   published_at    TEXT                NOT NULL    DEFAULT (datetime('now'))
+    -- Path the roundup was written to, if any
+,   path            TEXT
+    -- SHA-256 of the file as written, in hex
+,   digest          TEXT
+,   FOREIGN KEY (owner) REFERENCES users(id)
+);
+INSERT INTO publications_new (date, published_at, path, digest)
+    SELECT date, published_at, path, digest FROM publications;
+DROP TABLE publications;
+ALTER TABLE publications_new RENAME TO publications;
+CREATE UNIQUE INDEX publications_owner_date ON publications (COALESCE(owner, 0), date);
+
+-- If there are already accounts, the first one takes over what came before.
+UPDATE reading_list SET owner = (SELECT MIN(id) FROM users);
+UPDATE roundup_contents SET owner = (SELECT MIN(id) FROM users);
+UPDATE publications SET owner = (SELECT MIN(id) FROM users);
+
+-- Articles their owners have shared with other users.
+CREATE TABLE IF NOT EXISTS article_shares
+(   entry   INTEGER NOT NULL
+,   user    INTEGER NOT NULL
+,   FOREIGN KEY (entry) REFERENCES reading_list(id)
+,   FOREIGN KEY (user) REFERENCES users(id)
+,   PRIMARY KEY (entry, user)
+);
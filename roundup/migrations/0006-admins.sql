@@ -0,0 +1,4 @@
+-- Administrators may download backups of the whole database.
+-- The first account is one.
+ALTER TABLE users ADD COLUMN admin INTEGER NOT NULL DEFAULT 0;
+UPDATE users SET admin = 1 WHERE id = (SELECT MIN(id) FROM users);
@@ -0,0 +1,3 @@
+-- What sort of thing an article is: 'article', 'video', 'podcast', 'paper', or 'book'.
+-- NULL unless set by a journal tag or by hand; it's then inferred from the URL.
+ALTER TABLE reading_list ADD COLUMN kind TEXT;
@@ -0,0 +1,27 @@
+-- Articles as they were when their roundup was last published, so the public site and
+-- feeds show what was published, rather than later edits, until it's published again.
+CREATE TABLE published_articles
+(   owner           INTEGER
+,   date            TEXT                NOT NULL
+,   entry           INTEGER             NOT NULL
+,   url             TEXT                NOT NULL
+    -- Journal date the key was pulled from; YYYY-MM-DD
+,   source_date     TEXT                NOT NULL
+,   original_text   TEXT
+,   body_text       TEXT
+,   state           TEXT                NOT NULL
+,   kind            TEXT
+,   FOREIGN KEY (owner) REFERENCES users(id)
+);
+CREATE UNIQUE INDEX published_articles_owner_date_entry
+    ON published_articles (COALESCE(owner, 0), date, entry);
+
+-- Roundups already published are taken as they are now.
+INSERT INTO published_articles
+    (owner, date, entry, url, source_date, original_text, body_text, state, kind)
+SELECT publications.owner, publications.date, reading_list.id, url, source_date,
+    original_text, body_text, state, kind
+FROM publications
+JOIN roundup_contents
+    ON roundup_contents.owner IS publications.owner AND roundup_contents.date = publications.date
+JOIN reading_list ON reading_list.id = roundup_contents.entry;
@@ -0,0 +1,5 @@
+-- Record when state transitions happened in one format, RFC 3339 in UTC
+-- (YYYY-MM-DDTHH:MM:SSZ), so that they sort by time. Transitions from the journal,
+-- which were dated only by their entries, happened at the start of the day.
+UPDATE OR REPLACE state_transitions SET at = at || 'T00:00:00Z' WHERE length(at) = 10;
+UPDATE OR REPLACE state_transitions SET at = replace(at, ' ', 'T') || 'Z' WHERE length(at) = 19;
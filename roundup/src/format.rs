@@ -0,0 +1,407 @@
+//! Kinds of journal file, chosen by extension. Each finds entries in its own syntax
+//! and turns their bodies into Markdown, so all of them give the same entries.
+
+use std::{borrow::Cow, ffi::OsStr, path::Path, sync::LazyLock};
+
+use chrono::NaiveDate;
+use regex_lite::Regex;
+
+use crate::{Kind, ReadState, ENTRY_REGEX};
+
+pub(crate) static URL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"https?://[^\s<>"]+"#).expect("invalid regex provided"));
+
+static ORG_LINK_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\[\[([^\]]+)\](?:\[([^\]]*)\])?\]").expect("invalid regex provided")
+});
+
+static ANCHOR_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)<a\s[^>]*?href\s*=\s*["']([^"']*)["'][^>]*>(.*?)</a\s*>"#)
+        .expect("invalid regex provided")
+});
+
+static HREF_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)<a\s[^>]*?href\s*=\s*["']([^"']*)["']"#).expect("invalid regex provided")
+});
+
+/// Elements whose content isn't part of the text of a page.
+static HIDDEN_HTML_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?is)<head\b.*?</head\s*>|<style\b.*?</style\s*>|<script\b.*?</script\s*>|<!--.*?-->",
+    )
+    .expect("invalid regex provided")
+});
+
+/// Tags that start or end a block of text.
+static BLOCK_TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)</?(?:p|div|br|li|ul|ol|tr|td|table|h[1-6]|blockquote|hr)\b[^>]*>")
+        .expect("invalid regex provided")
+});
+
+static ENTITY_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"&(?:#(\d+)|#[xX]([0-9a-fA-F]+)|(amp|lt|gt|quot|apos|nbsp));")
+        .expect("invalid regex provided")
+});
+
+static TAG_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<[^>]*>").expect("invalid regex provided"));
+
+static KIND_TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:^|\s)#(article|video|podcast|paper|book)\b").expect("invalid regex provided")
+});
+
+static ORG_DATE_HEADING_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\*+\s+[<\[]?(\d{4}-\d{2}-\d{2})").expect("invalid regex provided")
+});
+
+static ORG_TAGGED_HEADING_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\*+\s+(.*?)\s+:([\w@:]+):\s*$").expect("invalid regex provided")
+});
+
+/// Syntax of a journal file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalFormat {
+    /// Markdown, e.g. `- #tbr [Title](https://example.com/)`.
+    Markdown,
+    /// Org mode, e.g. `- #tbr [[https://example.com/][Title]]`, or a heading tagged
+    /// `:tbr:`. Entries are dated by the `* 2024-03-05` heading they're under,
+    /// or else by the file name.
+    Org,
+    /// Plain text with bare URLs, e.g. `#tbr Title https://example.com/`.
+    Text,
+}
+
+impl JournalFormat {
+    /// Format of the file at `path`: `.org` and `.txt` files are Org and text;
+    /// others are Markdown.
+    pub fn of(path: &Path) -> JournalFormat {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("org") => JournalFormat::Org,
+            Some("txt") => JournalFormat::Text,
+            _ => JournalFormat::Markdown,
+        }
+    }
+
+    /// Whether entries may be dated by headings, rather than only by the file name.
+    pub(crate) fn has_date_headings(&self) -> bool {
+        *self == JournalFormat::Org
+    }
+
+    /// Date of the entries following the line, if it's a heading with one.
+    pub(crate) fn heading_date(&self, line: &str) -> Option<NaiveDate> {
+        match self {
+            JournalFormat::Org => ORG_DATE_HEADING_REGEX
+                .captures(line)
+                .and_then(|c| c[1].parse().ok()),
+            _ => None,
+        }
+    }
+
+    /// The tag and body of the entry on the line, if there is one.
+    pub(crate) fn entry<'a>(&self, line: &'a str) -> Option<(&'a str, &'a str)> {
+        if let Some(captures) = ENTRY_REGEX.captures(line) {
+            let tag = captures
+                .get(1)
+                .expect("failed to retrieve non-optional capture of tag");
+            let body = captures
+                .get(2)
+                .expect("failed to retrieve non-optional capture of body");
+            return Some((tag.as_str(), body.as_str()));
+        }
+        if *self != JournalFormat::Org {
+            return None;
+        }
+        let captures = ORG_TAGGED_HEADING_REGEX.captures(line)?;
+        let body = captures.get(1)?.as_str();
+        let tags = captures.get(2)?.as_str();
+        tags.split(':')
+            .find(|tag| ReadState::from_tag(tag).is_some())
+            .map(|tag| (tag, body))
+    }
+
+    /// What the entry on the line is, if a tag says, e.g. `#video`,
+    /// or `:video:` on an Org heading.
+    pub(crate) fn kind(&self, line: &str) -> Option<Kind> {
+        if let Some(c) = KIND_TAG_REGEX.captures(line) {
+            return Kind::from_tag(&c[1]);
+        }
+        if *self != JournalFormat::Org {
+            return None;
+        }
+        let captures = ORG_TAGGED_HEADING_REGEX.captures(line)?;
+        captures
+            .get(2)?
+            .as_str()
+            .split(':')
+            .find_map(Kind::from_tag)
+    }
+
+    /// A line for an entry with the journal tag, linking to the URL with the title,
+    /// and followed by the note if there is one. The title and note must be on one line.
+    pub fn entry_line(&self, tag: &str, title: &str, url: &str, note: &str) -> String {
+        let mut line = match self {
+            JournalFormat::Markdown => {
+                let title = title
+                    .replace('\\', "\\\\")
+                    .replace('[', "\\[")
+                    .replace(']', "\\]");
+                format!("- #{tag} [{title}]({url})")
+            }
+            JournalFormat::Org => {
+                // Org has no escapes for brackets in a link's description.
+                let title = title.replace('[', "(").replace(']', ")");
+                format!("- #{tag} [[{url}][{title}]]")
+            }
+            JournalFormat::Text => format!("#{tag} {title} {url}"),
+        };
+        if !note.is_empty() {
+            line.push_str(" — ");
+            line.push_str(note);
+        }
+        line
+    }
+
+    /// The body of an entry, in Markdown.
+    pub(crate) fn markdown_body<'a>(&self, body: &'a str) -> Cow<'a, str> {
+        match self {
+            JournalFormat::Markdown => Cow::Borrowed(body),
+            JournalFormat::Org => org_links_to_markdown(body),
+            JournalFormat::Text => Cow::Owned(autolink(body)),
+        }
+    }
+}
+
+/// The body of an entry without the kind tags it starts with, e.g. `#video`.
+pub(crate) fn without_kind_tags(mut body: &str) -> &str {
+    while let Some(m) = KIND_TAG_REGEX.find(body).filter(|m| m.start() == 0) {
+        body = body[m.end()..].trim_start_matches([' ', ':']);
+    }
+    body
+}
+
+/// Replace `[[url][description]]` and `[[url]]` links with Markdown ones.
+fn org_links_to_markdown(body: &str) -> Cow<'_, str> {
+    ORG_LINK_REGEX.replace_all(body, |c: &regex_lite::Captures| {
+        let url = &c[1];
+        match c.get(2).map(|m| m.as_str()).filter(|v| !v.is_empty()) {
+            Some(description) => format!("[{description}]({url})"),
+            None => format!("<{url}>"),
+        }
+    })
+}
+
+/// Replace HTML anchors, e.g. `<a href="https://example.com/">Title</a>`, with Markdown links,
+/// as HTML in entries isn't rendered.
+pub(crate) fn anchors_to_markdown(body: &str) -> Cow<'_, str> {
+    ANCHOR_REGEX.replace_all(body, |c: &regex_lite::Captures| {
+        let text = TAG_REGEX.replace_all(&c[2], "");
+        let text = text.trim();
+        let url = &c[1];
+        if text.is_empty() {
+            format!("<{url}>")
+        } else {
+            format!("[{text}]({url})")
+        }
+    })
+}
+
+/// The text of an HTML document, with a paragraph for each block, and its anchors as
+/// Markdown links.
+pub(crate) fn html_to_text(html: &str) -> String {
+    let html = HIDDEN_HTML_REGEX.replace_all(html, "");
+    let html = anchors_to_markdown(&html);
+    let html = BLOCK_TAG_REGEX.replace_all(&html, "\n\n");
+    let text = TAG_REGEX.replace_all(&html, "");
+    text.split("\n\n")
+        .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|p| !p.is_empty())
+        .map(|p| decode_entities(&p).into_owned())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Replace HTML's character references, e.g. `&amp;`, with the characters.
+fn decode_entities(text: &str) -> Cow<'_, str> {
+    ENTITY_REGEX.replace_all(text, |c: &regex_lite::Captures| {
+        let code = match (c.get(1), c.get(2), c.get(3).map(|m| m.as_str())) {
+            (Some(decimal), _, _) => decimal.as_str().parse().ok(),
+            (_, Some(hex), _) => u32::from_str_radix(hex.as_str(), 16).ok(),
+            (_, _, Some("amp")) => Some('&' as u32),
+            (_, _, Some("lt")) => Some('<' as u32),
+            (_, _, Some("gt")) => Some('>' as u32),
+            (_, _, Some("quot")) => Some('"' as u32),
+            (_, _, Some("apos")) => Some('\'' as u32),
+            _ => Some(' ' as u32),
+        };
+        code.and_then(char::from_u32)
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
+            .to_string()
+    })
+}
+
+/// Target of the anchor opened by an HTML tag, e.g. `<a href="https://example.com/">`.
+pub(crate) fn anchor_href(html: &str) -> Option<&str> {
+    HREF_REGEX
+        .captures(html)
+        .and_then(|c| c.get(1))
+        .map(|m| m.as_str())
+}
+
+/// Trailing punctuation is more likely the sentence's than the URL's.
+pub(crate) fn trim_url(url: &str) -> &str {
+    let mut url = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'']);
+    while url.ends_with(')') && url.matches('(').count() < url.matches(')').count() {
+        url = url[..url.len() - 1].trim_end_matches(['.', ',', ';', ':', '!', '?', '\'']);
+    }
+    url
+}
+
+/// Make the bare URLs in plain text into Markdown autolinks.
+pub(crate) fn autolink(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for m in URL_REGEX.find_iter(text) {
+        let url = trim_url(m.as_str());
+        let start = m.start();
+        // Leave URLs that are already links, or the text of one, alone.
+        let before = &text[..start];
+        if before.ends_with(['<', '[', '`'])
+            || ["](", "=\"", "='"].iter().any(|v| before.ends_with(v))
+        {
+            continue;
+        }
+        out.push_str(&text[last..start]);
+        out.push('<');
+        out.push_str(url);
+        out.push('>');
+        last = start + url.len();
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::scan_text;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn reads_org_date_headings() {
+        let org = JournalFormat::Org;
+        assert_eq!(org.heading_date("* 2024-03-05"), Some(date(2024, 3, 5)));
+        assert_eq!(
+            org.heading_date("** <2024-03-05 Tue>"),
+            Some(date(2024, 3, 5))
+        );
+        assert_eq!(
+            org.heading_date("* [2024-03-05 Tue] Notes"),
+            Some(date(2024, 3, 5))
+        );
+        assert_eq!(org.heading_date("* Notes from 2024-03-05"), None);
+        assert_eq!(JournalFormat::Markdown.heading_date("* 2024-03-05"), None);
+    }
+
+    #[test]
+    fn reads_org_heading_tags() {
+        let org = JournalFormat::Org;
+        let line = "** [[https://example.com/talk][A talk]]   :work:read:video:";
+        assert_eq!(
+            org.entry(line),
+            Some(("read", "[[https://example.com/talk][A talk]]"))
+        );
+        assert_eq!(org.kind(line), Some(Kind::Video));
+        assert_eq!(org.entry("** Meeting notes   :work:"), None);
+        assert_eq!(JournalFormat::Markdown.entry(line), None);
+    }
+
+    #[test]
+    fn converts_org_links() {
+        assert_eq!(
+            JournalFormat::Org.markdown_body(
+                "see [[https://example.com/][Example]] and [[https://example.org/]]"
+            ),
+            "see [Example](https://example.com/) and <https://example.org/>"
+        );
+    }
+
+    #[test]
+    fn dates_org_entries_by_heading_or_file() {
+        let text = "- #tbr [[https://example.com/a][A]]
+* 2024-03-06
+- #read [[https://example.com/b][B]]
+* <2024-03-07 Thu>
+** [[https://example.com/c][C]] :skimmed:
+";
+        let entries = scan_text(Path::new("2024-03-05.org"), text)
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.url.to_string(), e.source_date, e.state))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (
+                    "https://example.com/a".to_owned(),
+                    date(2024, 3, 5),
+                    ReadState::ToRead
+                ),
+                (
+                    "https://example.com/b".to_owned(),
+                    date(2024, 3, 6),
+                    ReadState::Read
+                ),
+                (
+                    "https://example.com/c".to_owned(),
+                    date(2024, 3, 7),
+                    ReadState::Skimmed
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_entry_lines_that_scan_back() {
+        for (ext, line) in [
+            (
+                "md",
+                "- #tbr [A \\[draft\\]](https://example.com/a) — a note",
+            ),
+            (
+                "org",
+                "- #tbr [[https://example.com/a][A (draft)]] — a note",
+            ),
+            ("txt", "#tbr A [draft] https://example.com/a — a note"),
+        ] {
+            let file = format!("2024-03-05.{ext}");
+            let format = JournalFormat::of(Path::new(&file));
+            assert_eq!(
+                format.entry_line("tbr", "A [draft]", "https://example.com/a", "a note"),
+                line
+            );
+            let entries = scan_text(Path::new(&file), line).unwrap();
+            assert_eq!(entries.len(), 1, "{line}");
+            assert_eq!(entries[0].url.to_string(), "https://example.com/a");
+            assert_eq!(entries[0].state, ReadState::ToRead);
+        }
+    }
+
+    #[test]
+    fn trims_punctuation_from_urls() {
+        assert_eq!(trim_url("https://example.com/a."), "https://example.com/a");
+        assert_eq!(
+            trim_url("https://en.wikipedia.org/wiki/Rust_(programming_language)"),
+            "https://en.wikipedia.org/wiki/Rust_(programming_language)"
+        );
+        assert_eq!(
+            trim_url("https://en.wikipedia.org/wiki/Rust_(programming_language))."),
+            "https://en.wikipedia.org/wiki/Rust_(programming_language)"
+        );
+        assert_eq!(trim_url("https://example.com/a),"), "https://example.com/a");
+    }
+}
@@ -0,0 +1,370 @@
+//! Papers and books, journaled by identifier rather than by link:
+//! `doi:10.1145/3290605.3300233`, `arXiv:2401.01234`, or `isbn:978-0-262-04630-5`.
+//!
+//! Each is stored in a canonical form, so that entries for the same paper or book are
+//! one article however they're written, and linked to a page for it only when shown.
+
+use std::{borrow::Cow, fmt::Display, sync::LazyLock};
+
+use regex_lite::Regex;
+
+use crate::{format::trim_url, Target};
+
+static IDENTIFIER_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\b(doi|arxiv|isbn):([^\s<>"\[\]]+)"#).expect("invalid regex provided")
+});
+
+/// Spans in which identifiers aren't words of their own: code, and URLs, including those
+/// with identifiers in their query string.
+static CODE_OR_URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"`[^`]*`|(?:https?://|www\.)[^\s<>"]+"#).expect("invalid regex provided")
+});
+
+/// Destinations of inline links, which may be identifiers, but shouldn't have any linked
+/// within them.
+static DESTINATION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\]\(\s*<?([^\s()<>]*)").expect("invalid regex provided"));
+
+static DOI_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^10\.\d{4,9}/\S+$").expect("invalid regex provided"));
+
+static ARXIV_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^(\d{4}\.\d{4,5}|[a-z-]+(?:\.[a-z]{2})?/\d{7})(?:v\d+)?$")
+        .expect("invalid regex provided")
+});
+
+/// Where books link to when no other page is configured; `{isbn}` is the ISBN-13.
+pub const DEFAULT_BOOK_URL: &str = "https://openlibrary.org/isbn/{isbn}";
+
+/// A paper or book, by its identifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identifier {
+    /// A DOI, in lowercase, as DOIs are case-insensitive.
+    Doi(String),
+    /// An arXiv ID, without a version, e.g. `2401.01234` or `math.GT/0309136`.
+    Arxiv(String),
+    /// An ISBN-13, digits only. ISBN-10s are converted.
+    Isbn(String),
+}
+
+impl Identifier {
+    /// Parse an identifier with its scheme, e.g. `doi:10.1145/3290605.3300233`.
+    pub fn parse(s: &str) -> Option<Identifier> {
+        let (scheme, value) = s.split_once(':')?;
+        match scheme.to_ascii_lowercase().as_str() {
+            "doi" => DOI_REGEX
+                .is_match(value)
+                .then(|| Identifier::Doi(value.to_ascii_lowercase())),
+            "arxiv" => ARXIV_REGEX
+                .captures(value)
+                .map(|c| Identifier::Arxiv(c[1].to_owned())),
+            "isbn" => isbn13(value).map(Identifier::Isbn),
+            _ => None,
+        }
+    }
+
+    /// Page for the paper or book: at doi.org, arxiv.org, or `book_url` with `{isbn}`
+    /// replaced by the ISBN.
+    pub fn url(&self, book_url: &str) -> String {
+        match self {
+            Identifier::Doi(doi) => format!("https://doi.org/{}", escape_path(doi)),
+            Identifier::Arxiv(id) => format!("https://arxiv.org/abs/{id}"),
+            Identifier::Isbn(isbn) => book_url.replace("{isbn}", isbn),
+        }
+    }
+}
+
+impl Display for Identifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Identifier::Doi(doi) => write!(f, "doi:{doi}"),
+            Identifier::Arxiv(id) => write!(f, "arXiv:{id}"),
+            Identifier::Isbn(isbn) => write!(f, "isbn:{isbn}"),
+        }
+    }
+}
+
+/// Link the identifiers in Markdown text in their canonical forms, e.g.
+/// `DOI:10.1145/3290605.3300233ABC` as
+/// `[doi:10.1145/3290605.3300233abc](doi:10.1145/3290605.3300233abc)`,
+/// and put the targets of links to them, e.g. `[Paper](arXiv:2401.01234v2)`,
+/// in canonical form too. This is how entries are stored;
+/// [resolve_identifiers] links them to pages when they're shown.
+pub(crate) fn canonicalize_identifiers(text: &str) -> Cow<'_, str> {
+    link_identifiers(text, |identifier| identifier.to_string())
+}
+
+/// Link the identifiers in Markdown text to their pages, e.g.
+/// `doi:10.1145/3290605.3300233` to
+/// `[doi:10.1145/3290605.3300233](https://doi.org/10.1145/3290605.3300233)`,
+/// and the targets of links to them, e.g. `[Paper](doi:10.1145/...)`, to the pages.
+pub fn resolve_identifiers<'a>(text: &'a str, book_url: &str) -> Cow<'a, str> {
+    link_identifiers(text, |identifier| identifier.url(book_url))
+}
+
+/// The page an entry links to: its URL, or the page for its identifier.
+pub fn page_url(target: &Target, book_url: &str) -> String {
+    match target {
+        Target::Page(uri) => uri.to_string(),
+        Target::Identifier(id) => match Identifier::parse(id) {
+            Some(identifier) => identifier.url(book_url),
+            None => id.clone(),
+        },
+    }
+}
+
+/// Replace the identifiers in Markdown text with links to `target(identifier)`,
+/// and the targets of links to identifiers with `target(identifier)`.
+/// Identifiers in the text of a link, in code, or within a URL are left alone.
+fn link_identifiers<'a>(text: &'a str, target: impl Fn(&Identifier) -> String) -> Cow<'a, str> {
+    let skipped: Vec<_> = CODE_OR_URL_REGEX
+        .find_iter(text)
+        .map(|m| m.range())
+        .collect();
+    let destinations: Vec<_> = DESTINATION_REGEX
+        .captures_iter(text)
+        .filter_map(|c| c.get(1))
+        .map(|m| m.range())
+        .collect();
+    let mut out = String::new();
+    let mut last = 0;
+    for m in IDENTIFIER_REGEX.find_iter(text) {
+        let found = trim_url(m.as_str());
+        let Some(identifier) = Identifier::parse(found) else {
+            continue;
+        };
+        if skipped.iter().any(|r| r.contains(&m.start())) {
+            continue;
+        }
+        let destination = destinations.iter().find(|r| r.contains(&m.start()));
+        let before = &text[..m.start()];
+        let in_link_text = before.matches('[').count() > before.matches(']').count();
+        let link = match destination {
+            Some(r) if r.start == m.start() => target(&identifier),
+            Some(_) => continue,
+            None if before.ends_with('<') => target(&identifier),
+            None if in_link_text || before.ends_with('/') => continue,
+            None => format!("[{identifier}]({})", target(&identifier)),
+        };
+        out.push_str(&text[last..m.start()]);
+        out.push_str(&link);
+        last = m.start() + found.len();
+    }
+    if last == 0 {
+        return Cow::Borrowed(text);
+    }
+    out.push_str(&text[last..]);
+    Cow::Owned(out)
+}
+
+/// The ISBN-13 for an ISBN-10 or ISBN-13, with or without hyphens,
+/// if its check digit is right.
+fn isbn13(s: &str) -> Option<String> {
+    let digits: String = s.chars().filter(|c| *c != '-').collect();
+    let value = |c: char| c.to_digit(10);
+    match digits.len() {
+        10 => {
+            let (body, check) = digits.split_at(9);
+            let sum = body
+                .chars()
+                .enumerate()
+                .map(|(i, c)| value(c).map(|v| v * (10 - i as u32)))
+                .sum::<Option<u32>>()?;
+            let check = match check {
+                "X" | "x" => 10,
+                v => v.parse().ok()?,
+            };
+            if (sum + check) % 11 != 0 {
+                return None;
+            }
+            let body = format!("978{body}");
+            let check = (10 - isbn13_sum(&body)? % 10) % 10;
+            Some(format!("{body}{check}"))
+        }
+        13 => (isbn13_sum(&digits)? % 10 == 0).then_some(digits),
+        _ => None,
+    }
+}
+
+/// Weighted sum of the digits of an ISBN-13, or the first 12 of them.
+fn isbn13_sum(digits: &str) -> Option<u32> {
+    digits
+        .chars()
+        .enumerate()
+        .map(|(i, c)| c.to_digit(10).map(|v| if i % 2 == 0 { v } else { v * 3 }))
+        .sum()
+}
+
+/// Percent-encode the characters of a DOI that can't be in a URL path as they are.
+fn escape_path(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'/'
+            | b':'
+            | b'@'
+            | b'!'
+            | b'$'
+            | b'&'
+            | b'\''
+            | b'('
+            | b')'
+            | b'*'
+            | b'+'
+            | b','
+            | b';'
+            | b'=' => out.push(b as char),
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(text: &str) -> Cow<'_, str> {
+        resolve_identifiers(text, DEFAULT_BOOK_URL)
+    }
+
+    #[test]
+    fn links_bare_identifiers() {
+        assert_eq!(
+            link("- #tbr doi:10.1145/3290605.3300233, a paper"),
+            "- #tbr [doi:10.1145/3290605.3300233](https://doi.org/10.1145/3290605.3300233), a paper"
+        );
+        assert_eq!(
+            link("isbn:0-262-04630-X"),
+            "[isbn:9780262046305](https://openlibrary.org/isbn/9780262046305)"
+        );
+    }
+
+    #[test]
+    fn replaces_link_destinations() {
+        assert_eq!(
+            link("[A paper](arXiv:2401.01234v2)"),
+            "[A paper](https://arxiv.org/abs/2401.01234)"
+        );
+        assert_eq!(
+            link("<doi:10.1145/3290605.3300233>"),
+            "<https://doi.org/10.1145/3290605.3300233>"
+        );
+    }
+
+    #[test]
+    fn leaves_urls_alone() {
+        for text in [
+            "[Search](https://www.google.com/search?q=isbn:9780262046305)",
+            "https://www.google.com/search?q=isbn:9780262046305",
+            "<https://example.com/?id=doi:10.1145/3290605.3300233>",
+            "www.example.com/lookup?isbn:9780262046305",
+            "[Search](/search?q=isbn:9780262046305)",
+        ] {
+            assert_eq!(link(text), text);
+        }
+    }
+
+    #[test]
+    fn leaves_link_text_and_code_alone() {
+        for text in [
+            "[isbn:9780262046305](https://example.com/book)",
+            "[see doi:10.1145/3290605.3300233 here](https://example.com/)",
+            "`isbn:9780262046305`",
+            "run `lookup doi:10.1145/3290605.3300233` to find it",
+        ] {
+            assert_eq!(link(text), text);
+        }
+    }
+
+    #[test]
+    fn converts_isbn_10_to_isbn_13() {
+        assert_eq!(isbn13("0-262-04630-X").as_deref(), Some("9780262046305"));
+        assert_eq!(isbn13("0306406152").as_deref(), Some("9780306406157"));
+        assert_eq!(
+            isbn13("978-0-306-40615-7").as_deref(),
+            Some("9780306406157")
+        );
+        // Wrong check digits.
+        assert_eq!(isbn13("0306406153"), None);
+        assert_eq!(isbn13("9780306406158"), None);
+        assert_eq!(isbn13("12345"), None);
+    }
+
+    #[test]
+    fn parses_identifiers_into_canonical_forms() {
+        assert_eq!(
+            Identifier::parse("DOI:10.1145/3290605.3300233ABC"),
+            Some(Identifier::Doi("10.1145/3290605.3300233abc".to_owned()))
+        );
+        assert_eq!(
+            Identifier::parse("arXiv:2401.01234v3"),
+            Some(Identifier::Arxiv("2401.01234".to_owned()))
+        );
+        assert_eq!(
+            Identifier::parse("arxiv:math.GT/0309136"),
+            Some(Identifier::Arxiv("math.GT/0309136".to_owned()))
+        );
+        assert_eq!(
+            Identifier::parse("isbn:0-306-40615-2"),
+            Some(Identifier::Isbn("9780306406157".to_owned()))
+        );
+        assert_eq!(Identifier::parse("arxiv:hello"), None);
+        assert_eq!(Identifier::parse("issn:0317-8471"), None);
+    }
+
+    #[test]
+    fn links_to_pages() {
+        assert_eq!(
+            Identifier::Doi("10.1000/a<b>".to_owned()).url(DEFAULT_BOOK_URL),
+            "https://doi.org/10.1000/a%3Cb%3E"
+        );
+        assert_eq!(
+            Identifier::Arxiv("2401.01234".to_owned()).url(DEFAULT_BOOK_URL),
+            "https://arxiv.org/abs/2401.01234"
+        );
+        let book_url = "https://books.example/{isbn}/page";
+        let url = Identifier::Isbn("9780306406157".to_owned()).url(book_url);
+        assert_eq!(url, "https://books.example/9780306406157/page");
+        let target = Target::Identifier("isbn:9780306406157".to_owned());
+        assert_eq!(page_url(&target, book_url), url);
+        let target = Target::Page("https://example.com/a".parse().unwrap());
+        assert_eq!(page_url(&target, book_url), "https://example.com/a");
+    }
+
+    #[test]
+    fn stores_identifiers_in_canonical_form() {
+        assert_eq!(
+            canonicalize_identifiers("DOI:10.1145/3290605.3300233ABC, a paper"),
+            "[doi:10.1145/3290605.3300233abc](doi:10.1145/3290605.3300233abc), a paper"
+        );
+        assert_eq!(
+            canonicalize_identifiers("[A paper](arxiv:2401.01234v2)"),
+            "[A paper](arXiv:2401.01234)"
+        );
+        assert_eq!(
+            canonicalize_identifiers("<isbn:0-262-04630-X>"),
+            "<isbn:9780262046305>"
+        );
+        // Stored text is canonical already, and links to pages when shown.
+        let stored = canonicalize_identifiers("isbn:0-262-04630-X").into_owned();
+        assert_eq!(canonicalize_identifiers(&stored), stored);
+        assert_eq!(
+            resolve_identifiers(&stored, "https://books.example/{isbn}"),
+            "[isbn:9780262046305](https://books.example/9780262046305)"
+        );
+    }
+
+    #[test]
+    fn ignores_invalid_identifiers() {
+        assert_eq!(link("isbn:9780262046306"), "isbn:9780262046306");
+        assert_eq!(link("doi:11.1/x"), "doi:11.1/x");
+    }
+}
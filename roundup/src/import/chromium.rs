@@ -0,0 +1,233 @@
+//! A Chromium profile's `Bookmarks`, a JSON tree of folders under a few roots,
+//! and its `History` database, with pages in `urls` and each visit in `visits`.
+//! Times are microseconds since 1601.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use rusqlite::Connection;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{date_of_timestamp, ImportError, ImportOptions, ImportReport, Item};
+
+/// Seconds from 1601 to the Unix epoch.
+const EPOCH_OFFSET: i64 = 11_644_473_600;
+
+#[derive(Deserialize)]
+struct Bookmarks {
+    roots: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    /// "url" or "folder".
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    url: String,
+    /// Microseconds since 1601, as a string.
+    #[serde(default)]
+    date_added: String,
+    #[serde(default)]
+    children: Vec<Node>,
+}
+
+/// Read the bookmarks. Folders count as tags.
+pub(super) fn bookmarks(input: &str, options: &ImportOptions) -> Result<ImportReport, ImportError> {
+    let bookmarks: Bookmarks = serde_json::from_str(input)?;
+    let mut report = ImportReport::default();
+    // Besides the roots, newer versions keep other data there.
+    for root in bookmarks.roots.into_values() {
+        if let Ok(root) = serde_json::from_value::<Node>(root) {
+            add_folder(&root, &mut Vec::new(), options, &mut report);
+        }
+    }
+    Ok(report)
+}
+
+fn add_folder<'a>(
+    folder: &'a Node,
+    path: &mut Vec<&'a str>,
+    options: &ImportOptions,
+    report: &mut ImportReport,
+) {
+    path.push(&folder.name);
+    for node in &folder.children {
+        if node.kind == "folder" {
+            add_folder(node, path, options, report);
+            continue;
+        }
+        let folders = || path.iter().rev().copied();
+        if node.kind != "url" || !options.in_folders(folders()) {
+            continue;
+        }
+        let item = Item {
+            url: node.url.clone(),
+            title: node.name.clone(),
+            description: String::new(),
+            date: node.date_added.parse().ok().and_then(date_of_chromium_time),
+            state: options.state_of(folders()),
+            original: json!({
+                "url": node.url,
+                "name": node.name,
+                "folder": path.join("/"),
+                "date_added": node.date_added,
+            })
+            .to_string(),
+        };
+        report.add(item, options);
+    }
+    path.pop();
+}
+
+/// Read the pages in the history that meet the visit rules.
+pub(super) fn history(
+    conn: &Connection,
+    options: &ImportOptions,
+) -> Result<ImportReport, ImportError> {
+    let mut stmt = conn.prepare(
+        "SELECT u.url, u.title, u.visit_count, u.last_visit_time,
+                COALESCE((SELECT SUM(v.visit_duration) FROM visits v WHERE v.url = u.id), 0)
+         FROM urls u
+         WHERE u.hidden = 0 AND (u.url LIKE 'http://%' OR u.url LIKE 'https://%')
+           AND u.visit_count >= ?1
+         ORDER BY u.last_visit_time",
+    )?;
+    let min_dwell = options.visits.min_dwell.as_micros() as i64;
+    let mut rows = stmt.query([options.visits.min_visits])?;
+    let mut report = ImportReport::default();
+    while let Some(row) = rows.next()? {
+        let dwell: i64 = row.get(4)?;
+        if dwell < min_dwell {
+            continue;
+        }
+        let url: String = row.get(0)?;
+        let title: String = row.get(1)?;
+        let visits: i64 = row.get(2)?;
+        let last_visit: i64 = row.get(3)?;
+        let item = Item {
+            original: json!({
+                "url": url,
+                "title": title,
+                "visit_count": visits,
+                "last_visit_time": last_visit,
+                "visit_duration": dwell,
+            })
+            .to_string(),
+            url,
+            title,
+            description: String::new(),
+            date: date_of_chromium_time(last_visit),
+            state: None,
+        };
+        report.add(item, options);
+    }
+    Ok(report)
+}
+
+/// Date of a time in microseconds since 1601, which is 0 when there's none.
+fn date_of_chromium_time(micros: i64) -> Option<NaiveDate> {
+    if micros <= 0 {
+        return None;
+    }
+    date_of_timestamp(micros / 1_000_000 - EPOCH_OFFSET)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::ReadState;
+
+    fn options() -> ImportOptions {
+        ImportOptions::new(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap())
+    }
+
+    #[test]
+    fn converts_times_since_1601() {
+        // 2023-11-14T22:13:20Z
+        assert_eq!(
+            date_of_chromium_time(13_344_473_600_000_000),
+            NaiveDate::from_ymd_opt(2023, 11, 14)
+        );
+        assert_eq!(date_of_chromium_time(0), None);
+    }
+
+    #[test]
+    fn reads_bookmarks_in_folders() {
+        let input = r#"{
+            "checksum": "0",
+            "roots": {
+                "bookmark_bar": {"type": "folder", "name": "Bookmarks bar", "children": [
+                    {"type": "url", "name": "A", "url": "https://example.com/a",
+                     "date_added": "13344473600000000"},
+                    {"type": "folder", "name": "Read", "children": [
+                        {"type": "url", "name": "B", "url": "https://example.com/b",
+                         "date_added": "0"}
+                    ]}
+                ]},
+                "other": {"type": "folder", "name": "Other", "children": []},
+                "sync_metadata": "not a folder"
+            },
+            "version": 1
+        }"#;
+        let report = bookmarks(input, &options()).unwrap();
+        let entries: Vec<_> = report
+            .entries
+            .iter()
+            .map(|e| (e.body_text.as_str(), e.state, e.source_date))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (
+                    "[A](https://example.com/a)",
+                    ReadState::Unknown,
+                    NaiveDate::from_ymd_opt(2023, 11, 14).unwrap(),
+                ),
+                (
+                    "[B](https://example.com/b)",
+                    ReadState::Read,
+                    NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                ),
+            ]
+        );
+
+        let mut options = options();
+        options.folders = vec!["read".to_owned()];
+        assert_eq!(bookmarks(input, &options).unwrap().entries.len(), 1);
+    }
+
+    #[test]
+    fn reads_history_by_visits_and_dwell() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE urls (id INTEGER PRIMARY KEY, url TEXT, title TEXT,
+                visit_count INTEGER, last_visit_time INTEGER, hidden INTEGER);
+            CREATE TABLE visits (id INTEGER PRIMARY KEY, url INTEGER, visit_duration INTEGER);
+            INSERT INTO urls VALUES
+                (1, 'https://example.com/long', 'Long', 3, 13344473600000000, 0),
+                (2, 'https://example.com/short', 'Short', 5, 13344473600000000, 0),
+                (3, 'https://example.com/once', 'Once', 1, 13344473600000000, 0),
+                (4, 'chrome://settings', 'Settings', 9, 13344473600000000, 0);
+            INSERT INTO visits (url, visit_duration) VALUES
+                (1, 200000000), (1, 200000000), (2, 1000000), (3, 900000000);
+            "#,
+        )
+        .unwrap();
+        let mut options = options();
+        options.visits.min_dwell = Duration::from_secs(300);
+        let report = history(&conn, &options).unwrap();
+        let urls: Vec<_> = report.entries.iter().map(|e| e.url.to_string()).collect();
+        assert_eq!(urls, ["https://example.com/long"]);
+        assert_eq!(
+            report.entries[0].source_date,
+            NaiveDate::from_ymd_opt(2023, 11, 14).unwrap()
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! A Firefox profile's `places.sqlite`: pages in `moz_places`, bookmarks and folders
+//! in `moz_bookmarks`, and how long pages were viewed in `moz_places_metadata`.
+//! Tags are folders under the tags root, holding a bookmark of each tagged page.
+
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+use serde_json::json;
+
+use super::{date_of_timestamp, has_table, ImportError, ImportOptions, ImportReport, Item};
+
+const ROOT: &str = "root________";
+const TAGS: &str = "tags________";
+const BOOKMARK: i64 = 1;
+const FOLDER: i64 = 2;
+
+struct Row {
+    kind: i64,
+    parent: i64,
+    title: String,
+    guid: String,
+    /// Microseconds since the Unix epoch.
+    date_added: i64,
+    url: Option<String>,
+    page_title: String,
+}
+
+/// Read the bookmarks. Tags and folders count as tags, in that order.
+pub(super) fn bookmarks(
+    conn: &Connection,
+    options: &ImportOptions,
+) -> Result<ImportReport, ImportError> {
+    let mut stmt = conn.prepare(
+        "SELECT b.id, b.type, b.parent, b.title, b.guid, b.dateAdded, p.url, p.title
+         FROM moz_bookmarks b LEFT JOIN moz_places p ON p.id = b.fk
+         ORDER BY b.parent, b.position",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                Row {
+                    kind: row.get(1)?,
+                    parent: row.get::<_, Option<i64>>(2)?.unwrap_or_default(),
+                    title: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                    guid: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                    date_added: row.get::<_, Option<i64>>(5)?.unwrap_or_default(),
+                    url: row.get(6)?,
+                    page_title: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+                },
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let folders: HashMap<i64, &Row> = rows
+        .iter()
+        .filter(|(_, row)| row.kind == FOLDER)
+        .map(|(id, row)| (*id, row))
+        .collect();
+    // Folders a bookmark is in, innermost first, and whether it's under the tags root.
+    let path = |row: &Row| {
+        let mut path = Vec::new();
+        let mut parent = folders.get(&row.parent);
+        while let Some(folder) = parent.filter(|f| f.guid != ROOT) {
+            if folder.guid == TAGS {
+                return (path, true);
+            }
+            path.push(folder.title.as_str());
+            parent = folders.get(&folder.parent);
+        }
+        (path, false)
+    };
+
+    let mut tags: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut bookmarks = Vec::new();
+    for (_, row) in rows.iter().filter(|(_, row)| row.kind == BOOKMARK) {
+        let Some(url) = &row.url else { continue };
+        match path(row) {
+            (path, true) => tags.entry(url).or_default().extend(path.first()),
+            (path, false) => bookmarks.push((row, url, path)),
+        }
+    }
+
+    let mut report = ImportReport::default();
+    for (row, url, path) in bookmarks {
+        let tags = tags
+            .get(url.as_str())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let names = || tags.iter().chain(&path).copied();
+        if !options.in_folders(names()) {
+            continue;
+        }
+        let title = if row.title.is_empty() {
+            &row.page_title
+        } else {
+            &row.title
+        };
+        let folder = path.iter().rev().copied().collect::<Vec<_>>().join("/");
+        let item = Item {
+            url: url.clone(),
+            title: title.clone(),
+            description: String::new(),
+            date: date_of_timestamp(row.date_added / 1_000_000),
+            state: options.state_of(names()),
+            original: json!({
+                "url": url,
+                "title": title,
+                "folder": folder,
+                "tags": tags,
+                "dateAdded": row.date_added,
+            })
+            .to_string(),
+        };
+        report.add(item, options);
+    }
+    Ok(report)
+}
+
+/// Read the pages in the history that meet the visit rules.
+pub(super) fn history(
+    conn: &Connection,
+    options: &ImportOptions,
+) -> Result<ImportReport, ImportError> {
+    // Milliseconds the page was in the foreground, recorded since Firefox 81.
+    let dwell = if has_table(conn, "moz_places_metadata")? {
+        "(SELECT SUM(m.total_view_time) FROM moz_places_metadata m WHERE m.place_id = p.id)"
+    } else if options.visits.min_dwell.is_zero() {
+        "0"
+    } else {
+        return Err(ImportError::NoDwellTimes);
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT p.url, p.title, p.visit_count, p.last_visit_date, COALESCE({dwell}, 0)
+         FROM moz_places p
+         WHERE p.hidden = 0 AND (p.url LIKE 'http://%' OR p.url LIKE 'https://%')
+           AND p.visit_count >= ?1
+         ORDER BY p.last_visit_date"
+    ))?;
+    let min_dwell = options.visits.min_dwell.as_millis() as i64;
+    let mut rows = stmt.query([options.visits.min_visits])?;
+    let mut report = ImportReport::default();
+    while let Some(row) = rows.next()? {
+        let dwell: i64 = row.get(4)?;
+        if dwell < min_dwell {
+            continue;
+        }
+        let url: String = row.get(0)?;
+        let title: String = row.get::<_, Option<String>>(1)?.unwrap_or_default();
+        let visits: i64 = row.get(2)?;
+        let last_visit: i64 = row.get::<_, Option<i64>>(3)?.unwrap_or_default();
+        let item = Item {
+            original: json!({
+                "url": url,
+                "title": title,
+                "visit_count": visits,
+                "last_visit_date": last_visit,
+                "total_view_time": dwell,
+            })
+            .to_string(),
+            url,
+            title,
+            description: String::new(),
+            date: date_of_timestamp(last_visit / 1_000_000),
+            state: None,
+        };
+        report.add(item, options);
+    }
+    Ok(report)
+}
@@ -0,0 +1,86 @@
+//! Instapaper's CSV export: `URL,Title,Selection,Folder,Timestamp,Tags`.
+//! The folder is `Unread`, `Archive`, `Starred`, or one of the user's own;
+//! tags, in newer exports, are a JSON array.
+
+use super::{date_of_timestamp, record_text, Csv, ImportError, ImportOptions, ImportReport, Item};
+
+pub(super) fn parse(input: &str, options: &ImportOptions) -> Result<ImportReport, ImportError> {
+    let csv = Csv::parse(input)?;
+    let url = csv.column("url")?;
+    let title = csv.optional_column("title");
+    let selection = csv.optional_column("selection");
+    let folder = csv.optional_column("folder");
+    let timestamp = csv.optional_column("timestamp");
+    let tags = csv.optional_column("tags");
+    let field = |record: &csv::StringRecord, i: Option<usize>| {
+        i.and_then(|i| record.get(i)).unwrap_or_default().to_owned()
+    };
+    let mut report = ImportReport::default();
+    for record in &csv.records {
+        let tags: Vec<String> = serde_json::from_str(&field(record, tags)).unwrap_or_default();
+        let folder = field(record, folder);
+        let item = Item {
+            url: field(record, Some(url)),
+            title: field(record, title),
+            description: field(record, selection),
+            date: field(record, timestamp)
+                .parse()
+                .ok()
+                .and_then(date_of_timestamp),
+            // The folder is the least specific tag.
+            state: options.state_of(
+                tags.iter()
+                    .map(String::as_str)
+                    .chain(std::iter::once(folder.as_str())),
+            ),
+            original: record_text(record),
+        };
+        report.add(item, options);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::ReadState;
+
+    #[test]
+    fn reads_columns_by_name() {
+        let input = r#"URL,Title,Selection,Folder,Timestamp,Tags
+https://example.com/a,A,A highlight,Unread,1700000000,"[""skimmed""]"
+https://example.com/b,B,,Archive,1700000000,
+https://example.com/c,C,,Recipes,,"[]"
+"#;
+        let options = ImportOptions::new(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        let report = parse(input, &options).unwrap();
+        let entries: Vec<_> = report
+            .entries
+            .iter()
+            .map(|e| (e.body_text.as_str(), e.state, e.source_date))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (
+                    "[A](https://example.com/a) — A highlight",
+                    // Tags come before the folder.
+                    ReadState::Skimmed,
+                    NaiveDate::from_ymd_opt(2023, 11, 14).unwrap(),
+                ),
+                (
+                    "[B](https://example.com/b)",
+                    ReadState::Read,
+                    NaiveDate::from_ymd_opt(2023, 11, 14).unwrap(),
+                ),
+                (
+                    "[C](https://example.com/c)",
+                    ReadState::Unknown,
+                    NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                ),
+            ]
+        );
+    }
+}
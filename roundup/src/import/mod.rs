@@ -0,0 +1,373 @@
+//! Reading-list entries from other tools' exports, e.g. browser bookmarks.
+//!
+//! Imported entries go into the database through [crate::insert], like those from journals,
+//! so importing the same export twice adds nothing new.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, NaiveDate};
+use http::Uri;
+use thiserror::Error;
+
+use crate::{ReadState, ReadingListEntry, Target};
+
+mod chromium;
+mod firefox;
+mod instapaper;
+mod netscape;
+mod pinboard;
+mod pocket;
+
+/// Kind of export file to import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Netscape bookmark HTML, as exported by browsers, Pinboard, and others.
+    Netscape,
+    /// Pinboard's JSON export.
+    Pinboard,
+    /// Pocket's CSV export.
+    Pocket,
+    /// Instapaper's CSV export.
+    Instapaper,
+    /// Bookmarks in a Firefox profile's `places.sqlite`.
+    Firefox,
+    /// Pages in a Firefox profile's history, `places.sqlite`.
+    FirefoxHistory,
+    /// A Chromium profile's `Bookmarks` file.
+    Chromium,
+    /// Pages in a Chromium profile's `History` database.
+    ChromiumHistory,
+}
+
+impl Format {
+    pub const ALL: [Format; 8] = [
+        Format::Netscape,
+        Format::Pinboard,
+        Format::Pocket,
+        Format::Instapaper,
+        Format::Firefox,
+        Format::FirefoxHistory,
+        Format::Chromium,
+        Format::ChromiumHistory,
+    ];
+
+    /// Name of the format, as given on the command line.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Netscape => "netscape",
+            Format::Pinboard => "pinboard",
+            Format::Pocket => "pocket",
+            Format::Instapaper => "instapaper",
+            Format::Firefox => "firefox",
+            Format::FirefoxHistory => "firefox-history",
+            Format::Chromium => "chromium",
+            Format::ChromiumHistory => "chromium-history",
+        }
+    }
+
+    /// Whether the format is a browser's own profile data, rather than an export.
+    /// Much of what a browser keeps was never meant for the reading list,
+    /// so its entries should be reviewed before they're added.
+    pub fn is_profile(&self) -> bool {
+        matches!(
+            self,
+            Format::Firefox | Format::FirefoxHistory | Format::Chromium | Format::ChromiumHistory
+        )
+    }
+
+    /// Read the entries from the export at `path`.
+    /// Browser databases are read from a copy, as the browser may have them open.
+    pub fn import(
+        &self,
+        path: &Path,
+        options: &ImportOptions,
+    ) -> Result<ImportReport, ImportError> {
+        let read = || std::fs::read_to_string(path);
+        match self {
+            Format::Netscape => Ok(netscape::parse(&read()?, options)),
+            Format::Pinboard => pinboard::parse(&read()?, options),
+            Format::Pocket => pocket::parse(&read()?, options),
+            Format::Instapaper => instapaper::parse(&read()?, options),
+            Format::Firefox => firefox::bookmarks(&DatabaseCopy::new(path)?.conn, options),
+            Format::FirefoxHistory => firefox::history(&DatabaseCopy::new(path)?.conn, options),
+            Format::Chromium => chromium::bookmarks(&read()?, options),
+            Format::ChromiumHistory => chromium::history(&DatabaseCopy::new(path)?.conn, options),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("unknown import format: {0:?}")]
+pub struct UnknownFormat(String);
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Format::ALL
+            .into_iter()
+            .find(|f| f.as_str() == s)
+            .ok_or_else(|| UnknownFormat(s.to_owned()))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("I/O error reading export: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("error parsing JSON: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("error parsing CSV: {0}")]
+    CsvError(#[from] csv::Error),
+    #[error("export has no {0:?} column")]
+    MissingColumn(&'static str),
+    #[error("error reading browser database: {0}")]
+    DatabaseError(#[from] rusqlite::Error),
+    #[error("this profile doesn't record how long pages were viewed")]
+    NoDwellTimes,
+}
+
+/// How to turn exported items into entries.
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// State for each tag (or folder), by its name in lowercase.
+    /// An item takes the state of the first of its tags listed here.
+    pub tags: HashMap<String, ReadState>,
+    /// State of items with no tag or flag saying otherwise.
+    pub default_state: ReadState,
+    /// Date of items without one.
+    pub default_date: NaiveDate,
+    /// Folders (or tags) to import bookmarks from, in lowercase, at any depth.
+    /// All bookmarks are imported if this is empty.
+    pub folders: Vec<String>,
+    /// Which pages in a browser's history to import.
+    pub visits: VisitRules,
+}
+
+/// Which pages in a browser's history are worth proposing for the reading list.
+#[derive(Debug, Clone, Copy)]
+pub struct VisitRules {
+    /// Times the page must have been visited.
+    pub min_visits: u32,
+    /// Time the page must have been viewed, over all visits.
+    pub min_dwell: Duration,
+}
+
+impl Default for VisitRules {
+    fn default() -> Self {
+        VisitRules {
+            min_visits: 3,
+            min_dwell: Duration::ZERO,
+        }
+    }
+}
+
+impl ImportOptions {
+    /// Options mapping the journal's tags (`tbr`, `read`, ...) and a few common others
+    /// (`toread`, `unread`, `archive`) to states.
+    pub fn new(default_date: NaiveDate) -> ImportOptions {
+        let mut tags: HashMap<String, ReadState> =
+            ["tbr", "reading", "read", "skimmed", "abandoned"]
+                .into_iter()
+                .filter_map(|tag| Some((tag.to_owned(), ReadState::from_tag(tag)?)))
+                .collect();
+        for state in ReadState::ALL {
+            tags.insert(state.as_str().to_owned(), state);
+        }
+        tags.remove(ReadState::Unknown.as_str());
+        for tag in ["toread", "unread"] {
+            tags.insert(tag.to_owned(), ReadState::ToRead);
+        }
+        // Where read-it-later services put what's been read.
+        tags.insert("archive".to_owned(), ReadState::Read);
+        ImportOptions {
+            tags,
+            default_state: ReadState::Unknown,
+            default_date,
+            folders: Vec::new(),
+            visits: VisitRules::default(),
+        }
+    }
+
+    /// Whether a bookmark in these folders (or with these tags) should be imported.
+    fn in_folders<'a>(&self, folders: impl IntoIterator<Item = &'a str>) -> bool {
+        self.folders.is_empty()
+            || folders
+                .into_iter()
+                .any(|folder| self.folders.contains(&folder.to_lowercase()))
+    }
+
+    /// State of the first of the tags that has one.
+    fn state_of<'a>(&self, tags: impl IntoIterator<Item = &'a str>) -> Option<ReadState> {
+        tags.into_iter()
+            .find_map(|tag| self.tags.get(&tag.to_lowercase()).copied())
+    }
+}
+
+/// Results of reading an export.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub entries: Vec<ReadingListEntry>,
+    /// Why each item that couldn't be imported was skipped.
+    pub skipped: Vec<String>,
+}
+
+/// An item from an export, before it becomes an entry.
+#[derive(Debug, Default)]
+struct Item {
+    url: String,
+    title: String,
+    description: String,
+    date: Option<NaiveDate>,
+    state: Option<ReadState>,
+    /// Text of the item in the export.
+    original: String,
+}
+
+impl ImportReport {
+    fn add(&mut self, item: Item, options: &ImportOptions) {
+        let url: Uri = match item.url.parse() {
+            Ok(v) => v,
+            Err(e) => {
+                self.skipped
+                    .push(format!("invalid URL {:?}: {e}", item.url));
+                return;
+            }
+        };
+        if !matches!(url.scheme_str(), Some("http" | "https")) {
+            self.skipped.push(format!("not a web page: {url}"));
+            return;
+        }
+        let title = if item.title.trim().is_empty() {
+            &item.url
+        } else {
+            item.title.trim()
+        };
+        let mut body_text = format!("[{}]({})", escape_link_text(title), url);
+        if !item.description.trim().is_empty() {
+            body_text.push_str(" — ");
+            body_text.push_str(item.description.trim());
+        }
+        self.entries.push(ReadingListEntry {
+            url: Target::Page(url),
+            original_text: item.original,
+            body_text,
+            source_date: item.date.unwrap_or(options.default_date),
+            state: item.state.unwrap_or(options.default_state),
+            kind: None,
+        });
+    }
+}
+
+fn escape_link_text(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+/// A CSV export, with its columns looked up by name.
+struct Csv {
+    columns: Vec<String>,
+    records: Vec<csv::StringRecord>,
+}
+
+impl Csv {
+    fn parse(input: &str) -> Result<Csv, ImportError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(input.as_bytes());
+        let columns = reader
+            .headers()?
+            .iter()
+            .map(|v| v.trim().to_lowercase())
+            .collect();
+        let records = reader.records().collect::<Result<_, _>>()?;
+        Ok(Csv { columns, records })
+    }
+
+    /// Index of the column, which must be present.
+    fn column(&self, name: &'static str) -> Result<usize, ImportError> {
+        self.columns
+            .iter()
+            .position(|v| v == name)
+            .ok_or(ImportError::MissingColumn(name))
+    }
+
+    /// Index of the column, if present.
+    fn optional_column(&self, name: &'static str) -> Option<usize> {
+        self.column(name).ok()
+    }
+}
+
+/// Text of a CSV record, as in the export.
+fn record_text(record: &csv::StringRecord) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let _ = writer.write_record(record);
+    let bytes = writer.into_inner().unwrap_or_default();
+    String::from_utf8_lossy(&bytes).trim_end().to_owned()
+}
+
+/// Date of a Unix timestamp, in UTC.
+fn date_of_timestamp(seconds: i64) -> Option<NaiveDate> {
+    DateTime::from_timestamp(seconds, 0).map(|t| t.date_naive())
+}
+
+/// A copy of a browser's SQLite database, removed when dropped.
+/// The browser keeps its databases locked while it runs, and may not have checkpointed
+/// recent changes from the write-ahead log, so both files are copied.
+struct DatabaseCopy {
+    conn: rusqlite::Connection,
+    dir: PathBuf,
+}
+
+impl DatabaseCopy {
+    fn new(path: &Path) -> Result<DatabaseCopy, ImportError> {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let dir =
+            std::env::temp_dir().join(format!("roundup-import-{}-{nanos}", std::process::id()));
+        std::fs::create_dir(&dir)?;
+        let copy = dir.join("browser.db");
+        let copied = std::fs::copy(path, &copy).and_then(|_| {
+            let mut wal = path.as_os_str().to_owned();
+            wal.push("-wal");
+            match std::fs::copy(wal, dir.join("browser.db-wal")) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                v => v.map(|_| ()),
+            }
+        });
+        let opened = copied
+            .map_err(ImportError::from)
+            .and_then(|()| Ok(rusqlite::Connection::open(&copy)?));
+        match opened {
+            Ok(conn) => Ok(DatabaseCopy { conn, dir }),
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&dir);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Drop for DatabaseCopy {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Whether the database has the table.
+fn has_table(conn: &rusqlite::Connection, name: &str) -> Result<bool, ImportError> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
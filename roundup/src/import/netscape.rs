@@ -0,0 +1,233 @@
+//! Netscape bookmark files: HTML with a `<DT><A ...>` for each bookmark,
+//! in `<DL>` lists under `<H3>` folder names.
+
+use std::sync::LazyLock;
+
+use regex_lite::Regex;
+
+use super::{date_of_timestamp, ImportOptions, ImportReport, Item};
+use crate::ReadState;
+
+static TOKEN_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<h3[^>]*>(.*?)</h3>|<dl[^>]*>|</dl>|<a\s([^>]*)>(.*?)</a>|<dd>([^<]*)")
+        .expect("invalid regex provided")
+});
+
+static ATTR_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"([A-Za-z_]+)\s*=\s*"([^"]*)""#).expect("invalid regex provided")
+});
+
+/// Read the bookmarks. Folders count as tags, after the bookmark's own.
+pub(super) fn parse(input: &str, options: &ImportOptions) -> ImportReport {
+    // Folder of each open <DL>, if it has one.
+    let mut folders: Vec<Option<String>> = Vec::new();
+    let mut folder = None;
+    let mut items: Vec<Item> = Vec::new();
+    // Whether the last token was a bookmark, which a <DD> describes.
+    let mut in_item = false;
+    for token in TOKEN_REGEX.captures_iter(input) {
+        let text = token.get(0).map_or("", |m| m.as_str());
+        if let Some(name) = token.get(1) {
+            folder = Some(decode(name.as_str()));
+        } else if let (Some(attrs), Some(title)) = (token.get(2), token.get(3)) {
+            let Some(mut item) = bookmark(attrs.as_str(), options, &folders) else {
+                in_item = false;
+                continue;
+            };
+            item.title = decode(title.as_str());
+            item.original = text.to_owned();
+            items.push(item);
+            in_item = true;
+            continue;
+        } else if let Some(description) = token.get(4) {
+            if let (true, Some(item)) = (in_item, items.last_mut()) {
+                item.description = decode(description.as_str());
+                item.original.push_str(text.trim_end());
+            }
+        } else if text.starts_with("</") {
+            folders.pop();
+        } else {
+            folders.push(folder.take());
+        }
+        in_item = false;
+    }
+    let mut report = ImportReport::default();
+    for item in items {
+        report.add(item, options);
+    }
+    report
+}
+
+/// A bookmark from the attributes of its `<A>` tag, if it's in the folders to import.
+fn bookmark(attrs: &str, options: &ImportOptions, folders: &[Option<String>]) -> Option<Item> {
+    let mut item = Item::default();
+    let mut tags = String::new();
+    let mut to_read = false;
+    for attr in ATTR_REGEX.captures_iter(attrs) {
+        let value = decode(&attr[2]);
+        match attr[1].to_ascii_lowercase().as_str() {
+            "href" => item.url = value,
+            "add_date" => item.date = value.parse().ok().and_then(date_of_timestamp),
+            "tags" => tags = value,
+            "toread" => to_read = value == "1",
+            _ => (),
+        }
+    }
+    let folders = folders.iter().rev().flatten().map(String::as_str);
+    let tags = || tags.split(',').map(str::trim).chain(folders.clone());
+    if !options.in_folders(tags()) {
+        return None;
+    }
+    item.state = options
+        .state_of(tags())
+        .or(to_read.then_some(ReadState::ToRead));
+    Some(item)
+}
+
+/// Replace HTML character references.
+fn decode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';').filter(|&i| i <= 10) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let decoded = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            v => v
+                .strip_prefix("#x")
+                .or_else(|| v.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| v.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out.trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    const BOOKMARKS: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<DL><p>
+    <DT><H3 ADD_DATE="1700000000">Read</H3>
+    <DL><p>
+        <DT><H3>Later</H3>
+        <DL><p>
+            <DT><A HREF="https://example.com/a" ADD_DATE="1700000000" TAGS="rust,tbr">A &amp; B</A>
+            <DD>A note
+        </DL><p>
+        <DT><A HREF="https://example.com/b">B</A>
+    </DL><p>
+    <DT><A HREF="https://example.com/c" TOREAD="1">C</A>
+    <DD>About C
+    <DT><A HREF="javascript:void(0)">Bookmarklet</A>
+</DL><p>
+"#;
+
+    fn options() -> ImportOptions {
+        ImportOptions::new(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap())
+    }
+
+    #[test]
+    fn reads_bookmarks_in_nested_folders() {
+        let report = parse(BOOKMARKS, &options());
+        let entries: Vec<_> = report
+            .entries
+            .iter()
+            .map(|e| {
+                (
+                    e.url.to_string(),
+                    e.body_text.as_str(),
+                    e.state,
+                    e.source_date,
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (
+                    "https://example.com/a".to_owned(),
+                    "[A & B](https://example.com/a) — A note",
+                    // The bookmark's own tags come before its folders.
+                    ReadState::ToRead,
+                    NaiveDate::from_ymd_opt(2023, 11, 14).unwrap(),
+                ),
+                (
+                    "https://example.com/b".to_owned(),
+                    "[B](https://example.com/b)",
+                    ReadState::Read,
+                    NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                ),
+                (
+                    "https://example.com/c".to_owned(),
+                    "[C](https://example.com/c) — About C",
+                    ReadState::ToRead,
+                    NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                ),
+            ]
+        );
+        assert_eq!(report.skipped, ["not a web page: javascript:void(0)"]);
+    }
+
+    #[test]
+    fn keeps_the_note_in_the_original_text() {
+        let report = parse(BOOKMARKS, &options());
+        assert!(report.entries[0].original_text.ends_with("<DD>A note"));
+    }
+
+    #[test]
+    fn takes_only_the_folders_given() {
+        let mut options = options();
+        options.folders = vec!["later".to_owned()];
+        let urls: Vec<_> = parse(BOOKMARKS, &options)
+            .entries
+            .iter()
+            .map(|e| e.url.to_string())
+            .collect();
+        assert_eq!(urls, ["https://example.com/a"]);
+
+        options.folders = vec!["read".to_owned()];
+        let urls: Vec<_> = parse(BOOKMARKS, &options)
+            .entries
+            .iter()
+            .map(|e| e.url.to_string())
+            .collect();
+        assert_eq!(urls, ["https://example.com/a", "https://example.com/b"]);
+    }
+
+    #[test]
+    fn decodes_character_references() {
+        assert_eq!(
+            decode("Q&amp;A &#8212; &#x27;quoted&#x27; &c."),
+            "Q&A — 'quoted' &c."
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Pinboard's JSON export: an array of posts.
+
+use chrono::DateTime;
+use serde::Deserialize;
+
+use super::{ImportError, ImportOptions, ImportReport, Item};
+use crate::ReadState;
+
+#[derive(Deserialize)]
+struct Post {
+    href: String,
+    /// The title.
+    #[serde(default)]
+    description: String,
+    /// The notes.
+    #[serde(default)]
+    extended: String,
+    /// RFC 3339 time the post was made.
+    #[serde(default)]
+    time: String,
+    /// "yes" if marked to read.
+    #[serde(default)]
+    toread: String,
+    /// Space-separated.
+    #[serde(default)]
+    tags: String,
+}
+
+pub(super) fn parse(input: &str, options: &ImportOptions) -> Result<ImportReport, ImportError> {
+    let posts: Vec<serde_json::Value> = serde_json::from_str(input)?;
+    let mut report = ImportReport::default();
+    for value in posts {
+        let original = value.to_string();
+        let post: Post = match serde_json::from_value(value) {
+            Ok(v) => v,
+            Err(e) => {
+                report.skipped.push(format!("invalid post {original}: {e}"));
+                continue;
+            }
+        };
+        let state = options
+            .state_of(post.tags.split_whitespace())
+            .or((post.toread == "yes").then_some(ReadState::ToRead));
+        let item = Item {
+            date: DateTime::parse_from_rfc3339(&post.time)
+                .ok()
+                .map(|t| t.date_naive()),
+            url: post.href,
+            title: post.description,
+            description: post.extended,
+            state,
+            original,
+        };
+        report.add(item, options);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn reads_posts() {
+        let input = r#"[
+            {"href": "https://example.com/a", "description": "A", "extended": "Notes",
+             "time": "2023-05-06T07:08:09Z", "toread": "yes", "tags": "rust"},
+            {"href": "https://example.com/b", "description": "B", "tags": "misc read"},
+            {"description": "No link"}
+        ]"#;
+        let options = ImportOptions::new(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        let report = parse(input, &options).unwrap();
+        let entries: Vec<_> = report
+            .entries
+            .iter()
+            .map(|e| (e.body_text.as_str(), e.state, e.source_date))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (
+                    "[A](https://example.com/a) — Notes",
+                    ReadState::ToRead,
+                    NaiveDate::from_ymd_opt(2023, 5, 6).unwrap(),
+                ),
+                (
+                    "[B](https://example.com/b)",
+                    ReadState::Read,
+                    NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                ),
+            ]
+        );
+        assert_eq!(report.skipped.len(), 1);
+    }
+}
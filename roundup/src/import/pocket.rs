@@ -0,0 +1,95 @@
+//! Pocket's CSV export: `title,url,time_added,tags,status`,
+//! with tags separated by `|` and a status of `unread` or `archive`.
+
+use super::{date_of_timestamp, record_text, Csv, ImportError, ImportOptions, ImportReport, Item};
+
+pub(super) fn parse(input: &str, options: &ImportOptions) -> Result<ImportReport, ImportError> {
+    let csv = Csv::parse(input)?;
+    let url = csv.column("url")?;
+    let title = csv.optional_column("title");
+    let time_added = csv.optional_column("time_added");
+    let tags = csv.optional_column("tags");
+    let status = csv.optional_column("status");
+    let field = |record: &csv::StringRecord, i: Option<usize>| {
+        i.and_then(|i| record.get(i)).unwrap_or_default().to_owned()
+    };
+    let mut report = ImportReport::default();
+    for record in &csv.records {
+        let tags = field(record, tags);
+        let status = field(record, status);
+        let item = Item {
+            url: field(record, Some(url)),
+            title: field(record, title),
+            description: String::new(),
+            date: field(record, time_added)
+                .parse()
+                .ok()
+                .and_then(date_of_timestamp),
+            // The status is the least specific tag.
+            state: options.state_of(
+                tags.split('|')
+                    .map(str::trim)
+                    .chain(std::iter::once(status.as_str())),
+            ),
+            original: record_text(record),
+        };
+        report.add(item, options);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::ReadState;
+
+    fn options() -> ImportOptions {
+        ImportOptions::new(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap())
+    }
+
+    #[test]
+    fn reads_columns_by_name() {
+        let input = "title,url,time_added,tags,status
+\"Title, with a comma\",https://example.com/a,1700000000,rust|read,unread
+,https://example.com/b,,,archive
+Relative,/c,1700000000,,unread
+";
+        let report = parse(input, &options()).unwrap();
+        let entries: Vec<_> = report
+            .entries
+            .iter()
+            .map(|e| (e.body_text.as_str(), e.state, e.source_date))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (
+                    "[Title, with a comma](https://example.com/a)",
+                    // Tags come before the status.
+                    ReadState::Read,
+                    NaiveDate::from_ymd_opt(2023, 11, 14).unwrap(),
+                ),
+                (
+                    "[https://example.com/b](https://example.com/b)",
+                    ReadState::Read,
+                    NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                ),
+            ]
+        );
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(
+            report.entries[0].original_text,
+            "\"Title, with a comma\",https://example.com/a,1700000000,rust|read,unread"
+        );
+    }
+
+    #[test]
+    fn requires_a_url_column() {
+        assert!(matches!(
+            parse("title,time_added\nA,1700000000\n", &options()),
+            Err(ImportError::MissingColumn("url"))
+        ));
+    }
+}
@@ -4,9 +4,10 @@ use markdown::mdast::Node;
 use regex_lite::Regex;
 use rusqlite::named_params;
 use std::{
+    collections::HashMap,
     ffi::OsStr,
-    fs::{read_dir, File},
-    io::{BufRead, BufReader},
+    fs::read_dir,
+    io::{Read, Seek, SeekFrom, Write},
     ops::Deref,
     path::{Path, PathBuf},
     sync::LazyLock,
@@ -14,10 +15,22 @@ use std::{
 };
 use thiserror::Error;
 
-pub use reading_roundup_data::ReadingListEntry;
+pub use reading_roundup_data::{Kind, ReadState, ReadingListEntry, Target};
+
+pub mod format;
+pub mod identifier;
+pub mod import;
+pub mod mail;
+mod schema;
+pub mod source;
+pub use format::JournalFormat;
+pub use identifier::Identifier;
+pub use schema::migrate;
+pub use source::{journal_source, JournalSource};
 
 static ENTRY_REGEX: std::sync::LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new("^.*#(reading|read|tbr)[ :]*(.*)$").expect("invalid regex provided")
+    Regex::new("^.*#(reading|read|tbr|skimmed|abandoned)[ :]*(.*)$")
+        .expect("invalid regex provided")
 });
 
 #[derive(Error, Debug)]
@@ -40,141 +53,380 @@ pub enum RoundupErrorKind {
     MarkdownError(String),
     #[error("no valid link found in body: {0}")]
     MissingLink(String),
+    #[error("link is not an absolute http or https URL: {0}")]
+    InvalidLink(String),
+    #[error("error reading message: {0}")]
+    MailError(String),
+    #[error("error reading from git: {0}")]
+    GitError(String),
+    #[error("error reading archive: {0}")]
+    ArchiveError(String),
+}
+
+/// A link found in an entry body, in the order it appears.
+enum Link<'a> {
+    /// An inline link, autolink, bare URL, or HTML anchor.
+    Url(&'a str),
+    /// A reference link, e.g. `[Title][1]`, by its identifier.
+    Reference(&'a str),
 }
 
-/// Recursive visitor to search for a URI.
-fn find_url(node: &Node) -> Option<Uri> {
+/// Recursive visitor to collect the links, and the link reference definitions
+/// by identifier, with their URLs.
+fn find_links<'a>(
+    node: &'a Node,
+    links: &mut Vec<Link<'a>>,
+    definitions: &mut HashMap<&'a str, &'a markdown::mdast::Definition>,
+) {
     match node {
-        Node::Link(link) => return link.url.parse().ok(),
-        _ => {
-            if let Some(children) = node.children() {
-                for child in children {
-                    if let Some(v) = find_url(child) {
-                        return Some(v);
-                    }
-                }
-            }
+        Node::Link(link) => links.push(Link::Url(&link.url)),
+        Node::LinkReference(reference) => links.push(Link::Reference(&reference.identifier)),
+        Node::Html(html) => links.extend(format::anchor_href(&html.value).map(Link::Url)),
+        Node::Definition(definition) => {
+            definitions.insert(&definition.identifier, definition);
         }
+        _ => (),
+    }
+    if let Some(children) = node.children() {
+        for child in children {
+            find_links(child, links, definitions);
+        }
+    }
+}
+
+/// Parse a link's URL, if it's an absolute `http` or `https` one.
+fn web_url(url: &str) -> Option<Uri> {
+    let uri: Uri = url.parse().ok()?;
+    let web = matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some();
+    web.then_some(uri)
+}
+
+/// What a link's URL is to, if it's a web page or an [Identifier].
+fn target(url: &str) -> Option<Target> {
+    match web_url(url) {
+        Some(uri) => Some(Target::Page(uri)),
+        None => Identifier::parse(url).map(|id| Target::Identifier(id.to_string())),
     }
-    None
+}
+
+/// Link reference definitions in a Markdown journal file, e.g. `[1]: https://example.com/`,
+/// so that entries anywhere in the file can use them.
+fn definitions(text: &str) -> Vec<String> {
+    let Ok(ast) = markdown::to_mdast(text, &markdown::ParseOptions::default()) else {
+        return Vec::new();
+    };
+    let mut links = Vec::new();
+    let mut definitions = HashMap::new();
+    find_links(&ast, &mut links, &mut definitions);
+    definitions.into_values().map(definition_line).collect()
+}
+
+/// A link reference definition, as written in Markdown.
+fn definition_line(definition: &markdown::mdast::Definition) -> String {
+    let label = definition
+        .label
+        .as_deref()
+        .unwrap_or(&definition.identifier);
+    format!("[{label}]: <{}>", definition.url)
 }
 
 /// Scan a provided string for the link.
 pub fn scan_body<S: AsRef<str>>(
     date: NaiveDate,
     s: S,
+) -> Result<ReadingListEntry, RoundupErrorKind> {
+    scan_body_with_definitions(date, s, &[])
+}
+
+/// Scan a provided string for the link, which may be a reference to one of the
+/// definitions, e.g. `[1]: https://example.com/`, given elsewhere in its file.
+///
+/// The link is the first that's an absolute `http` or `https` URL, whether an inline link,
+/// an autolink, a bare URL, a reference link, or an HTML anchor; or that's an
+/// [Identifier] such as `doi:10.1145/3290605.3300233`, which is kept in canonical form.
+pub fn scan_body_with_definitions<S: AsRef<str>>(
+    date: NaiveDate,
+    s: S,
+    definitions: &[String],
 ) -> Result<ReadingListEntry, RoundupErrorKind> {
     let parseopts = markdown::ParseOptions {
         constructs: markdown::Constructs {
             autolink: true,
+            gfm_autolink_literal: true,
             ..markdown::Constructs::default()
         },
         ..markdown::ParseOptions::default()
     };
-    let body_ast = markdown::to_mdast(s.as_ref(), &parseopts)
+    let body = format::anchors_to_markdown(s.as_ref());
+    let body = identifier::canonicalize_identifiers(&body);
+    // References are only links if their definitions are in the same document.
+    let mut document = body.to_string();
+    for definition in definitions {
+        document.push_str("\n\n");
+        document.push_str(definition);
+    }
+    let body_ast = markdown::to_mdast(&document, &parseopts)
         .map_err(|_err| RoundupErrorKind::MarkdownError(s.as_ref().to_owned()))?;
-    let url =
-        find_url(&body_ast).ok_or_else(|| RoundupErrorKind::MissingLink(s.as_ref().to_owned()))?;
+    let mut links = Vec::new();
+    let mut definitions = HashMap::new();
+    find_links(&body_ast, &mut links, &mut definitions);
+    let urls = links.iter().filter_map(|link| match link {
+        Link::Url(url) => Some(*url),
+        Link::Reference(id) => definitions.get(id).map(|d| d.url.as_str()),
+    });
+    let url = match urls.clone().find_map(target) {
+        Some(v) => v,
+        None => {
+            return Err(match urls.into_iter().next() {
+                Some(url) => RoundupErrorKind::InvalidLink(url.to_owned()),
+                None => RoundupErrorKind::MissingLink(s.as_ref().to_owned()),
+            })
+        }
+    };
+    // Keep the body readable as CommonMark, without the file's definitions.
+    let mut body_text = format::autolink(&body);
+    let mut referenced: Vec<&str> = Vec::new();
+    for link in &links {
+        if let Link::Reference(id) = link {
+            if definitions.contains_key(id) && !referenced.contains(id) {
+                referenced.push(id);
+            }
+        }
+    }
+    if !referenced.is_empty() {
+        body_text.push('\n');
+        for id in referenced {
+            body_text.push('\n');
+            body_text.push_str(&definition_line(definitions[id]));
+        }
+    }
     Ok(ReadingListEntry {
         url,
-        body_text: s.as_ref().to_owned(),
+        body_text,
         original_text: s.as_ref().to_owned(),
         source_date: date,
-        read: None,
+        state: ReadState::Unknown,
+        kind: None,
     })
 }
 
 /// Scan the file at the given path and find any reading-list entries in it.
 pub fn scan_file(file: &Path) -> Result<Vec<ReadingListEntry>, RoundupErrorKind> {
-    let stem = file.file_stem().and_then(OsStr::to_str).ok_or_else(|| {
-        RoundupErrorKind::InvalidFile("cannot determine file stem, or stem is not UTF-8")
-    })?;
-    let source_date: NaiveDate = stem
-        .parse()
-        .map_err(|_| RoundupErrorKind::InvalidFile("file stem is not YYYY-MM-DD"))?;
-
-    let f = BufReader::new(File::open(file)?);
+    scan_text(file, &std::fs::read_to_string(file)?)
+}
+
+/// Find any reading-list entries in the text of a journal file, named by its path.
+/// The file's extension says what format it's in (see [JournalFormat::of]).
+pub fn scan_text(file: &Path, text: &str) -> Result<Vec<ReadingListEntry>, RoundupErrorKind> {
+    let format = JournalFormat::of(file);
+    // Files dated by headings need only be named by date if they have entries before one.
+    let file_date = if format.has_date_headings() {
+        date_of_file(file).ok()
+    } else {
+        Some(date_of_file(file)?)
+    };
+    let definitions = match format {
+        JournalFormat::Markdown => definitions(text),
+        _ => Vec::new(),
+    };
+    let mut heading_date = None;
     let mut entries = Vec::new();
-    for line in f.lines() {
-        let line = line?;
-        if let Some(captures) = ENTRY_REGEX.captures(&line) {
-            let tag = captures
-                .get(1)
-                .expect("failed to retrieve non-optional capture of tag");
-            let body = captures
-                .get(2)
-                .expect("failed to retrieve non-optional capture of body");
-
-            let read = match tag.as_str() {
-                "read" => Some(true),
-                "tbr" => Some(false),
-                _ => None,
-            };
-            let mut entry = scan_body(source_date, body.as_str())?;
-            entry.original_text = line.clone();
-            entry.read = read;
-            entries.push(entry);
+    for line in text.lines() {
+        if let Some(date) = format.heading_date(line) {
+            heading_date = Some(date);
+            continue;
         }
+        let Some((tag, body)) = format.entry(line) else {
+            continue;
+        };
+        let date = match heading_date.or(file_date) {
+            Some(v) => v,
+            None => {
+                return Err(RoundupErrorKind::InvalidFile(
+                    "entry before any date heading, and file stem is not YYYY-MM-DD",
+                ))
+            }
+        };
+        let body = format::without_kind_tags(body);
+        let mut entry = scan_body_with_definitions(date, format.markdown_body(body), &definitions)?;
+        entry.original_text = line.to_owned();
+        entry.state = ReadState::from_tag(tag).unwrap_or_default();
+        entry.kind = format.kind(line).or(entry.kind);
+        entries.push(entry);
     }
-
     Ok(entries)
 }
 
+/// Date of a journal file, from its name.
+fn date_of_file(file: &Path) -> Result<NaiveDate, RoundupErrorKind> {
+    let stem = file
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .ok_or(RoundupErrorKind::InvalidFile(
+            "cannot determine file stem, or stem is not UTF-8",
+        ))?;
+    stem.parse()
+        .map_err(|_| RoundupErrorKind::InvalidFile("file stem is not YYYY-MM-DD"))
+}
+
+/// Scan a line of a Markdown journal file for a reading-list entry.
+pub fn scan_line(
+    date: NaiveDate,
+    line: &str,
+) -> Result<Option<ReadingListEntry>, RoundupErrorKind> {
+    let Some((tag, body)) = JournalFormat::Markdown.entry(line) else {
+        return Ok(None);
+    };
+    let mut entry = scan_body(date, format::without_kind_tags(body))?;
+    entry.original_text = line.to_owned();
+    entry.state = ReadState::from_tag(tag).unwrap_or_default();
+    entry.kind = JournalFormat::Markdown.kind(line).or(entry.kind);
+    Ok(Some(entry))
+}
+
+/// Which files in a journal directory to scan.
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// Extensions of the files to scan, without the dot.
+    pub extensions: Vec<String>,
+    /// Names of files and directories to skip, e.g. `.git`.
+    pub exclude: Vec<String>,
+    /// Read journals that are git repositories as committed at this revision (e.g. `HEAD`),
+    /// rather than from the working tree.
+    pub revision: Option<String>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            extensions: vec!["md".to_owned()],
+            exclude: Vec::new(),
+            revision: None,
+        }
+    }
+}
+
+impl ScanOptions {
+    fn excludes(&self, path: &Path) -> bool {
+        path.file_name()
+            .and_then(OsStr::to_str)
+            .is_some_and(|name| self.exclude.iter().any(|v| v == name))
+    }
+
+    fn includes(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|ext| self.extensions.iter().any(|v| v == ext))
+    }
+
+    /// Whether to scan the file at a path within an archive, none of whose
+    /// directories may be excluded.
+    fn includes_in_archive(&self, path: &Path) -> bool {
+        self.includes(path)
+            && !path
+                .ancestors()
+                .any(|p| !p.as_os_str().is_empty() && self.excludes(p))
+    }
+}
+
+/// Results of scanning a journal.
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub entries: Vec<ReadingListEntry>,
+    pub errors: Vec<RoundupError>,
+    /// Number of files (or messages) scanned.
+    pub files: usize,
+}
+
+impl ScanReport {
+    /// Add the results of scanning another source.
+    pub fn append(&mut self, mut other: ScanReport) {
+        self.entries.append(&mut other.entries);
+        self.errors.append(&mut other.errors);
+        self.files += other.files;
+    }
+}
+
 /// Scan all the files in the provided directory, recursively, and collect their reading-list
 /// entries and errors.
-pub fn scan_files(dir: &Path) -> (Vec<ReadingListEntry>, Vec<RoundupError>) {
-    let mut ok = Vec::new();
-    let mut err = Vec::new();
+pub fn scan_files(dir: &Path, options: &ScanOptions) -> ScanReport {
+    source::Directory(dir.to_owned()).scan(options)
+}
+
+/// Scan the journal at `path`, which may be a directory, a git repository, or an archive
+/// (see [journal_source]), and collect its reading-list entries and errors.
+pub fn scan_journal(path: &Path, options: &ScanOptions) -> ScanReport {
+    journal_source(path, options).scan(options)
+}
+
+/// Path of the journal file for the date, which may not exist yet.
+/// An existing file for the date anywhere in the journal is used, e.g. `2024/2024-10-01.md`;
+/// a new one goes at the top, with the first of the extensions scanned.
+pub fn journal_file(
+    dir: &Path,
+    date: NaiveDate,
+    options: &ScanOptions,
+) -> std::io::Result<PathBuf> {
+    let stem = date.to_string();
+    Ok(match find_file(dir, &stem, options)? {
+        Some(v) => v,
+        None => {
+            let ext = options.extensions.first().map_or("md", String::as_str);
+            dir.join(format!("{stem}.{ext}"))
+        }
+    })
+}
+
+/// Append a line to a journal file, creating it if there isn't one.
+pub fn append_to_file(path: &Path, line: &str) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .read(true)
+        .open(path)?;
+    let len = file.metadata()?.len();
+    let mut text = String::new();
+    if len > 0 {
+        // Start on a line of our own.
+        file.seek(SeekFrom::End(-1))?;
+        let mut last = [0];
+        file.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            text.push('\n');
+        }
+    }
+    text.push_str(line);
+    text.push('\n');
+    file.write_all(text.as_bytes())
+}
+
+/// Find a file in the journal with the given stem, among those scanned.
+fn find_file(dir: &Path, stem: &str, options: &ScanOptions) -> std::io::Result<Option<PathBuf>> {
     let mut dir_stack = vec![dir.to_owned()];
     while let Some(dir) = dir_stack.pop() {
-        tracing::debug!("visiting directory {}", dir.display());
-        let it = match read_dir(&dir) {
-            Ok(it) => it,
-            Err(e) => {
-                err.push(RoundupError {
-                    file: dir.clone(),
-                    kind: RoundupErrorKind::StatIOError(e),
-                });
+        for direntry in read_dir(&dir)? {
+            let direntry = direntry?;
+            let path = direntry.path();
+            if options.excludes(&path) {
                 continue;
-            }
-        };
-        for direntry in it {
-            let (path, metadata) =
-                match direntry.and_then(|v| v.metadata().map(|md| (v.path(), md))) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        err.push(RoundupError {
-                            file: dir.clone(),
-                            kind: RoundupErrorKind::StatIOError(e),
-                        });
-                        continue;
-                    }
-                };
-            tracing::debug!(
-                "visiting path {} (directory: {})",
-                path.display(),
-                metadata.is_dir()
-            );
-            if metadata.is_dir() {
+            } else if direntry.file_type()?.is_dir() {
                 dir_stack.push(path);
-            } else if path.extension().map(|ext| ext == "md").unwrap_or(false) {
-                match scan_file(&path) {
-                    Ok(mut v) => ok.append(&mut v),
-                    Err(e) => err.push(RoundupError {
-                        file: path.clone(),
-                        kind: e,
-                    }),
-                }
+            } else if options.includes(&path)
+                && path.file_stem().and_then(OsStr::to_str) == Some(stem)
+            {
+                return Ok(Some(path));
             }
         }
     }
-
-    (ok, err)
+    Ok(None)
 }
 
-/// Insert the entries into the database.
-/// Returns the total number of links in the database.
-pub fn insert<'a, I, T>(entries: I, db: &mut T) -> rusqlite::Result<usize>
+/// Insert the entries into the reading list of the given owner (a user ID),
+/// or into the list of entries with no owner.
+/// Entries with a known state also record a transition to that state as of the start
+/// of their source date; the entry's current state is the one with the latest transition.
+/// Returns the total number of links in the owner's list.
+pub fn insert<'a, I, T>(entries: I, owner: Option<isize>, db: &mut T) -> rusqlite::Result<usize>
 where
     I: Iterator<Item = &'a ReadingListEntry>,
     T: Deref<Target = rusqlite::Connection>,
@@ -182,21 +434,239 @@ where
     let mut q = db.prepare_cached(
         r#"
 INSERT INTO reading_list
-        ( url,  source_date,  original_text,  body_text,  read )
-VALUES  (:url, :source_date, :original_text, :body_text, :read )
-ON CONFLICT (url) DO NOTHING;"#,
+        ( owner,  url,  source_date,  original_text,  body_text,  kind )
+VALUES  (:owner, :url, :source_date, :original_text, :body_text, :kind )
+ON CONFLICT (COALESCE(owner, 0), url) DO NOTHING;"#,
     )?;
+    let mut transition = db.prepare_cached(
+        r#"
+INSERT INTO state_transitions (entry, state, at)
+SELECT id, :state, :at FROM reading_list WHERE owner IS :owner AND url = :url
+ON CONFLICT DO NOTHING;"#,
+    )?;
+    let mut id_of =
+        db.prepare_cached("SELECT id FROM reading_list WHERE owner IS :owner AND url = :url")?;
     for entry in entries {
+        let url = entry.url.to_string();
+        let source_date = format!("{}", entry.source_date);
         q.execute(named_params! {
-            ":url": entry.url.to_string(),
-            ":source_date": format!("{}", entry.source_date),
+            ":owner": owner,
+            ":url": url,
+            ":source_date": source_date,
             ":original_text": entry.original_text,
             ":body_text": entry.body_text,
-            ":read": entry.read,
+            ":kind": entry.kind.map(|k| k.as_str()),
         })?;
+        if entry.state != ReadState::Unknown {
+            transition.execute(named_params! {
+                ":owner": owner,
+                ":url": url,
+                ":state": entry.state.as_str(),
+                ":at": format!("{source_date}T00:00:00Z"),
+            })?;
+            let id = id_of.query_row(named_params! {":owner": owner, ":url": url}, |row| {
+                row.get(0)
+            })?;
+            update_state(db, id)?;
+        }
     }
+    drop(q);
+    drop(transition);
+    drop(id_of);
 
-    db.query_row("SELECT COUNT(*) FROM reading_list;", named_params![], |v| {
-        v.get(0)
-    })
+    db.query_row(
+        "SELECT COUNT(*) FROM reading_list WHERE owner IS :owner;",
+        named_params! {":owner": owner},
+        |v| v.get(0),
+    )
+}
+
+/// Move the entry with the given ID to a new state, as of now.
+pub fn transition(db: &rusqlite::Connection, id: isize, state: ReadState) -> rusqlite::Result<()> {
+    db.prepare_cached(
+        r#"
+INSERT INTO state_transitions (entry, state, at)
+VALUES (:id, :state, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+ON CONFLICT DO NOTHING;"#,
+    )?
+    .execute(named_params! {":id": id, ":state": state.as_str()})?;
+    update_state(db, id)
+}
+
+/// Set the entry's current state from its latest transition, if it has any.
+fn update_state(db: &rusqlite::Connection, id: isize) -> rusqlite::Result<()> {
+    db.prepare_cached(
+        r#"
+UPDATE reading_list
+SET state = (
+    SELECT state FROM state_transitions
+    WHERE entry = reading_list.id
+    ORDER BY at DESC, rowid DESC
+    LIMIT 1
+)
+WHERE id = :id AND id IN (SELECT entry FROM state_transitions);"#,
+    )?
+    .execute(named_params! {":id": id})?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 5).unwrap()
+    }
+
+    fn url_of(body: &str) -> String {
+        scan_body(date(), body).unwrap().url.to_string()
+    }
+
+    #[test]
+    fn finds_links_in_every_form() {
+        for (body, url) in [
+            ("[Title](https://example.com/a)", "https://example.com/a"),
+            ("<https://example.com/a>", "https://example.com/a"),
+            ("see https://example.com/a.", "https://example.com/a"),
+            ("see www.example.com/a", "http://www.example.com/a"),
+            (
+                r#"<a href="https://example.com/a">Title</a>"#,
+                "https://example.com/a",
+            ),
+            (
+                "[Docs](/docs) and [Title](https://example.com/a)",
+                "https://example.com/a",
+            ),
+        ] {
+            assert_eq!(url_of(body), url, "{body}");
+        }
+    }
+
+    #[test]
+    fn keeps_identifiers_in_canonical_form() {
+        let entry = scan_body(date(), "DOI:10.1145/3290605.3300233, a paper").unwrap();
+        assert_eq!(
+            entry.url,
+            Target::Identifier("doi:10.1145/3290605.3300233".to_owned())
+        );
+        assert_eq!(entry.kind(), Kind::Paper);
+        let entry = scan_body(date(), "[A book](isbn:0-262-04630-X)").unwrap();
+        assert_eq!(entry.url.to_string(), "isbn:9780262046305");
+        assert_eq!(entry.body_text, "[A book](isbn:9780262046305)");
+        assert_eq!(entry.kind(), Kind::Book);
+        assert_eq!(
+            url_of("[Docs](/docs) on arXiv:2401.01234v2"),
+            "arXiv:2401.01234"
+        );
+    }
+
+    #[test]
+    fn rejects_entries_without_web_links() {
+        assert!(matches!(
+            scan_body(date(), "[Docs](/docs)"),
+            Err(RoundupErrorKind::InvalidLink(url)) if url == "/docs"
+        ));
+        assert!(matches!(
+            scan_body(date(), "[Mail](mailto:me@example.com)"),
+            Err(RoundupErrorKind::InvalidLink(_))
+        ));
+        assert!(matches!(
+            scan_body(date(), "just a thought"),
+            Err(RoundupErrorKind::MissingLink(_))
+        ));
+    }
+
+    #[test]
+    fn resolves_references_to_definitions_anywhere_in_the_file() {
+        let text = "- #tbr [A post][1], and [another][other]
+- #read [Unrelated](https://example.com/c)
+
+[1]: https://example.com/a
+[other]: https://example.com/b \"Title\"
+";
+        let entries = scan_text(Path::new("2024-03-05.md"), text).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].url.to_string(), "https://example.com/a");
+        // The body keeps the definitions it uses, and only those.
+        assert_eq!(
+            entries[0].body_text,
+            "[A post][1], and [another][other]\n\n[1]: <https://example.com/a>\n[other]: <https://example.com/b>"
+        );
+        assert_eq!(entries[1].body_text, "[Unrelated](https://example.com/c)");
+    }
+
+    #[test]
+    fn leaves_undefined_references_as_text() {
+        assert!(matches!(
+            scan_body(date(), "[A post][1]"),
+            Err(RoundupErrorKind::MissingLink(_))
+        ));
+    }
+
+    fn transitions(conn: &rusqlite::Connection) -> Vec<(String, String)> {
+        conn.prepare("SELECT state, at FROM state_transitions ORDER BY at ASC, rowid ASC")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn records_state_transitions() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        schema::migrate(&mut conn).unwrap();
+        let mut entry = scan_body(date(), "[A post](https://example.com/a)").unwrap();
+        entry.state = ReadState::ToRead;
+        let mut tx = conn.transaction().unwrap();
+        insert([&entry].into_iter(), None, &mut tx).unwrap();
+        // Scanning the entry again doesn't record it again.
+        insert([&entry].into_iter(), None, &mut tx).unwrap();
+        tx.commit().unwrap();
+        let id: isize = conn
+            .query_row("SELECT id FROM reading_list", [], |row| row.get(0))
+            .unwrap();
+        transition(&conn, id, ReadState::Read).unwrap();
+
+        let rows = transitions(&conn);
+        assert_eq!(rows.len(), 2, "{rows:?}");
+        assert_eq!(
+            rows[0],
+            ("to-read".to_owned(), "2024-03-05T00:00:00Z".to_owned())
+        );
+        assert_eq!(rows[1].0, "read");
+        assert!(rows[1].1.ends_with('Z'), "{}", rows[1].1);
+        assert!(chrono::DateTime::parse_from_rfc3339(&rows[1].1).is_ok());
+        let state: String = conn
+            .query_row("SELECT state FROM reading_list", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(state, "read");
+    }
+
+    #[test]
+    fn migrates_transition_times_to_rfc_3339() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        schema::migrate(&mut conn).unwrap();
+        conn.execute_batch(
+            r#"
+            INSERT INTO reading_list (id, url) VALUES (1, 'https://example.com/a');
+            INSERT INTO state_transitions (entry, state, at) VALUES
+                (1, 'to-read', '2024-03-05'),
+                (1, 'read', '2024-03-06 17:30:00'),
+                (1, 'skimmed', '2024-03-07T08:00:00Z');
+            "#,
+        )
+        .unwrap();
+        conn.execute_batch(include_str!("../migrations/0009-transition-times.sql"))
+            .unwrap();
+        let at: Vec<String> = transitions(&conn).into_iter().map(|(_, at)| at).collect();
+        assert_eq!(
+            at,
+            [
+                "2024-03-05T00:00:00Z",
+                "2024-03-06T17:30:00Z",
+                "2024-03-07T08:00:00Z"
+            ]
+        );
+    }
 }
@@ -0,0 +1,377 @@
+//! Reading-list entries from email, e.g. links sent to ourselves from a phone,
+//! or newsletters. Messages are read from a Maildir or an mbox file.
+//!
+//! A message with links in one paragraph is one entry, with the whole message as its body;
+//! otherwise each paragraph with a link is an entry, as each line of a journal is.
+//! The plain-text body is read if there is one, or else the HTML body.
+
+use chrono::{DateTime, NaiveDate};
+use http::Uri;
+use mailparse::{MailHeaderMap, ParsedMail};
+use regex_lite::Regex;
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use crate::{
+    format::{autolink, html_to_text, trim_url, URL_REGEX},
+    JournalFormat, ReadState, ReadingListEntry, RoundupError, RoundupErrorKind, ScanReport, Target,
+};
+
+static TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"#(reading|read|tbr|skimmed|abandoned)\b").expect("invalid regex provided")
+});
+
+/// A Maildir or mbox file of messages with links in them.
+#[derive(Debug, Clone)]
+pub struct Mailbox {
+    /// A Maildir directory, or an mbox file.
+    pub path: PathBuf,
+    /// Take links from messages sent to these addresses.
+    pub to: Vec<String>,
+    /// Take links from messages with this text in their subject, e.g. `[reading]`.
+    pub subject_tags: Vec<String>,
+}
+
+impl Mailbox {
+    /// Scan the messages that are sent to one of the addresses or have one of the subject tags,
+    /// or all of them if neither is given, and collect their reading-list entries and errors.
+    pub fn scan(&self) -> ScanReport {
+        let mut report = ScanReport::default();
+        let messages = if self.path.is_dir() {
+            maildir(&self.path)
+        } else {
+            mbox(&self.path)
+        };
+        let messages = match messages {
+            Ok(v) => v,
+            Err(e) => {
+                report.errors.push(RoundupError {
+                    file: self.path.clone(),
+                    kind: RoundupErrorKind::StatIOError(e),
+                });
+                return report;
+            }
+        };
+        for (file, message) in messages {
+            let result = mailparse::parse_mail(&message)
+                .map_err(|e| RoundupErrorKind::MailError(e.to_string()))
+                .and_then(|mail| {
+                    if !self.matches(&mail) {
+                        return Ok(None);
+                    }
+                    scan_message(&mail).map(Some)
+                });
+            match result {
+                Ok(None) => (),
+                Ok(Some(mut entries)) => {
+                    report.files += 1;
+                    report.entries.append(&mut entries);
+                }
+                Err(kind) => {
+                    report.files += 1;
+                    report.errors.push(RoundupError { file, kind });
+                }
+            }
+        }
+        report
+    }
+
+    fn matches(&self, mail: &ParsedMail) -> bool {
+        if self.to.is_empty() && self.subject_tags.is_empty() {
+            return true;
+        }
+        let headers = mail.get_headers();
+        let subject = headers
+            .get_first_value("Subject")
+            .unwrap_or_default()
+            .to_lowercase();
+        if self
+            .subject_tags
+            .iter()
+            .any(|tag| subject.contains(&tag.to_lowercase()))
+        {
+            return true;
+        }
+        ["To", "Cc", "Delivered-To", "X-Original-To"]
+            .into_iter()
+            .flat_map(|name| headers.get_all_headers(name))
+            .filter_map(|header| mailparse::addrparse_header(header).ok())
+            .flat_map(mailparse::MailAddrList::into_inner)
+            .flat_map(|addr| match addr {
+                mailparse::MailAddr::Single(info) => vec![info.addr],
+                mailparse::MailAddr::Group(group) => {
+                    group.addrs.into_iter().map(|info| info.addr).collect()
+                }
+            })
+            .any(|addr| self.to.iter().any(|v| v.eq_ignore_ascii_case(&addr)))
+    }
+}
+
+/// Messages in the `cur` and `new` directories of a Maildir, with their paths.
+fn maildir(dir: &Path) -> std::io::Result<Vec<(PathBuf, Vec<u8>)>> {
+    let mut messages = Vec::new();
+    for sub in ["cur", "new"] {
+        for direntry in std::fs::read_dir(dir.join(sub))? {
+            let path = direntry?.path();
+            if path.is_file() {
+                messages.push((path.clone(), std::fs::read(path)?));
+            }
+        }
+    }
+    messages.sort();
+    Ok(messages)
+}
+
+/// Messages in an mbox file, each starting with a `From ` line.
+fn mbox(file: &Path) -> std::io::Result<Vec<(PathBuf, Vec<u8>)>> {
+    Ok(split_mbox(&std::fs::read(file)?)
+        .into_iter()
+        .map(|message| (file.to_owned(), message))
+        .collect())
+}
+
+/// The messages in the text of an mbox file.
+/// Lines quoted as `>From ` are unquoted.
+fn split_mbox(text: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages: Vec<Vec<u8>> = Vec::new();
+    let mut blank = true;
+    for line in text.split_inclusive(|&b| b == b'\n') {
+        if blank && line.starts_with(b"From ") {
+            messages.push(Vec::new());
+        } else if let Some(message) = messages.last_mut() {
+            let quoted = line.iter().position(|&b| b != b'>');
+            match quoted {
+                Some(i) if i > 0 && line[i..].starts_with(b"From ") => {
+                    message.extend_from_slice(&line[1..])
+                }
+                _ => message.extend_from_slice(line),
+            }
+        }
+        blank = line.trim_ascii().is_empty();
+    }
+    messages
+}
+
+/// Find the entries in a message's body, dated by its Date header.
+/// Messages without links have none.
+fn scan_message(mail: &ParsedMail) -> Result<Vec<ReadingListEntry>, RoundupErrorKind> {
+    let headers = mail.get_headers();
+    let date = headers
+        .get_first_value("Date")
+        .and_then(|v| date_of_header(&v))
+        .ok_or_else(|| RoundupErrorKind::MailError("no valid Date header".to_owned()))?;
+    let subject = headers.get_first_value("Subject").unwrap_or_default();
+    let body = match text_part(mail, "text/plain") {
+        Some(body) => body,
+        None => match text_part(mail, "text/html") {
+            Some(html) => html.map(|v| html_to_text(&v)),
+            None => return Ok(Vec::new()),
+        },
+    }
+    .map_err(|e| RoundupErrorKind::MailError(e.to_string()))?;
+    let body = body.replace("\r\n", "\n");
+    // Drop the signature.
+    let body = match body.find("\n-- \n") {
+        Some(i) => &body[..i],
+        None => &body,
+    };
+    let paragraphs: Vec<&str> = body
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|p| URL_REGEX.is_match(p))
+        .collect();
+    let texts = match paragraphs.len() {
+        0 => return Ok(Vec::new()),
+        1 => vec![body.trim()],
+        _ => paragraphs,
+    };
+    let subject_state = state_of(&subject);
+    texts
+        .into_iter()
+        .map(|text| {
+            let url = URL_REGEX
+                .find_iter(text)
+                .find_map(|m| trim_url(m.as_str()).parse::<Uri>().ok())
+                .ok_or_else(|| RoundupErrorKind::MissingLink(text.to_owned()))?;
+            Ok(ReadingListEntry {
+                url: Target::Page(url),
+                original_text: text.to_owned(),
+                body_text: autolink(text),
+                source_date: date,
+                state: state_of(text).or(subject_state).unwrap_or_default(),
+                kind: JournalFormat::Markdown.kind(text),
+            })
+        })
+        .collect()
+}
+
+/// The body of the first part of the message of the type, e.g. `text/plain`.
+fn text_part(
+    mail: &ParsedMail,
+    mimetype: &str,
+) -> Option<Result<String, mailparse::MailParseError>> {
+    if mail.subparts.is_empty() {
+        return (mail.ctype.mimetype == mimetype).then(|| mail.get_body());
+    }
+    mail.subparts
+        .iter()
+        .find_map(|part| text_part(part, mimetype))
+}
+
+/// Date the message was sent, where it was sent from.
+fn date_of_header(value: &str) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc2822(value)
+        .map(|t| t.date_naive())
+        .ok()
+        .or_else(|| {
+            let seconds = mailparse::dateparse(value).ok()?;
+            DateTime::from_timestamp(seconds, 0).map(|t| t.date_naive())
+        })
+}
+
+/// State named by a journal tag in the text, e.g. `#tbr`.
+fn state_of(text: &str) -> Option<ReadState> {
+    TAG_REGEX
+        .captures(text)
+        .and_then(|c| ReadState::from_tag(&c[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(message: &str) -> Vec<ReadingListEntry> {
+        scan_message(&mailparse::parse_mail(message.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn splits_mbox_and_unquotes_from_lines() {
+        let text = b"From a@example.com Sat Oct 17 10:00:00 2026
+Subject: one
+
+>From the start
+>>From quoted twice
+From here, not a new message
+
+From b@example.com Sat Oct 17 11:00:00 2026
+Subject: two
+
+Body
+";
+        let messages = split_mbox(text);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            String::from_utf8_lossy(&messages[0]),
+            "Subject: one\n\nFrom the start\n>From quoted twice\nFrom here, not a new message\n\n"
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&messages[1]),
+            "Subject: two\n\nBody\n"
+        );
+    }
+
+    #[test]
+    fn takes_a_message_with_one_link_whole() {
+        let entries = scan(
+            "Subject: [reading] #read
+Date: Sat, 17 Oct 2026 10:00:00 +0000
+
+Worth it:
+https://example.com/a.
+
+Really.
+-- 
+Me, https://example.com/me
+",
+        );
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].url.to_string(), "https://example.com/a");
+        assert_eq!(
+            entries[0].original_text,
+            "Worth it:\nhttps://example.com/a.\n\nReally."
+        );
+        assert_eq!(entries[0].state, ReadState::Read);
+        assert_eq!(
+            entries[0].source_date,
+            NaiveDate::from_ymd_opt(2026, 10, 17).unwrap()
+        );
+    }
+
+    #[test]
+    fn takes_each_paragraph_with_a_link() {
+        let entries = scan(
+            "Subject: Newsletter
+Date: Sat, 17 Oct 2026 10:00:00 +0000
+
+Hello.
+
+#tbr One: https://example.com/one
+
+Two: https://example.com/two
+",
+        );
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|e| (e.url.to_string(), e.state))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("https://example.com/one".to_owned(), ReadState::ToRead),
+                ("https://example.com/two".to_owned(), ReadState::Unknown),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_messages_without_links() {
+        assert!(scan(
+            "Subject: Hello
+Date: Sat, 17 Oct 2026 10:00:00 +0000
+
+No links here.
+"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn reads_html_without_a_plain_text_part() {
+        let entries = scan(
+            r#"Subject: Newsletter
+Date: Sat, 17 Oct 2026 10:00:00 +0000
+MIME-Version: 1.0
+Content-Type: multipart/alternative; boundary="b"
+
+--b
+Content-Type: text/html; charset=utf-8
+
+<html><head><style>a { color: red }</style></head><body>
+<p>First: <a href="https://example.com/one?a=1&amp;b=2">One &amp; only</a>.</p>
+<p>No link.</p>
+<div>Second: <a href="https://example.com/two"><b>Two</b></a></div>
+</body></html>
+--b--
+"#,
+        );
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|e| (e.url.to_string(), e.body_text.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (
+                    "https://example.com/one?a=1&b=2".to_owned(),
+                    "First: [One & only](https://example.com/one?a=1&b=2)."
+                ),
+                (
+                    "https://example.com/two".to_owned(),
+                    "Second: [Two](https://example.com/two)"
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use rusqlite::{Connection, OptionalExtension};
+
+/// Schema migrations, in order.
+/// The database's `user_version` records how many of these have been applied.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/0001-initial.sql"),
+    include_str!("../migrations/0002-read-state.sql"),
+    include_str!("../migrations/0003-publications.sql"),
+    include_str!("../migrations/0004-users.sql"),
+    include_str!("../migrations/0005-owners.sql"),
+    include_str!("../migrations/0006-admins.sql"),
+    include_str!("../migrations/0007-kinds.sql"),
+    include_str!("../migrations/0008-published-articles.sql"),
+    include_str!("../migrations/0009-transition-times.sql"),
+];
+
+/// Bring the database schema up to date.
+pub fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    // Migrations may rebuild tables that others refer to, so check foreign keys
+    // once they're all applied, rather than as each table is dropped.
+    // This can't be changed within a transaction.
+    let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let result = apply(conn);
+    conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+    result
+}
+
+fn apply(conn: &mut Connection) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    let version: usize = tx.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        tracing::info!("applying schema migration {}", i + 1);
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+    }
+    let violation: Option<String> = tx
+        .query_row("PRAGMA foreign_key_check", [], |row| row.get(0))
+        .optional()?;
+    if let Some(table) = violation {
+        tracing::error!("schema migration left dangling references in {table}");
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY),
+            Some(format!("foreign key violation in {table}")),
+        ));
+    }
+    tx.commit()
+}
@@ -0,0 +1,393 @@
+//! Where journals are read from: a directory, a git repository at a revision,
+//! or a tar or zip archive, e.g. a backup.
+
+use std::{
+    ffi::OsStr,
+    fs::{read_dir, File},
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{scan_text, RoundupError, RoundupErrorKind, ScanOptions, ScanReport};
+
+/// A journal: files named by date, to scan for reading-list entries.
+pub trait JournalSource {
+    /// Pass each file in the journal that the options include to `visit`,
+    /// with its contents. Problems reading the journal are added to `errors`.
+    fn read_files(
+        &self,
+        options: &ScanOptions,
+        visit: &mut dyn FnMut(PathBuf, Vec<u8>),
+        errors: &mut Vec<RoundupError>,
+    );
+
+    /// The directory the journal is read straight from, where files can be added to it.
+    /// Archives and git revisions have none.
+    fn directory(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Scan all the files in the journal, and collect their reading-list entries and errors.
+    fn scan(&self, options: &ScanOptions) -> ScanReport {
+        let mut report = ScanReport::default();
+        let mut errors = Vec::new();
+        self.read_files(
+            options,
+            &mut |file, contents| {
+                report.files += 1;
+                let text = String::from_utf8(contents).map_err(|e| {
+                    RoundupErrorKind::ScanIOError(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        e,
+                    ))
+                });
+                match text.and_then(|text| scan_text(&file, &text)) {
+                    Ok(mut v) => report.entries.append(&mut v),
+                    Err(kind) => report.errors.push(RoundupError { file, kind }),
+                }
+            },
+            &mut errors,
+        );
+        report.errors.append(&mut errors);
+        report
+    }
+}
+
+/// The source for a journal at `path`: a `.tar`, `.tar.gz`, `.tgz`, or `.zip` archive;
+/// a git repository, if the options give a revision to read; or else a directory.
+pub fn journal_source(path: &Path, options: &ScanOptions) -> Box<dyn JournalSource> {
+    if is_archive(path) {
+        return match extension(path) {
+            ".zip" => Box::new(ZipArchive(path.to_owned())),
+            _ => Box::new(TarArchive(path.to_owned())),
+        };
+    }
+    match &options.revision {
+        Some(revision) => Box::new(GitRevision {
+            repo: path.to_owned(),
+            revision: revision.clone(),
+        }),
+        None => Box::new(Directory(path.to_owned())),
+    }
+}
+
+const ARCHIVE_EXTENSIONS: [&str; 4] = [".tar", ".tar.gz", ".tgz", ".zip"];
+
+/// Whether the journal at `path` is an archive, rather than a directory.
+pub fn is_archive(path: &Path) -> bool {
+    !extension(path).is_empty()
+}
+
+/// Which of the archive extensions the path has, or `""`.
+fn extension(path: &Path) -> &'static str {
+    let name = path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+    ARCHIVE_EXTENSIONS
+        .into_iter()
+        .find(|ext| name.ends_with(ext))
+        .unwrap_or_default()
+}
+
+/// A directory of journal files, and its subdirectories.
+#[derive(Debug, Clone)]
+pub struct Directory(pub PathBuf);
+
+impl JournalSource for Directory {
+    fn directory(&self) -> Option<&Path> {
+        Some(&self.0)
+    }
+
+    fn read_files(
+        &self,
+        options: &ScanOptions,
+        visit: &mut dyn FnMut(PathBuf, Vec<u8>),
+        errors: &mut Vec<RoundupError>,
+    ) {
+        let mut dir_stack = vec![self.0.clone()];
+        while let Some(dir) = dir_stack.pop() {
+            tracing::debug!("visiting directory {}", dir.display());
+            let it = match read_dir(&dir) {
+                Ok(it) => it,
+                Err(e) => {
+                    errors.push(RoundupError {
+                        file: dir.clone(),
+                        kind: RoundupErrorKind::StatIOError(e),
+                    });
+                    continue;
+                }
+            };
+            for direntry in it {
+                let (path, metadata) =
+                    match direntry.and_then(|v| v.metadata().map(|md| (v.path(), md))) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            errors.push(RoundupError {
+                                file: dir.clone(),
+                                kind: RoundupErrorKind::StatIOError(e),
+                            });
+                            continue;
+                        }
+                    };
+                tracing::debug!(
+                    "visiting path {} (directory: {})",
+                    path.display(),
+                    metadata.is_dir()
+                );
+                if options.excludes(&path) {
+                    continue;
+                } else if metadata.is_dir() {
+                    dir_stack.push(path);
+                } else if options.includes(&path) {
+                    match std::fs::read(&path) {
+                        Ok(contents) => visit(path, contents),
+                        Err(e) => errors.push(RoundupError {
+                            file: path,
+                            kind: RoundupErrorKind::ScanIOError(e),
+                        }),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The journal as committed to a git repository at a revision (e.g. `HEAD` or `main`),
+/// read without checking it out.
+#[derive(Debug, Clone)]
+pub struct GitRevision {
+    pub repo: PathBuf,
+    pub revision: String,
+}
+
+impl JournalSource for GitRevision {
+    fn read_files(
+        &self,
+        options: &ScanOptions,
+        visit: &mut dyn FnMut(PathBuf, Vec<u8>),
+        errors: &mut Vec<RoundupError>,
+    ) {
+        let label = PathBuf::from(format!("{}@{}", self.repo.display(), self.revision));
+        let error = |kind| RoundupError {
+            file: label.clone(),
+            kind,
+        };
+        let child = Command::new("git")
+            .arg("-C")
+            .arg(&self.repo)
+            .args(["archive", "--format=tar", &self.revision])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(v) => v,
+            Err(e) => return errors.push(error(RoundupErrorKind::ScanIOError(e))),
+        };
+        if let Some(stdout) = child.stdout.take() {
+            read_tar(stdout, &label, options, visit, errors);
+        }
+        match child.wait_with_output() {
+            Ok(output) if !output.status.success() => {
+                errors.push(error(RoundupErrorKind::GitError(
+                    String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+                )))
+            }
+            Ok(_) => (),
+            Err(e) => errors.push(error(RoundupErrorKind::ScanIOError(e))),
+        }
+    }
+}
+
+/// A tar archive of the journal, optionally gzipped.
+#[derive(Debug, Clone)]
+pub struct TarArchive(pub PathBuf);
+
+impl JournalSource for TarArchive {
+    fn read_files(
+        &self,
+        options: &ScanOptions,
+        visit: &mut dyn FnMut(PathBuf, Vec<u8>),
+        errors: &mut Vec<RoundupError>,
+    ) {
+        let file = match File::open(&self.0) {
+            Ok(v) => v,
+            Err(e) => {
+                return errors.push(RoundupError {
+                    file: self.0.clone(),
+                    kind: RoundupErrorKind::StatIOError(e),
+                })
+            }
+        };
+        if extension(&self.0) == ".tar" {
+            read_tar(file, &self.0, options, visit, errors);
+        } else {
+            let gz = flate2::read::GzDecoder::new(file);
+            read_tar(gz, &self.0, options, visit, errors);
+        }
+    }
+}
+
+/// Read the files in a tar stream, naming them as if the archive were a directory.
+fn read_tar(
+    reader: impl Read,
+    archive: &Path,
+    options: &ScanOptions,
+    visit: &mut dyn FnMut(PathBuf, Vec<u8>),
+    errors: &mut Vec<RoundupError>,
+) {
+    let error = |kind| RoundupError {
+        file: archive.to_owned(),
+        kind,
+    };
+    let mut tar = tar::Archive::new(reader);
+    let entries = match tar.entries() {
+        Ok(v) => v,
+        Err(e) => return errors.push(error(RoundupErrorKind::ScanIOError(e))),
+    };
+    for entry in entries {
+        let mut entry = match entry {
+            Ok(v) => v,
+            Err(e) => return errors.push(error(RoundupErrorKind::ScanIOError(e))),
+        };
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Ok(path) = entry.path().map(|p| p.into_owned()) else {
+            continue;
+        };
+        if !options.includes_in_archive(&path) {
+            continue;
+        }
+        let mut contents = Vec::new();
+        match entry.read_to_end(&mut contents) {
+            Ok(_) => visit(archive.join(path), contents),
+            Err(e) => errors.push(RoundupError {
+                file: archive.join(path),
+                kind: RoundupErrorKind::ScanIOError(e),
+            }),
+        }
+    }
+}
+
+/// A zip archive of the journal.
+#[derive(Debug, Clone)]
+pub struct ZipArchive(pub PathBuf);
+
+impl JournalSource for ZipArchive {
+    fn read_files(
+        &self,
+        options: &ScanOptions,
+        visit: &mut dyn FnMut(PathBuf, Vec<u8>),
+        errors: &mut Vec<RoundupError>,
+    ) {
+        let error = |kind| RoundupError {
+            file: self.0.clone(),
+            kind,
+        };
+        let zip = File::open(&self.0)
+            .map_err(RoundupErrorKind::StatIOError)
+            .and_then(|file| {
+                zip::ZipArchive::new(file)
+                    .map_err(|e| RoundupErrorKind::ArchiveError(e.to_string()))
+            });
+        let mut zip = match zip {
+            Ok(v) => v,
+            Err(kind) => return errors.push(error(kind)),
+        };
+        for i in 0..zip.len() {
+            let mut entry = match zip.by_index(i) {
+                Ok(v) => v,
+                Err(e) => {
+                    errors.push(error(RoundupErrorKind::ArchiveError(e.to_string())));
+                    continue;
+                }
+            };
+            let Some(path) = entry.enclosed_name().filter(|_| !entry.is_dir()) else {
+                continue;
+            };
+            if !options.includes_in_archive(&path) {
+                continue;
+            }
+            let mut contents = Vec::new();
+            match entry.read_to_end(&mut contents) {
+                Ok(_) => visit(self.0.join(path), contents),
+                Err(e) => errors.push(RoundupError {
+                    file: self.0.join(path),
+                    kind: RoundupErrorKind::ScanIOError(e),
+                }),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chooses_the_source_by_path_and_revision() {
+        let mut options = ScanOptions::default();
+        let dir = Path::new("journal");
+        assert_eq!(journal_source(dir, &options).directory(), Some(dir));
+        for archive in [
+            "journal.tar",
+            "journal.tar.gz",
+            "journal.tgz",
+            "journal.zip",
+        ] {
+            assert!(is_archive(Path::new(archive)), "{archive}");
+            assert_eq!(
+                journal_source(Path::new(archive), &options).directory(),
+                None
+            );
+        }
+        assert!(!is_archive(Path::new("journal.gz")));
+        options.revision = Some("HEAD".to_owned());
+        assert_eq!(journal_source(dir, &options).directory(), None);
+    }
+
+    #[test]
+    fn reads_included_files_from_tar() {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, text) in [
+            (
+                "journal/2024/2024-03-05.md",
+                "- #tbr https://example.com/a\n",
+            ),
+            (
+                "journal/drafts/2024-03-06.md",
+                "- #tbr https://example.com/b\n",
+            ),
+            ("journal/notes.txt", "not scanned\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(text.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, text.as_bytes())
+                .unwrap();
+        }
+        let tar = builder.into_inner().unwrap();
+        let options = ScanOptions {
+            exclude: vec!["drafts".to_owned()],
+            ..ScanOptions::default()
+        };
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        read_tar(
+            tar.as_slice(),
+            Path::new("backup.tar"),
+            &options,
+            &mut |path, contents| files.push((path, contents)),
+            &mut errors,
+        );
+        assert!(errors.is_empty());
+        assert_eq!(
+            files,
+            [(
+                PathBuf::from("backup.tar/journal/2024/2024-03-05.md"),
+                b"- #tbr https://example.com/a\n".to_vec()
+            )]
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! Settings file for the server.
+//!
+//! Relative paths in the file are relative to the directory containing it.
+
+use std::{
+    net::ToSocketAddrs,
+    path::{Path, PathBuf},
+};
+
+use reading::{ReadState, RoundupPolicy};
+use roundup::{mail::Mailbox, ScanOptions};
+use serde::{Deserialize, Deserializer};
+
+use crate::listen;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Path of the database file.
+    pub db: Option<PathBuf>,
+    /// Journal to scan for users without their own.
+    pub journal: Option<PathBuf>,
+    /// Serve only the read-only site of published roundups.
+    #[serde(default)]
+    pub public: bool,
+    /// Public URL of the site serving roundups, for links in feeds; without it, there are none.
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub listen: Listen,
+    #[serde(default)]
+    pub scan: Scan,
+    #[serde(default)]
+    pub mail: Mail,
+    #[serde(default)]
+    pub roundup: Roundup,
+    #[serde(default)]
+    pub export: Export,
+    #[serde(default)]
+    pub publish: Publish,
+    #[serde(default)]
+    pub backup: Backup,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Listen {
+    /// TCP addresses and ports to listen on.
+    #[serde(default)]
+    pub tcp: Vec<String>,
+    /// Paths of Unix sockets to listen on.
+    #[serde(default)]
+    pub unix: Vec<PathBuf>,
+    /// Permissions for the Unix sockets, e.g. `0o660`.
+    pub unix_mode: Option<u32>,
+    pub unix_owner: Option<String>,
+    pub unix_group: Option<String>,
+    /// Seconds without requests after which to exit.
+    pub idle_timeout: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scan {
+    /// Extensions of journal files to scan.
+    pub extensions: Option<Vec<String>>,
+    /// Names of files and directories in journals to skip.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Git revision to read journals at, rather than their working trees.
+    pub revision: Option<String>,
+}
+
+impl From<Scan> for ScanOptions {
+    fn from(scan: Scan) -> Self {
+        let default = ScanOptions::default();
+        ScanOptions {
+            extensions: scan.extensions.unwrap_or(default.extensions),
+            exclude: scan.exclude,
+            revision: scan.revision,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mail {
+    /// Maildir or mbox file to scan along with the journal.
+    pub path: Option<PathBuf>,
+    /// Addresses whose messages to take links from.
+    #[serde(default)]
+    pub to: Vec<String>,
+    /// Subject tags of messages to take links from.
+    #[serde(default)]
+    pub subject_tags: Vec<String>,
+}
+
+impl Mail {
+    pub fn into_mailbox(self) -> Option<Mailbox> {
+        Some(Mailbox {
+            path: self.path?,
+            to: self.to,
+            subject_tags: self.subject_tags,
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Roundup {
+    /// States an article must be in to be added to a roundup.
+    #[serde(default, deserialize_with = "read_states")]
+    pub include: Option<Vec<ReadState>>,
+}
+
+impl From<Roundup> for RoundupPolicy {
+    fn from(roundup: Roundup) -> Self {
+        match roundup.include {
+            Some(include) => RoundupPolicy { include },
+            None => RoundupPolicy::default(),
+        }
+    }
+}
+
+fn read_states<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<ReadState>>, D::Error> {
+    Vec::<String>::deserialize(d)?
+        .iter()
+        .map(|s| s.parse().map_err(serde::de::Error::custom))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Export {
+    /// Template for Markdown exports of roundups.
+    pub markdown_template: Option<PathBuf>,
+    /// Page for books journaled by ISBN, with `{isbn}` in place of the ISBN.
+    pub book_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Publish {
+    /// Content directory of a static site to publish roundups into.
+    pub dir: Option<PathBuf>,
+    #[serde(default)]
+    pub bundle: bool,
+    /// Path of each roundup within `dir`, with `{user}` and `{date}` replaced.
+    pub path: Option<String>,
+    #[serde(default)]
+    pub git_commit: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Backup {
+    /// Directory to back up the database into while serving.
+    pub dir: Option<PathBuf>,
+    /// Hours between backups.
+    pub every_hours: Option<u64>,
+    /// How many backups to keep.
+    pub keep: Option<usize>,
+}
+
+impl Config {
+    /// Read and parse the file.
+    /// Errors name the file, and the line and key at fault.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        let mut config: Config =
+            toml::from_str(&text).map_err(|e| format!("in {}: {e}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        config.resolve_paths(base);
+        Ok(config)
+    }
+
+    fn resolve_paths(&mut self, base: &Path) {
+        let paths = [
+            self.db.as_mut(),
+            self.journal.as_mut(),
+            self.mail.path.as_mut(),
+            self.export.markdown_template.as_mut(),
+            self.publish.dir.as_mut(),
+            self.backup.dir.as_mut(),
+        ];
+        for path in paths.into_iter().flatten().chain(&mut self.listen.unix) {
+            if path.is_relative() {
+                *path = base.join(&*path);
+            }
+        }
+    }
+
+    /// Check the settings make sense on this system, e.g. that the files they name exist.
+    /// Returns a description of each problem.
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        match &self.db {
+            None => problems.push("db: not set".to_owned()),
+            Some(db) => check_parent(&mut problems, "db", db),
+        }
+        if let Some(journal) = &self.journal {
+            if roundup::source::is_archive(journal) {
+                if let Err(e) = std::fs::metadata(journal) {
+                    problems.push(format!("journal: {}: {e}", journal.display()));
+                }
+            } else {
+                check_dir(&mut problems, "journal", journal);
+            }
+        }
+        if let Some(path) = &self.mail.path {
+            if let Err(e) = std::fs::metadata(path) {
+                problems.push(format!("mail.path: {}: {e}", path.display()));
+            } else if path.is_dir() && !path.join("cur").is_dir() {
+                problems.push(format!("mail.path: {} is not a Maildir", path.display()));
+            }
+        }
+        if let Some(url) = &self.export.book_url {
+            let example = url.replace("{isbn}", "9780262046305");
+            if !url.contains("{isbn}") {
+                problems.push(format!("export.book_url: {url:?} has no {{isbn}} in it"));
+            } else if !(example.starts_with("https://") || example.starts_with("http://"))
+                || example.parse::<axum::http::Uri>().is_err()
+            {
+                problems.push(format!(
+                    "export.book_url: {url:?} is not an http or https URL"
+                ));
+            }
+        }
+        if let Some(url) = &self.base_url {
+            if !(url.starts_with("https://") || url.starts_with("http://"))
+                || url.parse::<axum::http::Uri>().is_err()
+            {
+                problems.push(format!("base_url: {url:?} is not an http or https URL"));
+            }
+        }
+        for addr in &self.listen.tcp {
+            if let Err(e) = addr.to_socket_addrs() {
+                problems.push(format!("listen.tcp: {addr:?}: {e}"));
+            }
+        }
+        for path in &self.listen.unix {
+            check_parent(&mut problems, "listen.unix", path);
+        }
+        if self.listen.unix_mode.is_some_and(|v| v > 0o7777) {
+            problems.push("listen.unix_mode: not a file mode".to_owned());
+        }
+        if let Some(Err(e)) = self.listen.unix_owner.as_deref().map(listen::uid) {
+            problems.push(format!("listen.unix_owner: {e}"));
+        }
+        if let Some(Err(e)) = self.listen.unix_group.as_deref().map(listen::gid) {
+            problems.push(format!("listen.unix_group: {e}"));
+        }
+        if let Some(template) = &self.export.markdown_template {
+            if let Err(e) = std::fs::metadata(template) {
+                problems.push(format!(
+                    "export.markdown_template: {}: {e}",
+                    template.display()
+                ));
+            }
+        }
+        if let Some(dir) = &self.publish.dir {
+            check_dir(&mut problems, "publish.dir", dir);
+        }
+        if let Some(path) = &self.publish.path {
+            if !path.contains("{date}") {
+                problems.push(format!("publish.path: {path:?} has no {{date}} in it"));
+            } else if Path::new(path).is_absolute() || path.split('/').any(|c| c == "..") {
+                problems.push(format!(
+                    "publish.path: {path:?} is not within the publish directory"
+                ));
+            }
+        }
+        if let Some(dir) = &self.backup.dir {
+            check_dir(&mut problems, "backup.dir", dir);
+        }
+        if self.backup.every_hours == Some(0) {
+            problems.push("backup.every_hours: must be at least 1".to_owned());
+        }
+        problems
+    }
+}
+
+fn check_dir(problems: &mut Vec<String>, key: &str, path: &Path) {
+    if !path.is_dir() {
+        problems.push(format!("{key}: {} is not a directory", path.display()));
+    }
+}
+
+fn check_parent(problems: &mut Vec<String>, key: &str, path: &Path) {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => problems.push(format!(
+            "{key}: {} is not in an existing directory",
+            path.display()
+        )),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, text: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("config-test-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("reading.toml");
+        std::fs::write(&path, text).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn resolves_paths_relative_to_the_file() {
+        let (dir, path) = write_config(
+            "paths",
+            r#"
+            db = "reading.db"
+            journal = "/home/me/journal"
+
+            [listen]
+            unix = ["run/http.sock"]
+
+            [export]
+            markdown_template = "roundup.md.j2"
+
+            [publish]
+            dir = "../site/content"
+            "#,
+        );
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.db, Some(dir.join("reading.db")));
+        assert_eq!(config.journal, Some(PathBuf::from("/home/me/journal")));
+        assert_eq!(config.listen.unix, [dir.join("run/http.sock")]);
+        assert_eq!(
+            config.export.markdown_template,
+            Some(dir.join("roundup.md.j2"))
+        );
+        assert_eq!(config.publish.dir, Some(dir.join("../site/content")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        for (name, text, key) in [
+            ("top", "dbb = \"reading.db\"", "dbb"),
+            ("table", "[publish]\ndirectory = \"site\"", "directory"),
+            (
+                "moved",
+                "[scan]\nbook_url = \"https://example.com/{isbn}\"",
+                "book_url",
+            ),
+        ] {
+            let (dir, path) = write_config(name, text);
+            let e = Config::load(&path).unwrap_err();
+            assert!(e.starts_with(&format!("in {}", path.display())), "{e}");
+            assert!(e.contains(&format!("unknown field `{key}`")), "{e}");
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Starting and stopping: signals, idle exit, and systemd notifications.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use sd_notify::NotifyState;
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Requests in flight, and when the last one finished.
+pub struct Activity {
+    in_flight: AtomicUsize,
+    last: Mutex<Instant>,
+}
+
+impl Activity {
+    pub fn new() -> Activity {
+        Activity {
+            in_flight: AtomicUsize::new(0),
+            last: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Count a request as in flight until the guard is dropped.
+    pub fn start(self: &Arc<Self>) -> RequestGuard {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        RequestGuard(self.clone())
+    }
+
+    /// How long there have been no requests, or None if some are in flight.
+    fn idle_for(&self) -> Option<Duration> {
+        if self.in_flight.load(Ordering::SeqCst) > 0 {
+            return None;
+        }
+        Some(self.last.lock().unwrap().elapsed())
+    }
+}
+
+pub struct RequestGuard(Arc<Activity>);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        *self.0.last.lock().unwrap() = Instant::now();
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Wait for SIGTERM or SIGINT, returning its name.
+pub async fn stop_signal() -> &'static str {
+    let mut terminate = signal(SignalKind::terminate()).expect("could not handle SIGTERM");
+    let mut interrupt = signal(SignalKind::interrupt()).expect("could not handle SIGINT");
+    tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    }
+}
+
+/// Wait until there have been no requests for `timeout`; forever if it's None.
+pub async fn idle(activity: &Activity, timeout: Option<Duration>) {
+    let Some(timeout) = timeout else {
+        return std::future::pending().await;
+    };
+    let mut interval = tokio::time::interval(timeout.min(Duration::from_secs(1)));
+    loop {
+        interval.tick().await;
+        if activity.idle_for().is_some_and(|idle| idle >= timeout) {
+            return;
+        }
+    }
+}
+
+/// Tell systemd (if it started us) that we're ready, and start pinging its watchdog
+/// if the unit has `WatchdogSec=` set.
+pub fn notify_ready() {
+    notify(&[NotifyState::Ready]);
+    let mut usec = 0;
+    if sd_notify::watchdog_enabled(false, &mut usec) {
+        let period = Duration::from_micros(usec) / 2;
+        tracing::debug!("pinging the systemd watchdog every {period:?}");
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                notify(&[NotifyState::Watchdog]);
+            }
+        });
+    }
+}
+
+/// Tell systemd (if it started us) that we're shutting down.
+pub fn notify_stopping() {
+    notify(&[NotifyState::Stopping]);
+}
+
+fn notify(state: &[NotifyState]) {
+    // Does nothing when not run by systemd.
+    if let Err(e) = sd_notify::notify(false, state) {
+        tracing::warn!("could not notify systemd: {e}");
+    }
+}
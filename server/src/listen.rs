@@ -0,0 +1,315 @@
+//! Sockets to serve on: TCP, Unix domain sockets, and those passed in by systemd.
+
+use std::{
+    io,
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::{http::Request, Router};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto, graceful::GracefulShutdown},
+};
+use listenfd::ListenFd;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, UnixListener},
+    sync::watch,
+};
+use tower::Service;
+
+use crate::lifecycle::Activity;
+
+/// A socket accepting connections.
+pub enum Listener {
+    Tcp(TcpListener),
+    /// With the socket file, if we created it, which is removed when the listener is dropped.
+    Unix(UnixListener, Option<SocketFile>),
+}
+
+/// A socket file we created, removed when dropped: when the server is done with it, or
+/// if starting up fails after it was made.
+pub struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            tracing::warn!("could not remove {}: {e}", self.0.display());
+        }
+    }
+}
+
+impl std::fmt::Display for Listener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Listener::Tcp(l) => match l.local_addr() {
+                Ok(addr) => write!(f, "TCP {addr}"),
+                Err(_) => write!(f, "TCP socket"),
+            },
+            Listener::Unix(_, Some(file)) => write!(f, "Unix socket {}", file.0.display()),
+            Listener::Unix(l, None) => match l
+                .local_addr()
+                .ok()
+                .and_then(|a| a.as_pathname().map(Path::to_owned))
+            {
+                Some(path) => write!(f, "Unix socket {}", path.display()),
+                None => write!(f, "Unix socket"),
+            },
+        }
+    }
+}
+
+/// Permissions to give a Unix socket we create.
+#[derive(Debug, Default, Clone)]
+pub struct UnixSocketOptions {
+    /// File mode, e.g. 0o660.
+    pub mode: Option<u32>,
+    /// User to own the socket, by name or ID.
+    pub owner: Option<String>,
+    /// Group to own the socket, by name or ID.
+    pub group: Option<String>,
+}
+
+/// Listen on a TCP address, e.g. `127.0.0.1:3000`.
+pub async fn bind_tcp(addr: &str) -> io::Result<Listener> {
+    Ok(Listener::Tcp(TcpListener::bind(addr).await?))
+}
+
+/// Listen on a Unix socket at the path.
+///
+/// Replaces a socket left behind by a server that is no longer running;
+/// refuses to replace one that is still accepting connections, or anything that isn't a socket.
+pub fn bind_unix(path: &Path, options: &UnixSocketOptions) -> io::Result<Listener> {
+    remove_stale_socket(path)?;
+    // Bind in a directory only we can get into, and set the socket's permissions there,
+    // so that nobody can connect before they're set; then move it into place.
+    let parent = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let private = parent.join(format!(".{name}.{}", std::process::id()));
+    std::fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let tmp = private.join("socket");
+    let result = bind_private(&tmp, path, options);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    let _ = std::fs::remove_dir(&private);
+    Ok(Listener::Unix(result?, Some(SocketFile(path.to_owned()))))
+}
+
+/// Bind at `tmp`, give the socket its permissions, and move it to `path`.
+fn bind_private(tmp: &Path, path: &Path, options: &UnixSocketOptions) -> io::Result<UnixListener> {
+    let listener = UnixListener::bind(tmp)?;
+    if let Some(mode) = options.mode {
+        std::fs::set_permissions(tmp, std::fs::Permissions::from_mode(mode))?;
+    }
+    if options.owner.is_some() || options.group.is_some() {
+        let uid = options.owner.as_deref().map(uid).transpose()?;
+        let gid = options.group.as_deref().map(gid).transpose()?;
+        std::os::unix::fs::chown(tmp, uid, gid)?;
+    }
+    std::fs::rename(tmp, path)?;
+    Ok(listener)
+}
+
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+    match std::os::unix::net::UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("another server is listening on {}", path.display()),
+        )),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            tracing::info!("removing stale socket {}", path.display());
+            std::fs::remove_file(path)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Resolve a user name or ID.
+pub fn uid(user: &str) -> io::Result<u32> {
+    if let Ok(id) = user.parse() {
+        return Ok(id);
+    }
+    nix::unistd::User::from_name(user)?
+        .map(|u| u.uid.as_raw())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no such user: {user}")))
+}
+
+/// Resolve a group name or ID.
+pub fn gid(group: &str) -> io::Result<u32> {
+    if let Ok(id) = group.parse() {
+        return Ok(id);
+    }
+    nix::unistd::Group::from_name(group)?
+        .map(|g| g.gid.as_raw())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no such group: {group}")))
+}
+
+/// Take the sockets passed in with the sd_listen_fds protocol.
+pub fn from_env() -> io::Result<Vec<Listener>> {
+    let mut listenfd = ListenFd::from_env();
+    let mut listeners = Vec::new();
+    for i in 0..listenfd.len() {
+        // Taking a socket as the wrong type fails, and leaves it to try as another.
+        if let Ok(Some(v)) = listenfd.take_tcp_listener(i) {
+            v.set_nonblocking(true)?;
+            listeners.push(Listener::Tcp(TcpListener::from_std(v)?));
+        } else if let Some(v) = listenfd.take_unix_listener(i)? {
+            v.set_nonblocking(true)?;
+            listeners.push(Listener::Unix(UnixListener::from_std(v)?, None));
+        }
+    }
+    Ok(listeners)
+}
+
+/// Serve the app on the listener until `shutdown` changes or accepting fails.
+///
+/// Returns a handle for waiting on the connections still open.
+pub async fn serve(
+    listener: Listener,
+    app: Router,
+    mut shutdown: watch::Receiver<bool>,
+    activity: Arc<Activity>,
+) -> GracefulShutdown {
+    tracing::info!("listening on {listener}");
+    let graceful = GracefulShutdown::new();
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let result = match &listener {
+        Listener::Tcp(l) => loop {
+            let (socket, remote_addr) = tokio::select! {
+                accepted = l.accept() => match accepted {
+                    Ok(v) => v,
+                    Err(e) => break Err(e),
+                },
+                _ = shutdown.changed() => break Ok(()),
+            };
+            tracing::debug!("got a connection from {remote_addr}");
+            spawn_connection(socket, app.clone(), &builder, &graceful, activity.clone());
+        },
+        Listener::Unix(l, _) => loop {
+            let (socket, remote_addr) = tokio::select! {
+                accepted = l.accept() => match accepted {
+                    Ok(v) => v,
+                    Err(e) => break Err(e),
+                },
+                _ = shutdown.changed() => break Ok(()),
+            };
+            tracing::debug!("got a connection from {remote_addr:?}");
+            spawn_connection(socket, app.clone(), &builder, &graceful, activity.clone());
+        },
+    };
+    if let Err(e) = result {
+        tracing::error!("stopped accepting connections on {listener}: {e}");
+    }
+    // Dropping the listener removes its socket file.
+    drop(listener);
+    graceful
+}
+
+/// Serve HTTP on the connection, in a new task.
+fn spawn_connection<S>(
+    socket: S,
+    app: Router,
+    builder: &auto::Builder<TokioExecutor>,
+    graceful: &GracefulShutdown,
+    activity: Arc<Activity>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let hyper_service = hyper::service::service_fn(move |request: Request<Incoming>| {
+        let request_guard = activity.start();
+        let response = app.clone().call(request);
+        async move {
+            let response = response.await;
+            drop(request_guard);
+            response
+        }
+    });
+    let connection = builder
+        .serve_connection_with_upgrades(TokioIo::new(socket), hyper_service)
+        .into_owned();
+    let connection = graceful.watch(connection);
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            tracing::error!("failed to serve connection: {err:#}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("listen-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn removes_only_stale_sockets() {
+        let dir = temp_dir("stale");
+        let path = dir.join("http.sock");
+        remove_stale_socket(&path).unwrap();
+
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let e = remove_stale_socket(&path).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::AddrInUse);
+        assert!(path.exists());
+
+        // Closing a listener leaves its socket file behind, refusing connections.
+        drop(listener);
+        remove_stale_socket(&path).unwrap();
+        assert!(!path.exists());
+
+        std::fs::write(&path, "not a socket").unwrap();
+        let e = remove_stale_socket(&path).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+        assert!(path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn binds_sockets_with_their_mode() {
+        let dir = temp_dir("bind");
+        let path = dir.join("http.sock");
+        let options = UnixSocketOptions {
+            mode: Some(0o600),
+            ..UnixSocketOptions::default()
+        };
+        let listener = bind_unix(&path, &options).unwrap();
+        let metadata = std::fs::symlink_metadata(&path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o600);
+        // The directory it was bound in is gone.
+        let names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["http.sock"]);
+        std::os::unix::net::UnixStream::connect(&path).unwrap();
+
+        // Dropping the listener removes the socket.
+        drop(listener);
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
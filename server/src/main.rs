@@ -1,93 +1,1126 @@
-use clap::Parser;
-use listenfd::ListenFd;
-use std::path::PathBuf;
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+use config::Config;
+use listen::{Listener, UnixSocketOptions};
+use reading::{
+    auth::User,
+    export::{ExportOptions, Format, Roundup},
+    publish::PublishOptions,
+    ArticleFilter, ReadState,
+};
+use roundup::import::{ImportOptions, VisitRules};
+use std::{
+    io::{IsTerminal, Read},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+    time::Duration,
+};
+
+mod config;
+mod lifecycle;
+mod listen;
 
 #[derive(Parser, Debug)]
+#[command(version, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// With no subcommand (as in `server --db ... --journal ...`), run the server.
+    #[command(flatten)]
+    serve: ServeArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the web server.
+    Serve(Box<ServeArgs>),
+
+    /// Scan the journal for entries and add new ones to the database.
+    Scan {
+        #[command(flatten)]
+        db: DbArgs,
+
+        /// Path of the source files to search for new entries: a directory, or a .tar, .tar.gz,
+        /// .tgz, or .zip archive of one. Defaults to the user's journal, then the one in the config file.
+        #[arg(long, short = 'j')]
+        journal: Option<PathBuf>,
+
+        /// Settings file, for the journal, which files in it to scan, and mail to scan.
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+
+    /// Write the roundup for a date as a post.
+    ExportRoundup {
+        #[command(flatten)]
+        db: DbArgs,
+
+        /// Date of the roundup (YYYY-MM-DD).
+        date: NaiveDate,
+
+        /// Output format: markdown, html, jsonfeed, gemtext, or text.
+        #[arg(long, short = 'f', default_value = "markdown")]
+        format: Format,
+
+        /// Write to this file instead of stdout.
+        #[arg(long, short = 'o')]
+        output: Option<PathBuf>,
+
+        #[command(flatten)]
+        export: ExportArgs,
+
+        /// Public URL of the site serving roundups, to identify JSON Feed items by their pages.
+        #[arg(long)]
+        base_url: Option<String>,
+    },
+
+    /// List articles in the database, oldest first.
+    ListArticles {
+        #[command(flatten)]
+        db: DbArgs,
+
+        /// Only list articles in this state (e.g. to-read, read). May be repeated.
+        #[arg(long)]
+        state: Vec<ReadState>,
+
+        /// Only list articles journaled on or after this date.
+        #[arg(long)]
+        since: Option<NaiveDate>,
+
+        /// Only list articles journaled on or before this date.
+        #[arg(long)]
+        until: Option<NaiveDate>,
+
+        /// Only list articles that are in at least one roundup.
+        #[arg(long, conflicts_with = "not_in_roundup")]
+        in_roundup: bool,
+
+        /// Only list articles that are not in any roundup.
+        #[arg(long)]
+        not_in_roundup: bool,
+    },
+
+    /// Add an article from a Markdown snippet containing its link.
+    Add {
+        #[command(flatten)]
+        db: DbArgs,
+
+        /// Markdown text of the article; "-" to read from stdin.
+        markdown: String,
+
+        /// Date to file the article under; defaults to today.
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+
+    /// Add articles from another tool's export, e.g. browser bookmarks.
+    Import {
+        #[command(flatten)]
+        db: DbArgs,
+
+        /// Format of the export: netscape (bookmark HTML), pinboard (JSON),
+        /// pocket (CSV), instapaper (CSV); or a browser profile's firefox or
+        /// firefox-history (places.sqlite), chromium (Bookmarks), or chromium-history (History).
+        format: roundup::import::Format,
+
+        /// Path of the export file.
+        file: PathBuf,
+
+        /// List what would be added, without adding it.
+        #[arg(long, short = 'n')]
+        dry_run: bool,
+
+        /// Add the articles found in a browser profile, which are only listed otherwise.
+        #[arg(long, conflicts_with = "dry_run")]
+        apply: bool,
+
+        /// Only import bookmarks in this folder, at any depth, or with this tag.
+        /// May be repeated.
+        #[arg(long, value_name = "NAME")]
+        folder: Vec<String>,
+
+        /// Only import pages from the history visited at least this many times.
+        #[arg(long, value_name = "N", default_value_t = VisitRules::default().min_visits)]
+        min_visits: u32,
+
+        /// Only import pages from the history viewed for at least this long, over all visits.
+        #[arg(long, value_name = "SECONDS", default_value_t = 0)]
+        min_dwell: u64,
+
+        /// Give articles with this tag or folder this state (e.g. archive=read, or "read later=tbr").
+        /// Journal tags like tbr and read, and toread and unread, are recognized already.
+        /// May be repeated.
+        #[arg(long, value_name = "TAG=STATE", value_parser = parse_tag_state)]
+        tag: Vec<(String, ReadState)>,
+
+        /// State of articles that no tag or flag gives one to.
+        #[arg(long, default_value = "unknown")]
+        state: ReadState,
+    },
+
+    /// Manage accounts for the editor.
+    User {
+        #[command(subcommand)]
+        command: UserCommand,
+    },
+
+    /// Back up the database, even while the server is running.
+    Backup {
+        /// Path of the database file.
+        #[arg(long)]
+        db: PathBuf,
+
+        /// File to write the backup to; or a directory to write a timestamped backup into,
+        /// removing older ones there.
+        dest: PathBuf,
+
+        /// When backing up into a directory, how many backups to keep there.
+        #[arg(long, default_value_t = 7)]
+        keep: usize,
+    },
+
+    /// Replace the database with a backup, after checking the backup is intact.
+    /// Stop the server first. The replaced database is kept as <db>.before-restore.
+    Restore {
+        /// Path of the database file.
+        #[arg(long)]
+        db: PathBuf,
+
+        /// Backup to restore.
+        backup: PathBuf,
+    },
+
+    /// Work with settings files.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Check that a settings file is valid, and that the files it names exist.
+    Check {
+        /// Path of the settings file.
+        config: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum UserCommand {
+    /// Create an account.
+    /// The password is prompted for, or read from stdin if it is not a terminal.
+    /// The first account is an administrator, and takes over any articles and roundups
+    /// from before there were accounts.
+    Add {
+        /// Path of the database file.
+        #[arg(long)]
+        db: PathBuf,
+
+        /// Name to log in with.
+        name: String,
+
+        /// Journal to scan for the user's entries.
+        /// If unspecified, uses the server's --journal.
+        #[arg(long, short = 'j')]
+        journal: Option<PathBuf>,
+
+        /// Let the user download backups of the whole database.
+        #[arg(long)]
+        admin: bool,
+    },
+
+    /// Set the journal scanned for an account's entries.
+    SetJournal {
+        /// Path of the database file.
+        #[arg(long)]
+        db: PathBuf,
+
+        /// Name of the account.
+        name: String,
+
+        /// Journal to scan; if omitted, uses the server's --journal.
+        journal: Option<PathBuf>,
+    },
+
+    /// Create an API token for an account, for use as `Authorization: Bearer <token>`.
+    /// With --list or --revoke, list or revoke the account's tokens instead.
+    Token {
+        /// Path of the database file.
+        #[arg(long)]
+        db: PathBuf,
+
+        /// Name of the account.
+        name: String,
+
+        /// What the token is for.
+        #[arg(long, default_value = "")]
+        label: String,
+
+        /// List the account's tokens: number, when created, and label.
+        #[arg(long, conflicts_with_all = ["label", "revoke"])]
+        list: bool,
+
+        /// Revoke the token with this number, as listed by --list.
+        #[arg(long, value_name = "NUMBER", conflicts_with = "label")]
+        revoke: Option<isize>,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct DbArgs {
     /// Path of the database file.
     #[arg(long)]
     db: PathBuf,
 
-    /// Path of the source files to search for new entries.
+    /// Act on this user's articles and roundups.
+    /// May be omitted if the database has at most one user.
+    #[arg(long)]
+    user: Option<String>,
+}
+
+impl DbArgs {
+    /// Open the database, and find the user to act as.
+    fn open(&self) -> Result<(rusqlite::Connection, Option<User>), reading::Error> {
+        let conn = reading::open(&self.db)?;
+        let user = reading::auth::resolve_user(&conn, self.user.as_deref())?;
+        Ok((conn, user))
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct ExportArgs {
+    /// Template for Markdown exports of roundups.
+    /// If unspecified, uses the built-in template.
+    #[arg(long)]
+    markdown_template: Option<PathBuf>,
+
+    /// Page that books journaled by ISBN link to, with `{isbn}` in place of the ISBN.
+    /// If unspecified, links to Open Library.
+    #[arg(long, value_name = "URL")]
+    book_url: Option<String>,
+}
+
+impl From<ExportArgs> for ExportOptions {
+    fn from(args: ExportArgs) -> Self {
+        let default = ExportOptions::default();
+        ExportOptions {
+            markdown_template: args.markdown_template,
+            book_url: args.book_url.unwrap_or(default.book_url),
+            base_url: None,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    /// Settings file (TOML). Flags given on the command line take precedence over it.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Path of the database file. Required, here or in the config file.
+    #[arg(long)]
+    db: Option<PathBuf>,
+
+    /// Path of the source files to search for new entries (a directory, or an archive of one),
+    /// for users without their own journal.
     #[arg(long, short = 'j')]
-    journal: PathBuf,
+    journal: Option<PathBuf>,
+
+    /// Serve only the read-only site of published roundups, without the editor.
+    #[arg(long, overrides_with = "no_public")]
+    public: bool,
 
-    /// Bind to this address and TCP port (e.g. 0.0.0.0:3000).
-    /// If unspecified (default), listen using the sd_listen_fd protocol.
+    /// Serve the editor, even if the config file sets `public`.
+    #[arg(long, overrides_with = "public")]
+    no_public: bool,
+
+    /// Bind to this address and TCP port (e.g. 127.0.0.1:3000). May be repeated.
+    /// If no sockets are given here or in the config file, listen using the sd_listen_fd protocol.
     #[arg(long, short = 'l')]
-    bind_pattern: Option<String>,
+    bind_pattern: Vec<String>,
+
+    #[command(flatten)]
+    unix_socket: UnixSocketArgs,
+
+    /// Exit after this many seconds without requests, e.g. when started by socket
+    /// activation, which will start the server again on the next connection.
+    #[arg(long, value_name = "SECONDS")]
+    idle_timeout: Option<u64>,
+
+    #[command(flatten)]
+    export: ExportArgs,
+
+    #[command(flatten)]
+    publish: PublishArgs,
+
+    #[command(flatten)]
+    backup: BackupArgs,
+
+    /// Public URL of the site serving roundups (e.g. https://example.com/reading),
+    /// for links in feeds. If unspecified, feeds are not served.
+    #[arg(long)]
+    base_url: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct UnixSocketArgs {
+    /// Listen on a Unix domain socket at this path. May be repeated.
+    /// A socket left behind by a server that is no longer running is replaced.
+    #[arg(long)]
+    unix_socket: Vec<PathBuf>,
+
+    /// Permissions for the Unix sockets, in octal (e.g. 660).
+    #[arg(long, value_parser = parse_mode)]
+    unix_socket_mode: Option<u32>,
+
+    /// User to own the Unix sockets, by name or ID.
+    #[arg(long)]
+    unix_socket_owner: Option<String>,
+
+    /// Group to own the Unix sockets, by name or ID (e.g. that of the reverse proxy).
+    #[arg(long)]
+    unix_socket_group: Option<String>,
+}
+
+fn parse_tag_state(s: &str) -> Result<(String, ReadState), String> {
+    let (tag, state) = s
+        .split_once('=')
+        .ok_or_else(|| format!("{s:?} is not TAG=STATE"))?;
+    // Accept the journal's tags for states, too.
+    let state = match ReadState::from_tag(state) {
+        Some(v) => v,
+        None => state.parse().map_err(|e| format!("{e}"))?,
+    };
+    Ok((tag.to_lowercase(), state))
+}
+
+fn parse_mode(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s, 8)
+        .ok()
+        .filter(|v| *v <= 0o7777)
+        .ok_or_else(|| format!("{s:?} is not an octal file mode"))
+}
+
+#[derive(clap::Args, Debug)]
+struct PublishArgs {
+    /// Content directory of a static site to publish roundups into.
+    /// If unspecified, publishing is disabled.
+    #[arg(long)]
+    publish_dir: Option<PathBuf>,
+
+    /// Publish each roundup as a page bundle ({date}/index.md) rather than {date}.md.
+    #[arg(long, overrides_with = "no_publish_bundle")]
+    publish_bundle: bool,
+
+    /// Publish each roundup as {date}.md, even if the config file sets `bundle`.
+    #[arg(long, overrides_with = "publish_bundle")]
+    no_publish_bundle: bool,
+
+    /// Path of each roundup within the publish directory, with {user} and {date} replaced
+    /// (e.g. {user}/{date}.md). If unspecified, the path is chosen by --publish-bundle.
+    #[arg(long, value_name = "TEMPLATE")]
+    publish_path: Option<String>,
+
+    /// Commit each published roundup to the git repository containing the publish directory.
+    #[arg(long, overrides_with = "no_publish_git_commit")]
+    publish_git_commit: bool,
+
+    /// Don't commit published roundups, even if the config file sets `git_commit`.
+    #[arg(long, overrides_with = "publish_git_commit")]
+    no_publish_git_commit: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct BackupArgs {
+    /// Directory to back up the database into while serving, e.g. daily.
+    /// If unspecified, there are no scheduled backups.
+    #[arg(long)]
+    backup_dir: Option<PathBuf>,
+
+    /// Hours between backups. [default: 24]
+    #[arg(long, value_name = "HOURS", value_parser = clap::value_parser!(u64).range(1..))]
+    backup_every: Option<u64>,
+
+    /// How many backups to keep in the directory. [default: 7]
+    #[arg(long)]
+    backup_keep: Option<usize>,
+}
+
+/// When and where to back up the database while serving.
+struct BackupSchedule {
+    dir: PathBuf,
+    every: Duration,
+    keep: usize,
+}
+
+impl ServeArgs {
+    /// Fill in settings not given on the command line from the config file.
+    /// Listeners from the command line replace those in the file.
+    fn merge(self, config: Config) -> Option<Settings> {
+        let (bind_pattern, unix_socket) =
+            if self.bind_pattern.is_empty() && self.unix_socket.unix_socket.is_empty() {
+                (config.listen.tcp, config.listen.unix)
+            } else {
+                (self.bind_pattern, self.unix_socket.unix_socket)
+            };
+        let publish_dir = self.publish.publish_dir.or(config.publish.dir);
+        let base_url = self.base_url.or(config.base_url);
+        let backup = self
+            .backup
+            .backup_dir
+            .or(config.backup.dir)
+            .map(|dir| BackupSchedule {
+                dir,
+                every: Duration::from_secs(
+                    3600 * self
+                        .backup
+                        .backup_every
+                        .or(config.backup.every_hours)
+                        .unwrap_or(24)
+                        .max(1),
+                ),
+                keep: self.backup.backup_keep.or(config.backup.keep).unwrap_or(7),
+            });
+        Some(Settings {
+            db: self.db.or(config.db)?,
+            journal: self.journal.or(config.journal),
+            public: flag(self.public, self.no_public).unwrap_or(config.public),
+            bind_pattern,
+            unix_socket,
+            unix_socket_options: UnixSocketOptions {
+                mode: self
+                    .unix_socket
+                    .unix_socket_mode
+                    .or(config.listen.unix_mode),
+                owner: self
+                    .unix_socket
+                    .unix_socket_owner
+                    .or(config.listen.unix_owner),
+                group: self
+                    .unix_socket
+                    .unix_socket_group
+                    .or(config.listen.unix_group),
+            },
+            idle_timeout: self
+                .idle_timeout
+                .or(config.listen.idle_timeout)
+                .map(Duration::from_secs),
+            backup,
+            options: reading::Options {
+                policy: config.roundup.into(),
+                scan: config.scan.into(),
+                mail: config.mail.into_mailbox(),
+                export: ExportOptions {
+                    markdown_template: self
+                        .export
+                        .markdown_template
+                        .or(config.export.markdown_template),
+                    book_url: self
+                        .export
+                        .book_url
+                        .or(config.export.book_url)
+                        .unwrap_or(ExportOptions::default().book_url),
+                    base_url: base_url.clone(),
+                },
+                publish: publish_dir.map(|dir| PublishOptions {
+                    dir,
+                    bundle: flag(self.publish.publish_bundle, self.publish.no_publish_bundle)
+                        .unwrap_or(config.publish.bundle),
+                    path: self.publish.publish_path.or(config.publish.path),
+                    git_commit: flag(
+                        self.publish.publish_git_commit,
+                        self.publish.no_publish_git_commit,
+                    )
+                    .unwrap_or(config.publish.git_commit),
+                }),
+                base_url,
+            },
+        })
+    }
+}
+
+/// The setting of a flag given as `--X` or `--no-X`, if either was.
+fn flag(yes: bool, no: bool) -> Option<bool> {
+    match (yes, no) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// Settings for the server, from the command line and config file.
+struct Settings {
+    db: PathBuf,
+    journal: Option<PathBuf>,
+    public: bool,
+    bind_pattern: Vec<String>,
+    unix_socket: Vec<PathBuf>,
+    unix_socket_options: UnixSocketOptions,
+    idle_timeout: Option<Duration>,
+    backup: Option<BackupSchedule>,
+    options: reading::Options,
+}
+
+/// Load a config file, exiting if it is invalid.
+fn load_config(path: &Path) -> Config {
+    Config::load(path).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        std::process::exit(1)
+    })
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     tracing_subscriber::fmt::init();
     let args: Args = Args::parse();
 
-    let server =
-        reading::serve(&args.db, &args.journal).expect("could not instantiate reading-list server");
-
-    let mut listenfd = ListenFd::from_env();
-
-    // TCP branch:
-    let tcp_listener = {
-        if let Some(v) = args.bind_pattern {
-            tracing::info!("attempting to listen at {}", v);
-            Some(
-                tokio::net::TcpListener::bind(&v)
-                    .await
-                    .expect("could not open TCP socket"),
-            )
-        } else if let Ok(Some(v)) = listenfd.take_tcp_listener(0) {
-            Some(
-                tokio::net::TcpListener::from_std(v)
-                    .expect("could not open TCP socket from environment"),
-            )
-        } else {
-            None
+    let Some(command) = args.command else {
+        return serve(args.serve).await;
+    };
+    let result = match command {
+        Command::Serve(args) => Ok(serve(*args).await),
+        Command::Scan {
+            db,
+            journal,
+            config,
+        } => scan(&db, journal, config.as_deref()),
+        Command::ExportRoundup {
+            db,
+            date,
+            format,
+            output,
+            export,
+            base_url,
+        } => export_roundup(
+            &db,
+            date,
+            format,
+            output,
+            &ExportOptions {
+                base_url,
+                ..export.into()
+            },
+        ),
+        Command::ListArticles {
+            db,
+            state,
+            since,
+            until,
+            in_roundup,
+            not_in_roundup,
+        } => {
+            let filter = ArticleFilter {
+                states: state,
+                since,
+                until,
+                in_roundup: match (in_roundup, not_in_roundup) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                },
+            };
+            list_articles(&db, &filter)
+        }
+        Command::Add { db, markdown, date } => add(&db, markdown, date),
+        Command::Import {
+            db,
+            format,
+            file,
+            dry_run,
+            apply,
+            folder,
+            min_visits,
+            min_dwell,
+            tag,
+            state,
+        } => {
+            let mut options = ImportOptions::new(chrono::Local::now().date_naive());
+            options.tags.extend(tag);
+            options.default_state = state;
+            options.folders = folder.iter().map(|v| v.to_lowercase()).collect();
+            options.visits = VisitRules {
+                min_visits,
+                min_dwell: Duration::from_secs(min_dwell),
+            };
+            let dry_run = dry_run || (format.is_profile() && !apply);
+            import(&db, format, &file, &options, dry_run)
         }
+        Command::User {
+            command:
+                UserCommand::Add {
+                    db,
+                    name,
+                    journal,
+                    admin,
+                },
+        } => add_user(&db, &name, journal.as_deref(), admin),
+        Command::User {
+            command: UserCommand::SetJournal { db, name, journal },
+        } => reading::open(&db)
+            .and_then(|conn| reading::auth::set_journal(&conn, &name, journal.as_deref()))
+            .map(|()| ExitCode::SUCCESS),
+        Command::User {
+            command:
+                UserCommand::Token {
+                    db,
+                    name,
+                    label,
+                    list,
+                    revoke,
+                },
+        } => match (list, revoke) {
+            (true, _) => list_api_tokens(&db, &name),
+            (false, Some(id)) => revoke_api_token(&db, &name, id),
+            (false, None) => create_api_token(&db, &name, &label),
+        },
+        Command::Backup { db, dest, keep } => backup(&db, &dest, keep),
+        Command::Restore { db, backup } => reading::backup::restore(&backup, &db).map(|()| {
+            println!("restored {} from {}", db.display(), backup.display());
+            ExitCode::SUCCESS
+        }),
+        Command::Config {
+            command: ConfigCommand::Check { config },
+        } => Ok(check_config(&config)),
     };
-    if let Some(tcp_listener) = tcp_listener {
-        tracing::info!("starting server via TCP");
-        axum::serve(tcp_listener, server).await.unwrap();
-        return;
-    }
-    tracing::info!("no TCP server available, attempting to listen on Unix domain socket");
-    let uds_listener = match listenfd.take_unix_listener(0) {
-        Ok(Some(v)) => v,
-        _ => {
-            tracing::error!("no Unix domain socket available");
-            std::process::exit(1);
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
         }
+    }
+}
+
+fn scan(
+    db: &DbArgs,
+    journal: Option<PathBuf>,
+    config: Option<&Path>,
+) -> Result<ExitCode, reading::Error> {
+    let config = config.map(load_config).unwrap_or_default();
+    let (mut conn, user) = db.open()?;
+    // Mail is for users without their own journal, as when serving.
+    let (journal, mail) = match user.as_ref().and_then(|u| u.journal.clone()) {
+        Some(own) => (journal.or(Some(own)), None),
+        None => (journal.or(config.journal), config.mail.into_mailbox()),
     };
-    let uds_listener = tokio::net::UnixListener::from_std(uds_listener).unwrap();
-    tracing::info!("listening on Unix domain socket");
-
-    // Complex example: https://github.com/tokio-rs/axum/blob/main/examples/unix-domain-socket/src/main.rs
-    use axum::http::Request;
-    use hyper::body::Incoming;
-    use hyper_util::{
-        rt::{TokioExecutor, TokioIo},
-        server,
+    if journal.is_none() && mail.is_none() {
+        eprintln!("no journal given, and none is configured");
+        return Ok(ExitCode::FAILURE);
+    }
+    let mut report = journal
+        .map(|journal| roundup::scan_journal(&journal, &config.scan.into()))
+        .unwrap_or_default();
+    if let Some(mail) = mail {
+        report.append(mail.scan());
+    }
+    let roundup::ScanReport {
+        entries, errors, ..
+    } = report;
+    println!(
+        "{} links found, with {} errors",
+        entries.len(),
+        errors.len()
+    );
+    for error in &errors {
+        println!("{error}");
+    }
+    let counts = reading::insert_entries(&mut conn, user.map(|u| u.id), &entries)?;
+    println!(
+        "{} articles before, new total {} ({} added)",
+        counts.before,
+        counts.after,
+        counts.after - counts.before
+    );
+    Ok(if errors.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn import(
+    db: &DbArgs,
+    format: roundup::import::Format,
+    file: &Path,
+    options: &ImportOptions,
+    dry_run: bool,
+) -> Result<ExitCode, reading::Error> {
+    let report = match format.import(file, options) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("could not import {}: {e}", file.display());
+            return Ok(ExitCode::FAILURE);
+        }
     };
-    use tower::Service;
-    let mut make_service = server.into_make_service();
-    // Accepting loop:
-    while let Ok((socket, remote_addr)) = uds_listener.accept().await {
-        tracing::debug!("got a connection from {:?}", remote_addr);
-        // Task for the socket
-        let service = make_service.call(&socket).await.unwrap();
-        tokio::spawn(async move {
-            let socket = TokioIo::new(socket);
-            let hyper_service = hyper::service::service_fn(move |request: Request<Incoming>| {
-                service.clone().call(request)
-            });
+    for skipped in &report.skipped {
+        eprintln!("skipped: {skipped}");
+    }
+    println!(
+        "{} articles found, {} skipped",
+        report.entries.len(),
+        report.skipped.len()
+    );
+    if dry_run {
+        for entry in &report.entries {
+            println!(
+                "{}\t{}\t{}\t{}",
+                entry.source_date, entry.state, entry.url, entry.body_text
+            );
+        }
+        if format.is_profile() && !report.entries.is_empty() {
+            eprintln!("run again with --apply to add them");
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    let (mut conn, user) = db.open()?;
+    let counts = reading::insert_entries(&mut conn, user.map(|u| u.id), &report.entries)?;
+    println!(
+        "{} articles before, new total {} ({} added)",
+        counts.before,
+        counts.after,
+        counts.after - counts.before
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+fn export_roundup(
+    db: &DbArgs,
+    date: NaiveDate,
+    format: Format,
+    output: Option<PathBuf>,
+    options: &ExportOptions,
+) -> Result<ExitCode, reading::Error> {
+    let (conn, user) = db.open()?;
+    let s = Roundup::load(&conn, user.map(|u| u.id), date)?.render(format, options)?;
+    match output {
+        Some(path) => std::fs::write(path, s)?,
+        None => print!("{s}"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn list_articles(db: &DbArgs, filter: &ArticleFilter) -> Result<ExitCode, reading::Error> {
+    let (conn, user) = db.open()?;
+    for article in reading::find_articles(&conn, user.map(|u| u.id), filter)? {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            article.id,
+            article.entry.source_date,
+            article.entry.state,
+            article.roundups,
+            article.entry.url
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn add(db: &DbArgs, markdown: String, date: Option<NaiveDate>) -> Result<ExitCode, reading::Error> {
+    let markdown = if markdown == "-" {
+        let mut s = String::new();
+        std::io::stdin().read_to_string(&mut s)?;
+        s.trim().to_owned()
+    } else {
+        markdown
+    };
+    let (mut conn, user) = db.open()?;
+    let date = date.unwrap_or_else(|| chrono::Local::now().date_naive());
+    let id = reading::add_article(&mut conn, user.map(|u| u.id), &markdown, date)?;
+    println!("{id}");
+    Ok(ExitCode::SUCCESS)
+}
+
+fn add_user(
+    db: &Path,
+    name: &str,
+    journal: Option<&Path>,
+    admin: bool,
+) -> Result<ExitCode, reading::Error> {
+    let password = if std::io::stdin().is_terminal() {
+        let password = rpassword::prompt_password("Password: ")?;
+        if rpassword::prompt_password("Repeat password: ")? != password {
+            eprintln!("passwords do not match");
+            return Ok(ExitCode::FAILURE);
+        }
+        password
+    } else {
+        let mut s = String::new();
+        std::io::stdin().read_line(&mut s)?;
+        s.trim_end_matches(['\r', '\n']).to_owned()
+    };
+    if password.is_empty() {
+        eprintln!("password must not be empty");
+        return Ok(ExitCode::FAILURE);
+    }
+    let conn = reading::open(db)?;
+    let user = reading::auth::add_user(&conn, name, &password, journal, admin)?;
+    println!("{}", user.id);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn backup(db: &Path, dest: &Path, keep: usize) -> Result<ExitCode, reading::Error> {
+    let conn = reading::backup::open_source(db)?;
+    let path = if dest.is_dir() {
+        reading::backup::backup_to_dir(&conn, dest, keep)?
+    } else {
+        reading::backup::backup(&conn, dest)?;
+        dest.to_owned()
+    };
+    println!("{}", path.display());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn create_api_token(db: &Path, name: &str, label: &str) -> Result<ExitCode, reading::Error> {
+    let conn = reading::open(db)?;
+    println!("{}", reading::auth::create_api_token(&conn, name, label)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn list_api_tokens(db: &Path, name: &str) -> Result<ExitCode, reading::Error> {
+    let conn = reading::open(db)?;
+    for token in reading::auth::api_tokens(&conn, name)? {
+        println!("{}\t{}\t{}", token.id, token.created_at, token.label);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn revoke_api_token(db: &Path, name: &str, id: isize) -> Result<ExitCode, reading::Error> {
+    let conn = reading::open(db)?;
+    reading::auth::revoke_api_token(&conn, name, id)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn check_config(path: &Path) -> ExitCode {
+    let problems = load_config(path).check();
+    for problem in &problems {
+        eprintln!("{}: {problem}", path.display());
+    }
+    if problems.is_empty() {
+        println!("{}: ok", path.display());
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
 
-            if let Err(err) = server::conn::auto::Builder::new(TokioExecutor::new())
-                .serve_connection_with_upgrades(socket, hyper_service)
-                .await
-            {
-                tracing::error!("failed to serve connection: {err:#}");
+async fn serve(args: ServeArgs) -> ExitCode {
+    let config = args.config.as_deref().map(load_config).unwrap_or_default();
+    let Some(settings) = args.merge(config) else {
+        eprintln!("error: no database: give --db, or db in the config file");
+        return ExitCode::FAILURE;
+    };
+    let server = if settings.public {
+        reading::public::public(&settings.db, settings.options)
+    } else {
+        reading::serve(&settings.db, settings.journal.as_ref(), settings.options)
+    }
+    .expect("could not instantiate reading-list server");
+
+    let listeners = match listeners(
+        &settings.bind_pattern,
+        &settings.unix_socket,
+        &settings.unix_socket_options,
+    )
+    .await
+    {
+        Ok(v) if v.is_empty() => {
+            tracing::error!("nothing to listen on: give --bind-pattern or --unix-socket, or use socket activation");
+            return ExitCode::FAILURE;
+        }
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("could not open sockets: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let (shutdown, shutdown_rx) = tokio::sync::watch::channel(false);
+    let activity = Arc::new(lifecycle::Activity::new());
+    let mut tasks = tokio::task::JoinSet::new();
+    for listener in listeners {
+        tasks.spawn(listen::serve(
+            listener,
+            server.clone(),
+            shutdown_rx.clone(),
+            activity.clone(),
+        ));
+    }
+    lifecycle::notify_ready();
+    if let Some(schedule) = settings.backup {
+        tokio::spawn(scheduled_backups(settings.db.clone(), schedule));
+    }
+
+    let idle_timeout = settings.idle_timeout;
+    let mut draining = Vec::new();
+    tokio::select! {
+        signal = lifecycle::stop_signal() => tracing::info!("received {signal}, shutting down"),
+        () = lifecycle::idle(&activity, idle_timeout) => tracing::info!("idle, shutting down"),
+        Some(result) = tasks.join_next() => {
+            tracing::error!("a listener stopped, shutting down");
+            draining.extend(result.ok());
+        }
+    }
+    lifecycle::notify_stopping();
+    let _ = shutdown.send(true);
+    while let Some(result) = tasks.join_next().await {
+        draining.extend(result.ok());
+    }
+
+    // Let requests in progress finish, so their transactions aren't cut off.
+    let mut drains = tokio::task::JoinSet::new();
+    for graceful in draining {
+        drains.spawn(graceful.shutdown());
+    }
+    let drained = async { while drains.join_next().await.is_some() {} };
+    if tokio::time::timeout(DRAIN_TIMEOUT, drained).await.is_err() {
+        tracing::warn!("connections still open after {DRAIN_TIMEOUT:?}; exiting anyway");
+    }
+    ExitCode::SUCCESS
+}
+
+/// Back up the database into the schedule's directory, as often as it says.
+/// Counts from the last backup there, so restarts don't put backups off.
+async fn scheduled_backups(db: PathBuf, schedule: BackupSchedule) {
+    let schedule = Arc::new(schedule);
+    loop {
+        let since_last = reading::backup::last_backup(&schedule.dir)
+            .ok()
+            .flatten()
+            .and_then(|t| t.elapsed().ok());
+        let wait = since_last.map_or(Duration::ZERO, |v| schedule.every.saturating_sub(v));
+        tokio::time::sleep(wait).await;
+        let (db, s) = (db.clone(), schedule.clone());
+        let result = tokio::task::spawn_blocking(move || {
+            let conn = reading::backup::open_source(&db)?;
+            reading::backup::backup_to_dir(&conn, &s.dir, s.keep)
+        })
+        .await;
+        let error = match result {
+            Ok(Ok(path)) => {
+                tracing::info!("backed up the database to {}", path.display());
+                continue;
             }
-        });
+            Ok(Err(e)) => e.to_string(),
+            Err(e) => e.to_string(),
+        };
+        tracing::error!("could not back up the database: {error}");
+        // Don't retry right away.
+        tokio::time::sleep(schedule.every.min(Duration::from_secs(3600))).await;
+    }
+}
+
+/// How long to wait for open connections when shutting down.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Open the sockets given on the command line, or else those passed in by systemd.
+async fn listeners(
+    bind_pattern: &[String],
+    unix_socket: &[PathBuf],
+    options: &UnixSocketOptions,
+) -> std::io::Result<Vec<Listener>> {
+    let mut listeners = Vec::new();
+    for addr in bind_pattern {
+        listeners.push(listen::bind_tcp(addr).await?);
+    }
+    for path in unix_socket {
+        listeners.push(listen::bind_unix(path, options)?);
+    }
+    if listeners.is_empty() {
+        listeners = listen::from_env()?;
+    }
+    Ok(listeners)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The settings from `serve` with the arguments, over the config file's.
+    fn merge(args: &[&str], config: Config) -> Settings {
+        let args =
+            Args::try_parse_from(["server", "serve", "--db", "reading.db"].iter().chain(args))
+                .unwrap();
+        let Some(Command::Serve(serve)) = args.command else {
+            panic!("not serve: {:?}", args.command);
+        };
+        serve.merge(config).unwrap()
+    }
+
+    fn config(bundle: bool, git_commit: bool) -> Config {
+        Config {
+            publish: config::Publish {
+                dir: Some(PathBuf::from("site/content")),
+                bundle,
+                git_commit,
+                ..config::Publish::default()
+            },
+            ..Config::default()
+        }
+    }
+
+    fn publish(settings: &Settings) -> (bool, bool) {
+        let publish = settings.options.publish.as_ref().unwrap();
+        (publish.bundle, publish.git_commit)
+    }
+
+    #[test]
+    fn flags_override_the_config_file() {
+        // Neither flag: the file decides.
+        assert_eq!(publish(&merge(&[], config(true, true))), (true, true));
+        assert_eq!(publish(&merge(&[], config(false, false))), (false, false));
+        // --X turns on what the file leaves off, and --no-X turns off what it turns on.
+        assert_eq!(
+            publish(&merge(
+                &["--publish-bundle", "--publish-git-commit"],
+                config(false, false)
+            )),
+            (true, true)
+        );
+        assert_eq!(
+            publish(&merge(
+                &["--no-publish-bundle", "--no-publish-git-commit"],
+                config(true, true)
+            )),
+            (false, false)
+        );
+        // The last of --X and --no-X wins.
+        assert_eq!(
+            publish(&merge(
+                &["--publish-bundle", "--no-publish-bundle"],
+                config(false, false)
+            )),
+            (false, false)
+        );
+        assert_eq!(
+            publish(&merge(
+                &["--no-publish-bundle", "--publish-bundle"],
+                config(false, false)
+            )),
+            (true, false)
+        );
+    }
+
+    #[test]
+    fn values_override_the_config_file() {
+        let config = Config {
+            db: Some(PathBuf::from("other.db")),
+            base_url: Some("https://example.com/".to_owned()),
+            publish: config::Publish {
+                dir: Some(PathBuf::from("site/content")),
+                path: Some("{user}/{date}.md".to_owned()),
+                ..config::Publish::default()
+            },
+            ..Config::default()
+        };
+        let settings = merge(&["--base-url", "https://example.org/"], config);
+        assert_eq!(settings.db, PathBuf::from("reading.db"));
+        assert_eq!(
+            settings.options.base_url.as_deref(),
+            Some("https://example.org/")
+        );
+        let publish = settings.options.publish.unwrap();
+        assert_eq!(publish.dir, PathBuf::from("site/content"));
+        assert_eq!(publish.path.as_deref(), Some("{user}/{date}.md"));
     }
 }